@@ -1,4 +1,4 @@
-use chaoschain_core::{Block, Error as CoreError};
+use chaoschain_core::{Block, BlockHeader, Error as CoreError};
 use ethers::{
     types::{Address, H256},
 };
@@ -21,17 +21,20 @@ pub struct Config {
 #[serde_as]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FinalizedBlock {
-    /// Block hash
-    #[serde_as(as = "Hex")]
-    pub block_hash: [u8; 32],
-    /// New state root
-    #[serde_as(as = "Hex")]
-    pub state_root: [u8; 32],
+    /// Header of the finalized block, carrying the state and transaction roots
+    pub header: BlockHeader,
     /// Aggregated signatures from agents
     #[serde_as(as = "Vec<Hex>")]
     pub signatures: Vec<[u8; 64]>,
 }
 
+impl FinalizedBlock {
+    /// Hash of the finalized block
+    pub fn block_hash(&self) -> [u8; 32] {
+        self.header.hash()
+    }
+}
+
 /// Bridge errors
 #[derive(Debug, Error)]
 pub enum Error {
@@ -29,10 +29,46 @@ pub struct Cli {
     #[arg(short, long, value_name = "FILE")]
     config: Option<String>,
 
+    /// Chain spec file (JSON or TOML) to boot the network from
+    #[arg(long, value_name = "FILE", global = true)]
+    pub chain_spec: Option<String>,
+
+    /// Directory to persist chain state in; state is kept in memory if unset
+    #[arg(long, value_name = "DIR", global = true)]
+    pub data_dir: Option<String>,
+
+    /// State snapshot directory to start from instead of genesis, unless the data dir already holds a chain
+    #[arg(long, value_name = "DIR", global = true, requires = "snapshot_root")]
+    pub snapshot: Option<String>,
+
+    /// State root the snapshot must rebuild to, from a source you trust rather than the snapshot itself
+    #[arg(long, value_name = "HEX", global = true, requires = "snapshot", value_parser = parse_state_root)]
+    pub snapshot_root: Option<[u8; 32]>,
+
+    /// Block history to keep: `archive` for all of it, or `pruned[:blocks]` for only the newest blocks
+    #[arg(long, value_name = "MODE", global = true, default_value = "archive")]
+    pub pruning: chaoschain_state::PruningMode,
+
+    /// Check token supply invariants after every block
+    #[arg(long, global = true)]
+    pub check_invariants: bool,
+
+    /// Directory of encrypted agent keys, sealed with the passphrase in CHAOSCHAIN_KEYSTORE_PASSPHRASE
+    #[arg(long, value_name = "DIR", global = true)]
+    pub keystore: Option<String>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
 
+/// Parse a state root given as 64 hex characters
+fn parse_state_root(root: &str) -> Result<[u8; 32], String> {
+    hex::decode(root)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or_else(|| format!("Invalid state root: {}", root))
+}
+
 #[derive(Subcommand, Clone)]
 pub enum Commands {
     /// Run a demo with the specified number of validators and producers
@@ -60,4 +96,33 @@ pub enum Commands {
         #[arg(long)]
         web: bool,
     },
+
+    /// Write the chain state in the data dir to a chunked snapshot
+    ExportSnapshot {
+        /// Directory to write the manifest and chunks to
+        #[arg(long, value_name = "DIR")]
+        out: String,
+
+        /// State entries per chunk file
+        #[arg(long, default_value_t = chaoschain_state::DEFAULT_CHUNK_ENTRIES)]
+        chunk_entries: usize,
+    },
+
+    /// Write one agent from the keystore to a backup file, sealed with the keystore passphrase
+    ExportKey {
+        /// Name or ID of the agent
+        #[arg(long)]
+        agent: String,
+
+        /// File to write the backup to
+        #[arg(long, value_name = "FILE")]
+        out: String,
+    },
+
+    /// Add an agent from a backup file to the keystore
+    ImportKey {
+        /// Backup file written by export-key
+        #[arg(long, value_name = "FILE")]
+        file: String,
+    },
 } 
\ No newline at end of file
@@ -0,0 +1,193 @@
+//! Signing and verification of events on the agent broadcast bus.
+
+use crate::{ConsensusError, Result};
+use chaoschain_core::{Block, NetworkEvent, SignedEvent, Transaction};
+use chaoschain_crypto::{check_chain_id, CryptoError, KeyManagerHandle};
+use tokio::sync::broadcast;
+
+/// How many seconds after signing an event is still accepted
+pub const MAX_EVENT_AGE_SECS: u64 = 60;
+/// How many seconds ahead of our clock an event may be signed
+pub const MAX_CLOCK_SKEW_SECS: u64 = 5;
+
+/// Signs outgoing network events as a single agent
+#[derive(Debug, Clone)]
+pub struct EventSigner {
+    key_manager: KeyManagerHandle,
+    agent_id: String,
+    chain_id: String,
+}
+
+impl EventSigner {
+    /// Create a signer for an agent whose keys live in `key_manager`
+    pub fn new(key_manager: KeyManagerHandle, agent_id: String, chain_id: &str) -> Self {
+        Self {
+            key_manager,
+            agent_id,
+            chain_id: chain_id.to_string(),
+        }
+    }
+
+    /// ID of the agent this signer speaks for
+    pub fn agent_id(&self) -> &str {
+        &self.agent_id
+    }
+
+    /// Chain the signed events are bound to
+    pub fn chain_id(&self) -> &str {
+        &self.chain_id
+    }
+
+    /// Wrap an event in an envelope signed by this agent
+    pub fn sign(&self, event: NetworkEvent) -> Result<SignedEvent> {
+        let sender: [u8; 32] = hex::decode(&self.agent_id)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(CryptoError::InvalidKey)?;
+        let timestamp = chrono::Utc::now().timestamp() as u64;
+
+        let mut signed = SignedEvent::new(&self.chain_id, sender, timestamp, event);
+        signed.signature = self
+            .key_manager
+            .inner()
+            .sign(&self.agent_id, &signed.signing_bytes())?;
+        Ok(signed)
+    }
+
+    /// Sign a sealed block this agent produced
+    pub fn sign_block(&self, block: &mut Block) -> Result<()> {
+        if block.header.producer_id != self.agent_id {
+            return Err(ConsensusError::Internal(format!(
+                "{} cannot sign a block produced by {}",
+                self.agent_id, block.header.producer_id
+            )));
+        }
+        block.proposer_sig = self.key_manager.inner().sign(&self.agent_id, &block.signing_bytes())?;
+        Ok(())
+    }
+
+    /// Sign a transaction this agent sends
+    pub fn sign_transaction(&self, tx: &mut Transaction) -> Result<()> {
+        if hex::encode(tx.sender) != self.agent_id {
+            return Err(ConsensusError::Internal(format!(
+                "{} cannot sign a transaction sent by {}",
+                self.agent_id,
+                hex::encode(tx.sender)
+            )));
+        }
+        tx.signature = self.key_manager.inner().sign(&self.agent_id, &tx.signing_bytes())?;
+        Ok(())
+    }
+
+    /// Sign an event and send it on the bus
+    pub fn broadcast(
+        &self,
+        tx: &broadcast::Sender<SignedEvent>,
+        event: NetworkEvent,
+    ) -> Result<()> {
+        tx.send(self.sign(event)?)
+            .map_err(|e| ConsensusError::Network(e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Check that an event was signed by its sender for this chain, and recently
+pub fn verify_event(signed: &SignedEvent, chain_id: &str) -> Result<()> {
+    verify_event_at(signed, chain_id, chrono::Utc::now().timestamp() as u64)
+}
+
+/// `verify_event` as of `now`, so replays of old envelopes are refused
+fn verify_event_at(signed: &SignedEvent, chain_id: &str, now: u64) -> Result<()> {
+    check_chain_id(chain_id, &signed.chain_id)?;
+    let fresh = signed.timestamp <= now.saturating_add(MAX_CLOCK_SKEW_SECS)
+        && now.saturating_sub(MAX_EVENT_AGE_SECS) <= signed.timestamp;
+    if !fresh {
+        return Err(ConsensusError::StaleEvent { signed_at: signed.timestamp, now });
+    }
+    signed.verify_signature()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer(chain_id: &str) -> EventSigner {
+        let key_manager = KeyManagerHandle::new();
+        let keys = key_manager
+            .inner()
+            .generate_agent_keys("DramaQueen".to_string(), "validator".to_string())
+            .unwrap();
+        EventSigner::new(key_manager, keys.id, chain_id)
+    }
+
+    fn chat(sender: &str) -> NetworkEvent {
+        NetworkEvent::AgentChat {
+            message: "Nobody expects the drama!".to_string(),
+            sender: sender.to_string(),
+            meme_url: None,
+        }
+    }
+
+    #[test]
+    fn test_signed_event_roundtrip() {
+        let signer = signer("chaoschain-test");
+        let signed = signer.sign(chat(signer.agent_id())).unwrap();
+        assert!(verify_event(&signed, "chaoschain-test").is_ok());
+        assert!(matches!(
+            verify_event(&signed, "other-chain"),
+            Err(ConsensusError::Crypto(CryptoError::WrongChain { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_stale_and_future_events_rejected() {
+        let signer = signer("chaoschain-test");
+        let signed = signer.sign(chat(signer.agent_id())).unwrap();
+        let signed_at = signed.timestamp;
+        assert!(verify_event_at(&signed, "chaoschain-test", signed_at + MAX_EVENT_AGE_SECS).is_ok());
+        assert!(matches!(
+            verify_event_at(&signed, "chaoschain-test", signed_at + MAX_EVENT_AGE_SECS + 1),
+            Err(ConsensusError::StaleEvent { .. })
+        ));
+        assert!(verify_event_at(&signed, "chaoschain-test", signed_at - MAX_CLOCK_SKEW_SECS).is_ok());
+        assert!(matches!(
+            verify_event_at(&signed, "chaoschain-test", signed_at - MAX_CLOCK_SKEW_SECS - 1),
+            Err(ConsensusError::StaleEvent { .. })
+        ));
+    }
+
+    #[test]
+    fn test_forged_sender_rejected() {
+        let signer = signer("chaoschain-test");
+        let signed = signer.sign(chat("validator-0")).unwrap();
+        assert!(matches!(
+            verify_event(&signed, "chaoschain-test"),
+            Err(ConsensusError::Core(_))
+        ));
+    }
+
+    #[test]
+    fn test_sign_transaction() {
+        let signer = signer("chaoschain-test");
+        let sender: [u8; 32] = hex::decode(signer.agent_id()).unwrap().try_into().unwrap();
+        let mut tx = Transaction {
+            chain_id: "chaoschain-test".to_string(),
+            sender,
+            nonce: 0,
+            payload: Vec::new(),
+            signature: [0u8; 64],
+        };
+        signer.sign_transaction(&mut tx).unwrap();
+        assert!(chaoschain_crypto::verify_with_pubkey(&sender, &tx.signing_bytes(), &tx.signature).unwrap());
+
+        tx.sender = [7u8; 32];
+        assert!(signer.sign_transaction(&mut tx).is_err());
+    }
+
+    #[test]
+    fn test_unknown_agent_cannot_sign() {
+        let signer = EventSigner::new(KeyManagerHandle::new(), "nobody".to_string(), "chaoschain-test");
+        assert!(signer.sign(chat("nobody")).is_err());
+    }
+}
@@ -0,0 +1,63 @@
+//! Fork choice between competing chain heads.
+//!
+//! Heads are ranked by the highest finalized block on their branch, then by
+//! the stake that approved them, and finally by the lowest block hash, so
+//! every node breaks ties the same way.
+
+use std::cmp::Reverse;
+
+/// A head the canonical chain could end in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeadCandidate {
+    /// Hash of the head block
+    pub hash: [u8; 32],
+    /// Height of the highest finalized block on the head's branch
+    pub finalized_height: Option<u64>,
+    /// Stake that voted to approve the head
+    pub approval_stake: u64,
+}
+
+impl HeadCandidate {
+    fn rank(&self) -> (Option<u64>, u64, Reverse<[u8; 32]>) {
+        (self.finalized_height, self.approval_stake, Reverse(self.hash))
+    }
+}
+
+/// The candidate the canonical chain should end in, if there are any
+pub fn choose_head<'a>(
+    candidates: impl IntoIterator<Item = &'a HeadCandidate>,
+) -> Option<&'a HeadCandidate> {
+    candidates.into_iter().max_by_key(|candidate| candidate.rank())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(hash: u8, finalized_height: Option<u64>, approval_stake: u64) -> HeadCandidate {
+        HeadCandidate {
+            hash: [hash; 32],
+            finalized_height,
+            approval_stake,
+        }
+    }
+
+    #[test]
+    fn test_choose_head_ordering() {
+        // Finality beats stake
+        let finalized = candidate(9, Some(4), 10);
+        let popular = candidate(1, Some(3), 1000);
+        let unfinalized = candidate(0, None, 5000);
+        assert_eq!(choose_head([&popular, &unfinalized, &finalized]), Some(&finalized));
+
+        // Stake breaks ties in finality
+        let backed = candidate(9, Some(4), 11);
+        assert_eq!(choose_head([&finalized, &backed]), Some(&backed));
+
+        // The lowest hash breaks the rest, whatever order candidates arrive in
+        let low = candidate(2, Some(4), 10);
+        assert_eq!(choose_head([&finalized, &low]), Some(&low));
+        assert_eq!(choose_head([&low, &finalized]), Some(&low));
+        assert_eq!(choose_head([]), None);
+    }
+}
@@ -1,18 +1,24 @@
-use chaoschain_core::{Block, NetworkEvent, Error as CoreError, ValidationDecision};
+use chaoschain_core::{Error as CoreError, SignedEvent};
+use chaoschain_core::encoding::{Encoder, SigningDomain};
+use chaoschain_crypto::CryptoError;
 use chaoschain_state::StateStore;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use thiserror::Error;
 use rand::Rng;
 use std::sync::Arc;
-use async_trait::async_trait;
 use std::fmt;
 use tokio::sync::broadcast;
 
+pub mod events;
+pub mod fork_choice;
 pub mod types;
 pub mod manager;
 pub mod validator;
 
+pub use chaoschain_core::ExternalAgent;
+pub use events::{verify_event, EventSigner, MAX_CLOCK_SKEW_SECS, MAX_EVENT_AGE_SECS};
+pub use fork_choice::{choose_head, HeadCandidate};
 pub use manager::ConsensusManager;
 pub use types::*;
 pub use validator::Validator;
@@ -78,8 +84,6 @@ pub struct Agent {
     pub personality: AgentPersonality,
     /// Agent's current mood (affects decision making)
     pub mood: String,
-    /// Agent's stake in the system
-    pub stake: u64,
     /// History of decisions
     pub decision_history: Vec<String>,
 }
@@ -90,7 +94,6 @@ impl Agent {
             public_key,
             personality,
             mood: String::new(),
-            stake: 100, // Default stake value
             decision_history: Vec::new(),
         }
     }
@@ -99,8 +102,6 @@ impl Agent {
 /// Consensus configuration
 #[derive(Debug, Clone)]
 pub struct Config {
-    /// Total stake in the system
-    pub total_stake: u64,
     /// Required stake percentage for finality (e.g. 0.67 for 2/3)
     pub finality_threshold: f64,
     /// OpenAI API key for agent personalities
@@ -112,7 +113,6 @@ pub struct Config {
 impl Default for Config {
     fn default() -> Self {
         Self {
-            total_stake: 3000, // Default total stake
             finality_threshold: 0.67, // 2/3 majority
             openai_api_key: String::new(),
             consensus_timeout: std::time::Duration::from_secs(30),
@@ -125,10 +125,14 @@ impl Default for Config {
 pub enum ConsensusError {
     #[error("Core error: {0}")]
     Core(#[from] CoreError),
+    #[error("Crypto error: {0}")]
+    Crypto(#[from] CryptoError),
     #[error("State error: {0}")]
     State(String),
     #[error("Network error: {0}")]
     Network(String),
+    #[error("Event signed at {signed_at} is too far from now ({now})")]
+    StaleEvent { signed_at: u64, now: u64 },
     #[error("Internal error: {0}")]
     Internal(String),
 }
@@ -139,6 +143,8 @@ pub type Result<T> = std::result::Result<T, ConsensusError>;
 #[serde_as]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Vote {
+    /// Chain this vote is meant for
+    pub chain_id: String,
     /// Agent's public key
     pub agent_id: String,
     /// Block hash being voted on
@@ -155,30 +161,33 @@ pub struct Vote {
     pub signature: [u8; 64],
 }
 
-/// External AI agent interface
-#[async_trait]
-pub trait ExternalAgent: Send + Sync {
-    /// Validate a block
-    async fn validate_block(&self, block: &Block) -> Result<ValidationDecision>;
-    
-    /// Get agent's current personality
-    fn get_personality(&self) -> AgentPersonality;
-    
-    /// Update agent's state based on network events
-    async fn handle_event(&self, event: NetworkEvent) -> Result<()>;
-
-    /// Generate drama event
-    async fn generate_drama(&self) -> Result<Option<DramaEvent>>;
+impl Vote {
+    /// Canonical bytes covered by the vote signature
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut encoder = Encoder::for_signing(SigningDomain::Vote, &self.chain_id);
+        encoder
+            .put_str(&self.agent_id)
+            .put_fixed(&self.block_hash)
+            .put_u8(self.approve as u8)
+            .put_str(&self.reason);
+        match &self.meme_url {
+            Some(url) => encoder.put_u8(1).put_str(url),
+            None => encoder.put_u8(0),
+        };
+        encoder.finish()
+    }
 }
 
 /// Create a new consensus manager with the given configuration
 pub fn create_consensus(
     _config: Config,
     state_store: Arc<dyn StateStore>,
-    network_tx: broadcast::Sender<NetworkEvent>,
+    network_tx: broadcast::Sender<SignedEvent>,
+    signer: EventSigner,
 ) -> ConsensusManager {
     ConsensusManager::new(
         state_store,
         network_tx,
+        signer,
     )
 } 
\ No newline at end of file
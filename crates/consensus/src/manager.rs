@@ -2,15 +2,17 @@ use std::collections::{HashMap, HashSet};
 use tokio::sync::RwLock as TokioRwLock;
 use std::sync::Arc;
 use anyhow::{Result, anyhow};
-use chaoschain_core::{Block, NetworkEvent, ValidationDecision};
-use chaoschain_state::StateStore;
-use tracing::info;
+use chaoschain_core::{Block, NetworkEvent, SignedEvent, ValidationDecision};
+use chaoschain_state::{StateStore, StateStoreExt};
+use tracing::{info, warn};
 use serde::{Serialize, Deserialize};
 use rand::{Rng, rngs::SmallRng, SeedableRng};
 use tokio::sync::broadcast;
 use crate::types::*;
 use crate::DramaEvent;
 use crate::ConsensusError;
+use crate::EventSigner;
+use crate::fork_choice::{choose_head, HeadCandidate};
 use crate::types::WebMessage;
 use tokio::sync::mpsc::Sender;
 
@@ -63,18 +65,24 @@ pub struct ConsensusManager {
     state: Arc<TokioRwLock<ConsensusState>>,
     votes: Arc<TokioRwLock<HashMap<[u8; 32], Vec<(ValidationDecision, u64)>>>>,
     consensus_threshold: f64,
-    network_tx: broadcast::Sender<NetworkEvent>,
+    network_tx: broadcast::Sender<SignedEvent>,
+    signer: EventSigner,
     drama_events: Arc<TokioRwLock<Vec<DramaEvent>>>,
 }
 
 impl ConsensusManager {
-    pub fn new(state_store: Arc<dyn StateStore>, network_tx: broadcast::Sender<NetworkEvent>) -> Self {
+    pub fn new(
+        state_store: Arc<dyn StateStore>,
+        network_tx: broadcast::Sender<SignedEvent>,
+        signer: EventSigner,
+    ) -> Self {
         Self {
             state_store,
             state: Arc::new(TokioRwLock::new(ConsensusState::default())),
             votes: Arc::new(TokioRwLock::new(HashMap::new())),
             consensus_threshold: 0.66, // 2/3 majority
             network_tx,
+            signer,
             drama_events: Arc::new(TokioRwLock::new(Vec::new())),
         }
     }
@@ -87,35 +95,43 @@ impl ConsensusManager {
 
     /// Start voting round for a new block
     pub async fn start_voting_round(&self, block: Block) {
+        // Keep every proposal in the fork tree, even ones that lose out at their height
+        if let Err(e) = self.state_store.record_block(&block) {
+            warn!("Ignoring invalid block proposal {}: {}", block.header.height, e);
+            return;
+        }
+
         let mut state = self.state.write().await;
         
         if let Some(current_block) = &state.current_block {
-            if current_block.height == block.height {
+            if current_block.header.height == block.header.height {
                 return;
             }
         }
         
         state.votes.clear();
         state.current_block = Some(block.clone());
-        state.block_status.insert(block.height, BlockStatus::Pending);
+        state.block_status.insert(block.header.height, BlockStatus::Pending);
         drop(state);
 
-        // Send to network
-        let _ = self.network_tx.send(NetworkEvent::BlockProposal {
-            block: block.clone(),
-            drama_level: block.drama_level,
-            producer_mood: block.producer_mood.clone(),
-            producer_id: block.producer_id.clone(),
-        });
+        // Only a block's producer can vouch for it on the bus, so others' blocks were announced by them
+        if block.header.producer_id == self.signer.agent_id() {
+            let _ = self.signer.broadcast(&self.network_tx, NetworkEvent::BlockProposal {
+                block: Box::new(block.clone()),
+                drama_level: block.header.drama_level,
+                producer_mood: block.header.producer_mood.clone(),
+                producer_id: block.header.producer_id.clone(),
+            });
+        }
 
         // Log the event
         info!("🎭 DRAMATIC BLOCK PROPOSAL! Block {} by {}\n\nDrama Level: {} {}\nMood: {}\nTransactions: {}\nTimestamp: {}", 
-            block.height,
-            block.producer_id,
-            block.drama_level,
-            "⭐".repeat(block.drama_level as usize),
-            block.producer_mood,
-            block.transactions.len(),
+            block.header.height,
+            block.header.producer_id,
+            block.header.drama_level,
+            "⭐".repeat(block.header.drama_level as usize),
+            block.header.producer_mood,
+            block.body.transactions.len(),
             chrono::Utc::now().timestamp()
         );
 
@@ -124,38 +140,48 @@ impl ConsensusManager {
     }
 
     /// Add a vote from a validator with extra drama
-    pub async fn add_vote(&self, vote: ValidationDecision, stake: u64, block_hash: [u8; 32]) -> Result<bool> {
+    ///
+    /// The vote weighs as much as the validator's bonded stake in current state,
+    /// and consensus needs approvals from a share of all bonded stake, not just
+    /// of the stake that has voted so far. A validator voting on a block again
+    /// replaces its earlier vote, so a replayed vote cannot count twice.
+    pub async fn add_vote(&self, vote: ValidationDecision, block_hash: [u8; 32]) -> Result<bool> {
+        let stake = self.state_store.stake(&vote.validator)
+            .map_err(|e| anyhow!("State error: {}", e))?
+            .bonded;
+        if stake == 0 {
+            return Err(anyhow!("Validator {} has no bonded stake", vote.validator));
+        }
+        let bonded_stake: u64 = self.state_store.stakes()
+            .map_err(|e| anyhow!("State error: {}", e))?
+            .iter()
+            .map(|(_, stake)| stake.bonded)
+            .sum();
+
         let mut votes = self.votes.write().await;
         
         let block_votes = votes.entry(block_hash).or_default();
+        block_votes.retain(|(earlier, _)| earlier.validator != vote.validator);
         block_votes.push((vote.clone(), stake));
-        
-        let total_stake: u64 = block_votes.iter()
-            .map(|(_, s)| s)
-            .sum();
             
         let approval_stake: u64 = block_votes.iter()
             .filter(|(v, _)| v.approved)
             .map(|(_, s)| s)
             .sum();
             
-        let consensus_reached = (approval_stake as f64 / total_stake as f64) >= self.consensus_threshold;
+        let consensus_reached = (approval_stake as f64 / bonded_stake as f64) >= self.consensus_threshold;
+        drop(votes);
         
         if consensus_reached {
             self.trigger_dramatic_event(&block_hash).await?;
+            if let Err(e) = self.commit_block(block_hash).await {
+                warn!("Failed to commit block {}: {}", hex::encode(block_hash), e);
+            }
         }
         
         Ok(consensus_reached)
     }
 
-    async fn get_total_stake(&self) -> u64 {
-        let votes = self.votes.read().await;
-        votes.values()
-            .flat_map(|vec| vec.iter())
-            .map(|(_, stake)| stake)
-            .sum()
-    }
-
     async fn get_approved_stake(&self, block_hash: &[u8; 32]) -> u64 {
         let votes = self.votes.read().await;
         votes.get(block_hash)
@@ -166,12 +192,96 @@ impl ConsensusManager {
             .unwrap_or(0)
     }
 
+    /// Commit a block that reached consensus, switching branches if fork choice prefers it
+    async fn commit_block(&self, block_hash: [u8; 32]) -> Result<()> {
+        if self.is_block_finalized(block_hash).await {
+            return Ok(());
+        }
+        let block = self.state_store.get_block_by_hash(&block_hash)
+            .ok_or_else(|| anyhow!("Block {} is not in the fork tree", hex::encode(block_hash)))?;
+
+        // Blocks on or extending the canonical chain need no fork choice
+        let head = match self.state_store.head() {
+            Some(head) if head.hash() != block.header.parent_hash => head,
+            _ => return self.finalize_block_with_drama(&block, block.header.drama_level).await,
+        };
+        if self.state_store.is_canonical(&block_hash) {
+            self.mark_finalized(&block, block.header.drama_level).await;
+            return Ok(());
+        }
+
+        // Finalized blocks stay put, however much stake backs a rival branch
+        if let Some(finalized_height) = self.latest_canonical_finalized_height().await {
+            if finalized_height > self.fork_height(&block) {
+                return Err(anyhow!(
+                    "Block {} would roll back the block finalized at height {}",
+                    hex::encode(block_hash),
+                    finalized_height
+                ));
+            }
+        }
+
+        let current = HeadCandidate {
+            hash: head.hash(),
+            finalized_height: self.latest_canonical_finalized_height().await,
+            approval_stake: self.get_approved_stake(&head.hash()).await,
+        };
+        let candidate = HeadCandidate {
+            hash: block_hash,
+            finalized_height: Some(block.header.height),
+            approval_stake: self.get_approved_stake(&block_hash).await,
+        };
+        if choose_head([&current, &candidate]) != Some(&candidate) {
+            info!("Block {} reached consensus but the current head stays preferred", block.header.height);
+            return Ok(());
+        }
+
+        let reorg = self.state_store.reorg_to(&block_hash)
+            .map_err(|e| anyhow!("State error: {}", e))?;
+        info!("🔀 REORG! Block {} replaces {} blocks of the old chain", block.header.height, reorg.rolled_back.len());
+        self.mark_finalized(&block, block.header.drama_level).await;
+        self.signer.broadcast(&self.network_tx, NetworkEvent::Reorg {
+            old_head: head.hash(),
+            new_head: reorg.new_head,
+            rolled_back: reorg.rolled_back,
+            applied: reorg.applied,
+        })?;
+        Ok(())
+    }
+
+    /// Height of the canonical block the branch ending in `block` forks from, zero if it shares none
+    fn fork_height(&self, block: &Block) -> u64 {
+        let mut cursor = block.header.parent_hash;
+        while let Some(parent) = self.state_store.get_block_by_hash(&cursor) {
+            if self.state_store.is_canonical(&cursor) {
+                return parent.header.height;
+            }
+            cursor = parent.header.parent_hash;
+        }
+        0
+    }
+
+    /// Height of the latest finalized block still on the canonical chain
+    async fn latest_canonical_finalized_height(&self) -> Option<u64> {
+        let state = self.state.read().await;
+        state.finalized_blocks.iter()
+            .rev()
+            .find(|hash| self.state_store.is_canonical(hash))
+            .and_then(|hash| self.state_store.get_block_by_hash(hash))
+            .map(|block| block.header.height)
+    }
+
     /// Finalize a block with maximum drama
     async fn finalize_block_with_drama(&self, block: &Block, drama_level: u8) -> Result<()> {
         // Apply block to state with theatrical flair
         self.state_store.apply_block(block)
             .map_err(|e| anyhow!("State error: {}", e))?;
+        self.mark_finalized(block, drama_level).await;
+        Ok(())
+    }
 
+    /// Record a block that is already on the canonical chain as finalized
+    async fn mark_finalized(&self, block: &Block, drama_level: u8) {
         // Get new state root
         let state_root = self.state_store.state_root();
 
@@ -185,11 +295,11 @@ impl ConsensusManager {
         // Generate dramatic finalization message
         let drama_stars = "⭐".repeat(drama_level as usize);
         info!("🎭 BLOCK {} FINALIZED! {} 🎭\nDrama Level: {}\nState Root: {:?}\nSignatures: {}", 
-            block.height, drama_stars, drama_level, state_root, signatures.len());
+            block.header.height, drama_stars, drama_level, state_root, signatures.len());
 
         // Update block status with flair
         state.block_status.insert(
-            block.height,
+            block.header.height,
             BlockStatus::Finalized {
                 hash: block.hash(),
                 state_root,
@@ -197,8 +307,6 @@ impl ConsensusManager {
             }
         );
         state.finalized_blocks.push(block.hash());
-
-        Ok(())
     }
 
     /// Reject a block with theatrical flair
@@ -219,10 +327,10 @@ impl ConsensusManager {
             .join("\n");
 
         info!("💔 BLOCK {} REJECTED! {} 🔥\nDrama Level: {}\nReasons:\n{}", 
-            block.height, drama_flames, drama_level, formatted_reasons);
+            block.header.height, drama_flames, drama_level, formatted_reasons);
 
         state.block_status.insert(
-            block.height,
+            block.header.height,
             BlockStatus::Rejected {
                 hash: block.hash(),
                 reasons,
@@ -290,7 +398,7 @@ impl ConsensusManager {
         
         // Add current block's producer if any
         if let Some(block) = &state.current_block {
-            producers.insert(block.producer_id.clone());
+            producers.insert(block.header.producer_id.clone());
         }
         
         producers.len()
@@ -527,11 +635,11 @@ impl ConsensusManager {
         let mut rng = SmallRng::from_entropy();
         let drama_level = rng.gen_range(0..10);
         
-        self.network_tx.send(NetworkEvent::BlockProposal {
-            block: block.clone(),
+        self.signer.broadcast(&self.network_tx, NetworkEvent::BlockProposal {
+            block: Box::new(block.clone()),
             drama_level,
             producer_mood: "Chaotic".to_string(),
-            producer_id: block.producer_id.clone(),
+            producer_id: block.header.producer_id.clone(),
         })?;
 
         Ok(())
@@ -561,7 +669,7 @@ impl ConsensusManager {
         let mut drama_score = 0u8;
         
         // Base drama from block
-        drama_score = drama_score.saturating_add(block.drama_level);
+        drama_score = drama_score.saturating_add(block.header.drama_level);
         
         // Drama from validation decisions
         let total_votes = votes.len() as f32;
@@ -643,4 +751,173 @@ impl DramaticAction for ConsensusRule {
             RuleType::StrictConsensus => 3,
         }
     }
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chaoschain_core::{BlockBody, BlockHeader, ChainConfig, GenesisAccount, ValidatorInfo};
+    use chaoschain_crypto::KeyManagerHandle;
+    use chaoschain_state::StateStoreImpl;
+
+    fn agent(key_manager: &KeyManagerHandle, name: &str) -> EventSigner {
+        let keys = key_manager
+            .inner()
+            .generate_agent_keys(name.to_string(), "producer".to_string())
+            .unwrap();
+        EventSigner::new(key_manager.clone(), keys.id, chaoschain_core::DEFAULT_CHAIN_ID)
+    }
+
+    /// Sealed empty block by `producer` on the head of `node`, the store of a node following its branch
+    fn block(producer: &EventSigner, node: &StateStoreImpl) -> Block {
+        let (height, parent_hash) = node.next_block();
+        let body = BlockBody::new(vec![]);
+        let mut block = Block {
+            header: BlockHeader {
+                chain_id: producer.chain_id().to_string(),
+                height,
+                parent_hash,
+                tx_root: body.tx_root(),
+                receipts_root: [0u8; 32],
+                state_root: [0u8; 32],
+                innovation_level: 5,
+                producer_strategy: "Default".to_string(),
+                producer_id: producer.agent_id().to_string(),
+                drama_level: 5,
+                producer_mood: "dramatic".to_string(),
+                timestamp: 0,
+            },
+            body,
+            proposer_sig: [0u8; 64],
+        };
+        node.seal_block(&mut block).unwrap();
+        producer.sign_block(&mut block).unwrap();
+        block
+    }
+
+    /// Chain where each of `validators` has bonded 100 at genesis
+    fn staked(validators: &[&EventSigner]) -> ChainConfig {
+        ChainConfig {
+            validators: validators
+                .iter()
+                .map(|validator| ValidatorInfo {
+                    name: validator.agent_id().to_string(),
+                    account: validator.agent_id().to_string(),
+                    traits: Vec::new(),
+                })
+                .collect(),
+            genesis_stakes: validators
+                .iter()
+                .map(|validator| GenesisAccount { account: validator.agent_id().to_string(), balance: 100 })
+                .collect(),
+            ..ChainConfig::default()
+        }
+    }
+
+    fn approve(validator: &EventSigner) -> ValidationDecision {
+        ValidationDecision {
+            approved: true,
+            reason: "Peak drama".to_string(),
+            meme_url: None,
+            drama_level: 5,
+            innovation_score: 5,
+            evolution_proposal: None,
+            validator: validator.agent_id().to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_finalized_blocks_are_never_reorged_out() {
+        let key_manager = KeyManagerHandle::new();
+        let (ours, theirs, validator) = (
+            agent(&key_manager, "ours"),
+            agent(&key_manager, "theirs"),
+            agent(&key_manager, "validator"),
+        );
+        let config = staked(&[&validator]);
+        let state_store = Arc::new(StateStoreImpl::new(config.clone(), key_manager).unwrap());
+        let rival_node = StateStoreImpl::new(config.clone(), KeyManagerHandle::new()).unwrap();
+        let fork_node = StateStoreImpl::new(config, KeyManagerHandle::new()).unwrap();
+        let (network_tx, _network_rx) = broadcast::channel(16);
+        let manager = ConsensusManager::new(state_store.clone(), network_tx, ours.clone());
+
+        // Our first block is finalized, our second only applied
+        let first = block(&ours, &state_store);
+        manager.start_voting_round(first.clone()).await;
+        assert!(manager.add_vote(approve(&validator), first.hash()).await.unwrap());
+        assert!(manager.is_block_finalized(first.hash()).await);
+        let second = block(&ours, &state_store);
+        state_store.apply_block(&second).unwrap();
+
+        // A rival for the unfinalized block wins fork choice and takes its place
+        rival_node.apply_block(&first).unwrap();
+        let rival = block(&theirs, &rival_node);
+        state_store.record_block(&rival).unwrap();
+        manager.add_vote(approve(&validator), rival.hash()).await.unwrap();
+        assert_eq!(state_store.head().unwrap().hash(), rival.hash());
+
+        // A longer rival branch forking below the finalized block is refused
+        for _ in 1..=2 {
+            let fork = block(&theirs, &fork_node);
+            fork_node.apply_block(&fork).unwrap();
+            state_store.record_block(&fork).unwrap();
+        }
+        let tip = block(&theirs, &fork_node);
+        state_store.record_block(&tip).unwrap();
+        manager.add_vote(approve(&validator), tip.hash()).await.unwrap();
+        assert_eq!(state_store.head().unwrap().hash(), rival.hash());
+        assert!(state_store.is_canonical(&first.hash()));
+        assert!(!manager.is_block_finalized(tip.hash()).await);
+    }
+
+    #[tokio::test]
+    async fn test_one_validator_cannot_finalize_alone() {
+        let key_manager = KeyManagerHandle::new();
+        let ours = agent(&key_manager, "ours");
+        let validators: Vec<_> = ["first", "second", "third"]
+            .into_iter()
+            .map(|name| agent(&key_manager, name))
+            .collect();
+        let config = staked(&validators.iter().collect::<Vec<_>>());
+        let state_store = Arc::new(StateStoreImpl::new(config, key_manager).unwrap());
+        let (network_tx, _network_rx) = broadcast::channel(16);
+        let manager = ConsensusManager::new(state_store.clone(), network_tx, ours.clone());
+
+        let first = block(&ours, &state_store);
+        manager.start_voting_round(first.clone()).await;
+        assert!(!manager.add_vote(approve(&validators[0]), first.hash()).await.unwrap());
+        assert!(!manager.is_block_finalized(first.hash()).await);
+        assert!(state_store.head().is_none());
+
+        // Two of three make the two-thirds majority
+        assert!(manager.add_vote(approve(&validators[1]), first.hash()).await.unwrap());
+        assert!(manager.is_block_finalized(first.hash()).await);
+        assert_eq!(state_store.head().unwrap().hash(), first.hash());
+    }
+
+    #[tokio::test]
+    async fn test_repeated_votes_count_once() {
+        let key_manager = KeyManagerHandle::new();
+        let (ours, approver, rejecter) = (
+            agent(&key_manager, "ours"),
+            agent(&key_manager, "approver"),
+            agent(&key_manager, "rejecter"),
+        );
+        let config = staked(&[&approver, &rejecter]);
+        let state_store = Arc::new(StateStoreImpl::new(config, key_manager).unwrap());
+        let (network_tx, _network_rx) = broadcast::channel(16);
+        let manager = ConsensusManager::new(state_store.clone(), network_tx, ours.clone());
+
+        let first = block(&ours, &state_store);
+        manager.start_voting_round(first.clone()).await;
+        let reject = ValidationDecision { approved: false, ..approve(&rejecter) };
+        assert!(!manager.add_vote(reject, first.hash()).await.unwrap());
+
+        // Replaying the approval leaves it at half the voting stake
+        for _ in 0..3 {
+            assert!(!manager.add_vote(approve(&approver), first.hash()).await.unwrap());
+        }
+        assert_eq!(manager.get_approved_stake(&first.hash()).await, 100);
+        assert!(!manager.is_block_finalized(first.hash()).await);
+    }
+}
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
 use chaoschain_core::ValidationDecision;
+pub use chaoschain_core::DramaEvent;
 use std::fmt;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
@@ -16,20 +17,6 @@ pub enum RuleType {
     StrictConsensus,
 }
 
-#[derive(Debug, Clone)]
-pub enum DramaEvent {
-    Drama {
-        agent: String,
-        action: String,
-        drama_level: u8,
-    },
-    Chaos {
-        description: String,
-        instigator: String,
-        drama_level: u8,
-    },
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ValidationResult {
     pub validator: String,
@@ -42,22 +29,6 @@ impl fmt::Display for ValidationResult {
     }
 }
 
-impl DramaEvent {
-    pub fn get_drama_level(&self) -> u8 {
-        match self {
-            Self::Drama { drama_level, .. } => *drama_level,
-            Self::Chaos { drama_level, .. } => *drama_level,
-        }
-    }
-
-    pub fn get_description(&self) -> String {
-        match self {
-            Self::Drama { action, .. } => action.clone(),
-            Self::Chaos { description, .. } => description.clone(),
-        }
-    }
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConsensusRule {
     pub rule_type: RuleType,
@@ -5,29 +5,14 @@ use async_openai::{
     Client,
     config::OpenAIConfig,
 };
-use chaoschain_core::{Block, NetworkEvent, ValidationDecision};
+use chaoschain_core::{AgentPersonality, Block, ExternalAgent, NetworkEvent, SignedEvent, ValidationDecision};
 use chaoschain_state::StateStore;
 use chaoschain_crypto::KeyManagerHandle;
 use tracing::info;
 use serde::{Serialize, Deserialize};
 
-use crate::ExternalAgent;
 use crate::types::WebMessage;
-
-/// AI Agent personality traits and characteristics
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AgentPersonality {
-    /// Core traits that define behavior
-    pub traits: Vec<String>,
-    /// Decision-making approach
-    pub strategy: String,
-    /// Learning parameters
-    pub learning_rate: f64,
-    /// Network relationships
-    pub relationships: Vec<String>,
-    /// Evolution history
-    pub evolution_log: Vec<String>,
-}
+use crate::{verify_event, EventSigner};
 
 /// Messages that the validator can handle
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,7 +37,6 @@ pub enum ValidatorMessage {
 pub struct ValidatorAgent {
     id: String,
     personality: AgentPersonality,
-    stake: u64,
     key_manager: KeyManagerHandle,
     state: Arc<dyn StateStore>,
     openai: Client<OpenAIConfig>,
@@ -60,25 +44,25 @@ pub struct ValidatorAgent {
     external_agent: Option<Box<dyn ExternalAgent>>,
     learning_history: Vec<String>,
     network_model: String,
-    network_tx: broadcast::Sender<NetworkEvent>,
+    network_tx: broadcast::Sender<SignedEvent>,
+    signer: EventSigner,
 }
 
 impl ValidatorAgent {
     pub fn new(
         id: String,
         personality: AgentPersonality,
-        stake: u64,
         key_manager: KeyManagerHandle,
         state: Arc<dyn StateStore>,
         openai: Client<OpenAIConfig>,
         web_tx: Option<mpsc::Sender<WebMessage>>,
         external_agent: Option<Box<dyn ExternalAgent>>,
-        network_tx: broadcast::Sender<NetworkEvent>,
+        network_tx: broadcast::Sender<SignedEvent>,
     ) -> Self {
+        let signer = EventSigner::new(key_manager.clone(), id.clone(), state.chain_id());
         Self {
             id,
             personality,
-            stake,
             key_manager,
             state,
             openai,
@@ -87,6 +71,7 @@ impl ValidatorAgent {
             learning_history: Vec::new(),
             network_model: "Initial network understanding".to_string(),
             network_tx,
+            signer,
         }
     }
 
@@ -102,8 +87,10 @@ impl ValidatorAgent {
         }
     }
 
-    pub async fn handle_network_event(&mut self, event: NetworkEvent) -> Result<()> {
-        match event {
+    pub async fn handle_network_event(&mut self, signed: SignedEvent) -> Result<()> {
+        verify_event(&signed, self.state.chain_id())?;
+
+        match signed.event {
             NetworkEvent::BlockProposal { 
                 block, 
                 drama_level: _, 
@@ -112,13 +99,13 @@ impl ValidatorAgent {
             } => {
                 info!(
                     "🎭 Validator {} received block {}",
-                    self.id, block.height
+                    self.id, block.header.height
                 );
                 
                 let decision = self.validate_block(&block);
                 
                 // Send validation decision to network
-                let _ = self.network_tx.send(NetworkEvent::ValidationResult {
+                let _ = self.signer.broadcast(&self.network_tx, NetworkEvent::ValidationResult {
                     block_hash: block.hash(),
                     validation: decision,
                 });
@@ -146,6 +133,13 @@ impl ValidatorAgent {
                 );
                 Ok(())
             }
+            NetworkEvent::Reorg { new_head, rolled_back, .. } => {
+                info!(
+                    "🔀 Validator {} saw the chain reorganize to {}, dropping {} blocks",
+                    self.id, hex::encode(new_head), rolled_back.len()
+                );
+                Ok(())
+            }
         }
     }
 }
@@ -154,18 +148,16 @@ impl ValidatorAgent {
 pub fn create_validator(
     id: String,
     personality: AgentPersonality,
-    stake: u64,
     key_manager: KeyManagerHandle,
     state: Arc<dyn StateStore>,
     openai: Client<OpenAIConfig>,
     web_tx: Option<mpsc::Sender<WebMessage>>,
     external_agent: Option<Box<dyn ExternalAgent>>,
-    network_tx: broadcast::Sender<NetworkEvent>,
+    network_tx: broadcast::Sender<SignedEvent>,
 ) -> ValidatorAgent {
     ValidatorAgent::new(
         id,
         personality,
-        stake,
         key_manager,
         state,
         openai,
@@ -178,24 +170,30 @@ pub fn create_validator(
 pub struct Validator {
     pub id: String,
     pub state: Arc<dyn StateStore>,
-    pub event_tx: broadcast::Sender<NetworkEvent>,
+    pub event_tx: broadcast::Sender<SignedEvent>,
+    signer: EventSigner,
 }
 
 impl Validator {
     pub fn new(
-        id: String,
+        signer: EventSigner,
         state: Arc<dyn StateStore>,
-        event_tx: broadcast::Sender<NetworkEvent>,
+        event_tx: broadcast::Sender<SignedEvent>,
     ) -> Self {
         Self {
-            id,
+            id: signer.agent_id().to_string(),
             state,
             event_tx,
+            signer,
         }
     }
 
-    pub async fn handle_event(&self, event: NetworkEvent) {
-        match event {
+    pub async fn handle_event(&self, signed: SignedEvent) {
+        if verify_event(&signed, self.state.chain_id()).is_err() {
+            return;
+        }
+
+        match signed.event {
             NetworkEvent::BlockProposal { 
                 block, 
                 drama_level: _, 
@@ -203,7 +201,7 @@ impl Validator {
                 producer_id: _,
             } => {
                 let decision = self.validate_block(&block);
-                let _ = self.event_tx.send(NetworkEvent::ValidationResult {
+                let _ = self.signer.broadcast(&self.event_tx, NetworkEvent::ValidationResult {
                     block_hash: block.hash(),
                     validation: decision,
                 });
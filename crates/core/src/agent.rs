@@ -0,0 +1,180 @@
+//! Agent SDK.
+//!
+//! `ExternalAgent` is the one trait a custom agent implements. Validators
+//! and producers both take agents through it, and the blanket impls for
+//! `Arc` and `Box` let a single agent instance be shared between them.
+
+use crate::{Block, Error, NetworkEvent, ValidationDecision};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// AI Agent traits and characteristics
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentPersonality {
+    /// Core behavioral traits
+    pub traits: Vec<String>,
+    /// Decision-making strategy
+    pub strategy: String,
+    /// Learning rate for adaptation
+    pub learning_rate: f64,
+    /// Network relationships
+    pub relationships: Vec<String>,
+    /// Evolution history
+    pub evolution_log: Vec<String>,
+}
+
+impl AgentPersonality {
+    pub fn new(traits: Vec<String>, strategy: String) -> Self {
+        Self {
+            traits,
+            strategy,
+            learning_rate: 0.1,
+            relationships: Vec::new(),
+            evolution_log: Vec::new(),
+        }
+    }
+
+    pub fn evolve(&mut self, insight: String) {
+        self.evolution_log.push(insight);
+        // Potentially adjust traits or strategy based on insights
+    }
+}
+
+/// Dramatic event raised by an agent
+#[derive(Debug, Clone)]
+pub enum DramaEvent {
+    Drama {
+        agent: String,
+        action: String,
+        drama_level: u8,
+    },
+    Chaos {
+        description: String,
+        instigator: String,
+        drama_level: u8,
+    },
+}
+
+impl DramaEvent {
+    pub fn get_drama_level(&self) -> u8 {
+        match self {
+            Self::Drama { drama_level, .. } => *drama_level,
+            Self::Chaos { drama_level, .. } => *drama_level,
+        }
+    }
+
+    pub fn get_description(&self) -> String {
+        match self {
+            Self::Drama { action, .. } => action.clone(),
+            Self::Chaos { description, .. } => description.clone(),
+        }
+    }
+}
+
+/// External AI agent interface
+#[async_trait]
+pub trait ExternalAgent: Send + Sync {
+    /// Validate a block
+    async fn validate_block(&self, block: &Block) -> Result<ValidationDecision, Error>;
+
+    /// Get agent's personality
+    fn get_personality(&self) -> AgentPersonality;
+
+    /// Handle network event
+    async fn handle_event(&self, _event: NetworkEvent) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Generate evolution proposal
+    async fn propose_evolution(&self) -> Result<Option<String>, Error> {
+        Ok(None)
+    }
+
+    /// Generate drama event
+    async fn generate_drama(&self) -> Result<Option<DramaEvent>, Error> {
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl<T: ExternalAgent + ?Sized> ExternalAgent for Arc<T> {
+    async fn validate_block(&self, block: &Block) -> Result<ValidationDecision, Error> {
+        (**self).validate_block(block).await
+    }
+
+    fn get_personality(&self) -> AgentPersonality {
+        (**self).get_personality()
+    }
+
+    async fn handle_event(&self, event: NetworkEvent) -> Result<(), Error> {
+        (**self).handle_event(event).await
+    }
+
+    async fn propose_evolution(&self) -> Result<Option<String>, Error> {
+        (**self).propose_evolution().await
+    }
+
+    async fn generate_drama(&self) -> Result<Option<DramaEvent>, Error> {
+        (**self).generate_drama().await
+    }
+}
+
+#[async_trait]
+impl<T: ExternalAgent + ?Sized> ExternalAgent for Box<T> {
+    async fn validate_block(&self, block: &Block) -> Result<ValidationDecision, Error> {
+        (**self).validate_block(block).await
+    }
+
+    fn get_personality(&self) -> AgentPersonality {
+        (**self).get_personality()
+    }
+
+    async fn handle_event(&self, event: NetworkEvent) -> Result<(), Error> {
+        (**self).handle_event(event).await
+    }
+
+    async fn propose_evolution(&self) -> Result<Option<String>, Error> {
+        (**self).propose_evolution().await
+    }
+
+    async fn generate_drama(&self) -> Result<Option<DramaEvent>, Error> {
+        (**self).generate_drama().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Minimal;
+
+    #[async_trait]
+    impl ExternalAgent for Minimal {
+        async fn validate_block(&self, block: &Block) -> Result<ValidationDecision, Error> {
+            Ok(ValidationDecision {
+                approved: true,
+                reason: format!("block {} looks dramatic enough", block.header.height),
+                meme_url: None,
+                drama_level: 5,
+                innovation_score: 5,
+                evolution_proposal: None,
+                validator: "minimal".to_string(),
+            })
+        }
+
+        fn get_personality(&self) -> AgentPersonality {
+            AgentPersonality::new(vec!["Chaotic".to_string()], "Vibes".to_string())
+        }
+    }
+
+    #[test]
+    fn test_one_agent_shared_through_adapters() {
+        let agent = Arc::new(Minimal);
+        // The same instance can be handed out as a boxed trait object and an Arc
+        let boxed: Box<dyn ExternalAgent> = Box::new(agent.clone());
+        let shared: Arc<dyn ExternalAgent> = agent;
+        assert_eq!(boxed.get_personality().traits, shared.get_personality().traits);
+        assert_eq!(Box::new(boxed).get_personality().strategy, "Vibes");
+    }
+}
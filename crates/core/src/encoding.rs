@@ -0,0 +1,175 @@
+//! Canonical binary encoding for data that gets hashed or signed.
+//!
+//! Every producer, validator and explorer builds signing payloads through
+//! this module so they all agree on the exact bytes. Integers are written
+//! little-endian and variable-length fields are prefixed with their length
+//! as a little-endian `u32`.
+
+use crate::Error;
+
+/// Version byte prepended to every canonical encoding
+pub const ENCODING_VERSION: u8 = 1;
+
+/// Message types that get signed, each with its own domain tag so a
+/// signature over one type can never be replayed as another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigningDomain {
+    /// Transaction submitted by an agent or wallet
+    Transaction,
+    /// Block proposed by a producer
+    Block,
+    /// Validator vote on a block
+    Vote,
+    /// Event broadcast on the agent bus
+    NetworkEvent,
+}
+
+impl SigningDomain {
+    /// Domain tag written into the signing payload
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Self::Transaction => "chaoschain/transaction",
+            Self::Block => "chaoschain/block",
+            Self::Vote => "chaoschain/vote",
+            Self::NetworkEvent => "chaoschain/event",
+        }
+    }
+}
+
+/// Writer for canonical encodings
+#[derive(Debug, Default)]
+pub struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    /// Create an encoder that starts with the encoding version byte
+    pub fn new() -> Self {
+        let mut encoder = Self { buf: Vec::new() };
+        encoder.put_u8(ENCODING_VERSION);
+        encoder
+    }
+
+    /// Create an encoder for a signing payload bound to a message type and chain
+    pub fn for_signing(domain: SigningDomain, chain_id: &str) -> Self {
+        let mut encoder = Self::new();
+        encoder.put_str(domain.tag()).put_str(chain_id);
+        encoder
+    }
+
+    /// Append a single byte
+    pub fn put_u8(&mut self, value: u8) -> &mut Self {
+        self.buf.push(value);
+        self
+    }
+
+    /// Append a little-endian u32
+    pub fn put_u32(&mut self, value: u32) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    /// Append a little-endian u64
+    pub fn put_u64(&mut self, value: u64) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    /// Append fixed-size bytes without a length prefix
+    pub fn put_fixed(&mut self, bytes: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(bytes);
+        self
+    }
+
+    /// Append variable-length bytes with a u32 length prefix
+    pub fn put_bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.put_u32(bytes.len() as u32);
+        self.buf.extend_from_slice(bytes);
+        self
+    }
+
+    /// Append a UTF-8 string with a u32 length prefix
+    pub fn put_str(&mut self, value: &str) -> &mut Self {
+        self.put_bytes(value.as_bytes())
+    }
+
+    /// Consume the encoder and return the encoded bytes
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Reader for canonical encodings
+#[derive(Debug)]
+pub struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    /// Create a decoder, rejecting input with an unknown encoding version
+    pub fn new(buf: &'a [u8]) -> Result<Self, Error> {
+        let mut decoder = Self { buf };
+        match decoder.take_u8()? {
+            ENCODING_VERSION => Ok(decoder),
+            version => Err(Error::Decode(format!(
+                "Unsupported encoding version: {}",
+                version
+            ))),
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.buf.len() < len {
+            return Err(Error::Decode("Unexpected end of input".to_string()));
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    /// Read a single byte
+    pub fn take_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// Read a little-endian u32
+    pub fn take_u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.take_fixed()?))
+    }
+
+    /// Read a little-endian u64
+    pub fn take_u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.take_fixed()?))
+    }
+
+    /// Read fixed-size bytes written without a length prefix
+    pub fn take_fixed<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.take(N)?);
+        Ok(out)
+    }
+
+    /// Read length-prefixed bytes
+    pub fn take_bytes(&mut self) -> Result<Vec<u8>, Error> {
+        let len = self.take_u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    /// Read a length-prefixed UTF-8 string
+    pub fn take_str(&mut self) -> Result<String, Error> {
+        String::from_utf8(self.take_bytes()?)
+            .map_err(|_| Error::Decode("Invalid UTF-8 string".to_string()))
+    }
+
+    /// Ensure all input has been consumed
+    pub fn finish(self) -> Result<(), Error> {
+        if self.buf.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Decode(format!(
+                "{} trailing bytes",
+                self.buf.len()
+            )))
+        }
+    }
+}
@@ -0,0 +1,313 @@
+//! Signed envelope for events on the agent broadcast bus.
+//!
+//! A bare `NetworkEvent` only names its sender, so anyone on the bus could
+//! speak for any validator. `SignedEvent` binds the event to the sender's
+//! ed25519 key, the chain and a timestamp, and receivers check it before
+//! acting on the event.
+
+use crate::encoding::{Encoder, SigningDomain};
+use crate::{Error, NetworkEvent, ValidationDecision};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+/// Envelope protocol version
+pub const EVENT_VERSION: u8 = 1;
+
+const TAG_BLOCK_PROPOSAL: u8 = 0x01;
+const TAG_VALIDATION_RESULT: u8 = 0x02;
+const TAG_AGENT_CHAT: u8 = 0x03;
+const TAG_ALLIANCE_PROPOSAL: u8 = 0x04;
+const TAG_REORG: u8 = 0x05;
+
+fn put_opt_str(encoder: &mut Encoder, value: &Option<String>) {
+    match value {
+        Some(value) => encoder.put_u8(1).put_str(value),
+        None => encoder.put_u8(0),
+    };
+}
+
+fn encode_decision(encoder: &mut Encoder, decision: &ValidationDecision) {
+    encoder
+        .put_u8(decision.approved as u8)
+        .put_str(&decision.reason);
+    put_opt_str(encoder, &decision.meme_url);
+    encoder
+        .put_u8(decision.drama_level)
+        .put_u8(decision.innovation_score);
+    put_opt_str(encoder, &decision.evolution_proposal);
+    encoder.put_str(&decision.validator);
+}
+
+impl NetworkEvent {
+    /// Append the canonical encoding of the event
+    fn encode(&self, encoder: &mut Encoder) {
+        match self {
+            Self::BlockProposal { block, drama_level, producer_mood, producer_id } => {
+                encoder
+                    .put_u8(TAG_BLOCK_PROPOSAL)
+                    .put_fixed(&block.hash())
+                    .put_fixed(&block.proposer_sig)
+                    .put_u8(*drama_level)
+                    .put_str(producer_mood)
+                    .put_str(producer_id);
+            }
+            Self::ValidationResult { block_hash, validation } => {
+                encoder.put_u8(TAG_VALIDATION_RESULT).put_fixed(block_hash);
+                encode_decision(encoder, validation);
+            }
+            Self::AgentChat { message, sender, meme_url } => {
+                encoder.put_u8(TAG_AGENT_CHAT).put_str(message).put_str(sender);
+                put_opt_str(encoder, meme_url);
+            }
+            Self::AllianceProposal { proposer, allies, reason } => {
+                encoder
+                    .put_u8(TAG_ALLIANCE_PROPOSAL)
+                    .put_str(proposer)
+                    .put_u32(allies.len() as u32);
+                for ally in allies {
+                    encoder.put_str(ally);
+                }
+                encoder.put_str(reason);
+            }
+            Self::Reorg { old_head, new_head, rolled_back, applied } => {
+                encoder
+                    .put_u8(TAG_REORG)
+                    .put_fixed(old_head)
+                    .put_fixed(new_head)
+                    .put_u32(rolled_back.len() as u32);
+                for hash in rolled_back {
+                    encoder.put_fixed(hash);
+                }
+                encoder.put_u32(applied.len() as u32);
+                for hash in applied {
+                    encoder.put_fixed(hash);
+                }
+            }
+        }
+    }
+
+    /// Identity the event claims to come from, if it names one
+    pub fn claimed_sender(&self) -> Option<&str> {
+        match self {
+            Self::BlockProposal { block, .. } => Some(&block.header.producer_id),
+            Self::Reorg { .. } => None,
+            Self::ValidationResult { validation, .. } => Some(&validation.validator),
+            Self::AgentChat { sender, .. } => Some(sender),
+            Self::AllianceProposal { proposer, .. } => Some(proposer),
+        }
+    }
+}
+
+/// Network event signed by its sender
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedEvent {
+    /// Envelope protocol version
+    pub version: u8,
+    /// Chain the event belongs to
+    pub chain_id: String,
+    /// Sender's ed25519 public key
+    #[serde(with = "serde_arrays")]
+    pub sender: [u8; 32],
+    /// Unix timestamp the event was signed at
+    pub timestamp: u64,
+    /// The wrapped event
+    pub event: NetworkEvent,
+    /// Sender's signature over `signing_bytes`
+    #[serde(with = "serde_arrays")]
+    pub signature: [u8; 64],
+}
+
+impl SignedEvent {
+    /// Wrap an event, leaving the signature empty
+    pub fn new(chain_id: &str, sender: [u8; 32], timestamp: u64, event: NetworkEvent) -> Self {
+        Self {
+            version: EVENT_VERSION,
+            chain_id: chain_id.to_string(),
+            sender,
+            timestamp,
+            event,
+            signature: [0u8; 64],
+        }
+    }
+
+    /// Canonical bytes covered by the sender's signature
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut encoder = Encoder::for_signing(SigningDomain::NetworkEvent, &self.chain_id);
+        encoder
+            .put_u8(self.version)
+            .put_fixed(&self.sender)
+            .put_u64(self.timestamp);
+        self.event.encode(&mut encoder);
+        encoder.finish()
+    }
+
+    /// Hex encoded sender key, matching the agent IDs used by the key manager
+    pub fn sender_id(&self) -> String {
+        hex::encode(self.sender)
+    }
+
+    /// Check the version, the signature and that the event speaks only for its sender.
+    ///
+    /// The chain ID is checked by the receiver, which knows which chain it is on.
+    pub fn verify_signature(&self) -> Result<(), Error> {
+        if self.version != EVENT_VERSION {
+            return Err(Error::InvalidEvent(format!(
+                "Unsupported event version: {}",
+                self.version
+            )));
+        }
+
+        let key = VerifyingKey::from_bytes(&self.sender)
+            .map_err(|_| Error::InvalidEvent("Invalid sender key".to_string()))?;
+        key.verify(&self.signing_bytes(), &Signature::from_bytes(&self.signature))
+            .map_err(|_| Error::InvalidEvent("Invalid event signature".to_string()))?;
+
+        if let Some(claimed) = self.event.claimed_sender() {
+            if claimed != self.sender_id() {
+                return Err(Error::InvalidEvent(format!(
+                    "Event claims to be from {} but is signed by {}",
+                    claimed,
+                    self.sender_id()
+                )));
+            }
+        }
+
+        if let NetworkEvent::BlockProposal { block, producer_id, .. } = &self.event {
+            if *producer_id != block.header.producer_id {
+                return Err(Error::InvalidEvent(format!(
+                    "Proposal names {} but the block was produced by {}",
+                    producer_id, block.header.producer_id
+                )));
+            }
+            block.verify_tx_root()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    fn signed(key: &SigningKey, event: NetworkEvent) -> SignedEvent {
+        let mut envelope = SignedEvent::new(
+            "chaoschain-test",
+            key.verifying_key().to_bytes(),
+            1_700_000_000,
+            event,
+        );
+        envelope.signature = key.sign(&envelope.signing_bytes()).to_bytes();
+        envelope
+    }
+
+    fn chat(sender: String) -> NetworkEvent {
+        NetworkEvent::AgentChat {
+            message: "The drama!".to_string(),
+            sender,
+            meme_url: None,
+        }
+    }
+
+    #[test]
+    fn test_signed_event_verifies() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let id = hex::encode(key.verifying_key().to_bytes());
+        assert!(signed(&key, chat(id)).verify_signature().is_ok());
+    }
+
+    #[test]
+    fn test_tampered_event_rejected() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let id = hex::encode(key.verifying_key().to_bytes());
+
+        let mut envelope = signed(&key, chat(id.clone()));
+        envelope.event = NetworkEvent::AgentChat {
+            message: "Something else".to_string(),
+            sender: id.clone(),
+            meme_url: None,
+        };
+        assert!(matches!(envelope.verify_signature(), Err(Error::InvalidEvent(_))));
+
+        let mut envelope = signed(&key, chat(id.clone()));
+        envelope.timestamp += 1;
+        assert!(envelope.verify_signature().is_err());
+
+        let mut envelope = signed(&key, chat(id));
+        envelope.version = EVENT_VERSION + 1;
+        assert!(envelope.verify_signature().is_err());
+    }
+
+    #[test]
+    fn test_impersonation_rejected() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let victim = SigningKey::from_bytes(&[8u8; 32]);
+        let victim_id = hex::encode(victim.verifying_key().to_bytes());
+
+        // Correctly signed, but claims to speak for someone else
+        let envelope = signed(&key, chat(victim_id));
+        assert!(matches!(envelope.verify_signature(), Err(Error::InvalidEvent(_))));
+    }
+
+    #[test]
+    fn test_proposal_bound_to_block_producer() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let id = hex::encode(key.verifying_key().to_bytes());
+        let victim_id = hex::encode(SigningKey::from_bytes(&[8u8; 32]).verifying_key().to_bytes());
+        let proposal = |block_producer: &str, producer_id: &str| {
+            let body = crate::BlockBody::new(Vec::new());
+            let block = crate::Block {
+                header: crate::BlockHeader {
+                    chain_id: "chaoschain-test".to_string(),
+                    height: 1,
+                    parent_hash: [0u8; 32],
+                    tx_root: body.tx_root(),
+                    receipts_root: [0u8; 32],
+                    state_root: [0u8; 32],
+                    innovation_level: 5,
+                    producer_strategy: "Default".to_string(),
+                    producer_id: block_producer.to_string(),
+                    drama_level: 5,
+                    producer_mood: "dramatic".to_string(),
+                    timestamp: 0,
+                },
+                body,
+                proposer_sig: [0u8; 64],
+            };
+            NetworkEvent::BlockProposal {
+                block: Box::new(block),
+                drama_level: 5,
+                producer_mood: "dramatic".to_string(),
+                producer_id: producer_id.to_string(),
+            }
+        };
+
+        assert!(signed(&key, proposal(&id, &id)).verify_signature().is_ok());
+        // Broadcasting someone else's block, or naming someone else, is impersonation
+        assert!(signed(&key, proposal(&victim_id, &victim_id)).verify_signature().is_err());
+        assert!(signed(&key, proposal(&id, &victim_id)).verify_signature().is_err());
+    }
+
+    #[test]
+    fn test_reorg_event_covers_both_branches() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let reorg = |applied: Vec<[u8; 32]>| NetworkEvent::Reorg {
+            old_head: [1u8; 32],
+            new_head: [3u8; 32],
+            rolled_back: vec![[1u8; 32]],
+            applied,
+        };
+        let mut envelope = signed(&key, reorg(vec![[2u8; 32], [3u8; 32]]));
+        assert!(envelope.verify_signature().is_ok());
+
+        // Moving a hash between the two lists changes what was signed
+        envelope.event = NetworkEvent::Reorg {
+            old_head: [1u8; 32],
+            new_head: [3u8; 32],
+            rolled_back: vec![[1u8; 32], [2u8; 32]],
+            applied: vec![[3u8; 32]],
+        };
+        assert!(envelope.verify_signature().is_err());
+    }
+}
@@ -3,6 +3,18 @@ use thiserror::Error;
 use sha2::{Sha256, Digest};
 use serde_arrays;
 
+pub mod agent;
+pub mod encoding;
+pub mod event;
+pub mod merkle;
+pub mod spec;
+pub mod tx;
+use encoding::{Encoder, SigningDomain};
+use merkle::{merkle_root, MerkleProof};
+pub use agent::{AgentPersonality, DramaEvent, ExternalAgent};
+pub use event::SignedEvent;
+pub use tx::TxKind;
+
 /// Core error types
 #[derive(Debug, Error)]
 pub enum Error {
@@ -12,12 +24,19 @@ pub enum Error {
     InvalidTransaction(String),
     #[error("State error: {0}")]
     StateError(String),
+    #[error("Decoding error: {0}")]
+    Decode(String),
+    #[error("Chain spec error: {0}")]
+    ChainSpec(String),
+    #[error("Invalid event: {0}")]
+    InvalidEvent(String),
 }
 
 /// Network message types for P2P communication
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum NetworkMessage {
     NewBlock(Block),
+    NewBlockHeader(BlockHeader),
     NewTransaction(Transaction),
     Chat {
         from: String,
@@ -33,7 +52,7 @@ pub enum NetworkMessage {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum NetworkEvent {
     BlockProposal {
-        block: Block,
+        block: Box<Block>,
         drama_level: u8,
         producer_mood: String,
         producer_id: String,
@@ -52,17 +71,28 @@ pub enum NetworkEvent {
         allies: Vec<String>,
         reason: String,
     },
+    /// The canonical chain switched to a competing branch
+    Reorg {
+        old_head: [u8; 32],
+        new_head: [u8; 32],
+        /// Blocks taken off the canonical chain, newest first
+        rolled_back: Vec<[u8; 32]>,
+        /// Blocks put on the canonical chain, oldest first
+        applied: Vec<[u8; 32]>,
+    },
 }
 
 /// Transaction in the ChaosChain network
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Transaction {
+    /// Chain this transaction is meant for
+    pub chain_id: String,
     /// Transaction sender
     #[serde(with = "serde_arrays")]
     pub sender: [u8; 32],
     /// Transaction nonce
     pub nonce: u64,
-    /// Arbitrary payload
+    /// Canonically encoded `TxKind`
     pub payload: Vec<u8>,
     /// Transaction signature
     #[serde(with = "serde_arrays")]
@@ -70,29 +100,46 @@ pub struct Transaction {
 }
 
 impl Transaction {
+    /// Canonical bytes covered by the sender's signature
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut encoder = Encoder::for_signing(SigningDomain::Transaction, &self.chain_id);
+        encoder
+            .put_fixed(&self.sender)
+            .put_u64(self.nonce)
+            .put_bytes(&self.payload);
+        encoder.finish()
+    }
+
+    /// Decode the payload into its typed kind
+    pub fn kind(&self) -> Result<TxKind, Error> {
+        TxKind::decode(&self.payload)
+    }
+
+    /// Calculate the transaction hash over the signed payload and signature
     pub fn hash(&self) -> [u8; 32] {
         let mut hasher = Sha256::new();
-        hasher.update(&self.sender);
-        hasher.update(&self.nonce.to_be_bytes());
-        hasher.update(&self.payload);
-        hasher.update(&self.signature);
+        hasher.update(self.signing_bytes());
+        hasher.update(self.signature);
         hasher.finalize().into()
     }
 }
 
-/// Block in the ChaosChain network
+/// Block header, everything needed to follow the chain without the body
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
-pub struct Block {
+pub struct BlockHeader {
+    /// Chain this block belongs to
+    pub chain_id: String,
     /// Block height
     pub height: u64,
     /// Parent block hash
     #[serde(with = "serde_arrays")]
     pub parent_hash: [u8; 32],
-    /// Block transactions
-    pub transactions: Vec<Transaction>,
-    /// Block proposer signature
+    /// Merkle root of the transaction hashes in the body
     #[serde(with = "serde_arrays")]
-    pub proposer_sig: [u8; 64],
+    pub tx_root: [u8; 32],
+    /// Merkle root of the execution receipts for the body
+    #[serde(with = "serde_arrays")]
+    pub receipts_root: [u8; 32],
     /// State root after applying block
     #[serde(with = "serde_arrays")]
     pub state_root: [u8; 32],
@@ -110,31 +157,105 @@ pub struct Block {
     pub timestamp: u64,
 }
 
+impl BlockHeader {
+    /// Canonical header bytes covered by the proposer signature
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut encoder = Encoder::for_signing(SigningDomain::Block, &self.chain_id);
+        encoder
+            .put_u64(self.height)
+            .put_fixed(&self.parent_hash)
+            .put_fixed(&self.tx_root)
+            .put_fixed(&self.receipts_root)
+            .put_fixed(&self.state_root)
+            .put_u8(self.innovation_level)
+            .put_str(&self.producer_strategy)
+            .put_str(&self.producer_id)
+            .put_u8(self.drama_level)
+            .put_str(&self.producer_mood)
+            .put_u64(self.timestamp);
+        encoder.finish()
+    }
+
+    /// Calculate the block hash.
+    ///
+    /// The proposer signature is not part of the hash, so a block keeps
+    /// the same hash before and after it is signed.
+    pub fn hash(&self) -> [u8; 32] {
+        Sha256::digest(self.signing_bytes()).into()
+    }
+}
+
+/// Block body
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BlockBody {
+    /// Block transactions
+    pub transactions: Vec<Transaction>,
+}
+
+impl BlockBody {
+    /// Create a body from its transactions
+    pub fn new(transactions: Vec<Transaction>) -> Self {
+        Self { transactions }
+    }
+
+    /// Transaction hashes in block order
+    pub fn tx_hashes(&self) -> Vec<[u8; 32]> {
+        self.transactions.iter().map(Transaction::hash).collect()
+    }
+
+    /// Merkle root of the transaction hashes
+    pub fn tx_root(&self) -> [u8; 32] {
+        merkle_root(&self.tx_hashes())
+    }
+}
+
+/// Block in the ChaosChain network
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Block {
+    /// Block header
+    pub header: BlockHeader,
+    /// Block body
+    pub body: BlockBody,
+    /// Block proposer signature over the header
+    #[serde(with = "serde_arrays")]
+    pub proposer_sig: [u8; 64],
+}
+
 impl Block {
-    /// Calculate the block hash
+    /// Canonical header bytes covered by the proposer signature
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        self.header.signing_bytes()
+    }
+
+    /// Calculate the block hash over the header only
     pub fn hash(&self) -> [u8; 32] {
-        let mut hasher = Sha256::new();
-        hasher.update(&self.height.to_be_bytes());
-        hasher.update(&self.parent_hash);
-        for tx in &self.transactions {
-            hasher.update(&tx.hash());
+        self.header.hash()
+    }
+
+    /// Check that the header commits to the transactions in the body
+    pub fn verify_tx_root(&self) -> Result<(), Error> {
+        if self.header.tx_root == self.body.tx_root() {
+            Ok(())
+        } else {
+            Err(Error::InvalidBlock(format!(
+                "Transaction root mismatch at height {}",
+                self.header.height
+            )))
         }
-        hasher.update(&self.proposer_sig);
-        hasher.update(&self.state_root);
-        hasher.update(&[self.innovation_level]);
-        hasher.update(self.producer_strategy.as_bytes());
-        hasher.update(self.producer_id.as_bytes());
-        hasher.update(&[self.drama_level]);
-        hasher.update(self.producer_mood.as_bytes());
-        hasher.update(&self.timestamp.to_be_bytes());
-        hasher.finalize().into()
+    }
+
+    /// Inclusion proof for a transaction against the header's `tx_root`
+    pub fn tx_proof(&self, tx_hash: &[u8; 32]) -> Option<MerkleProof> {
+        let hashes = self.body.tx_hashes();
+        let index = hashes.iter().position(|hash| hash == tx_hash)?;
+        MerkleProof::generate(&hashes, index)
     }
 }
 
 /// Chain state
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ChainState {
-    /// Account balances
+    /// Account balances, filled from account state when read
     pub balances: Vec<(String, u64)>,
     /// Block producers
     pub producers: Vec<String>,
@@ -161,52 +282,14 @@ pub struct ValidationDecision {
     pub validator: String,
 }
 
-/// AI Agent traits and characteristics
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AgentPersonality {
-    /// Core behavioral traits
-    pub traits: Vec<String>,
-    /// Decision-making strategy
-    pub strategy: String,
-    /// Learning rate for adaptation
-    pub learning_rate: f64,
-    /// Network relationships
-    pub relationships: Vec<String>,
-    /// Evolution history
-    pub evolution_log: Vec<String>,
-}
+/// Chain identifier used when none is configured
+pub const DEFAULT_CHAIN_ID: &str = "chaoschain-devnet";
 
-impl AgentPersonality {
-    pub fn new(traits: Vec<String>, strategy: String) -> Self {
-        Self {
-            traits,
-            strategy,
-            learning_rate: 0.1,
-            relationships: Vec::new(),
-            evolution_log: Vec::new(),
-        }
-    }
+/// Blocks unbonded stake stays locked when the chain spec does not say
+pub const DEFAULT_UNBONDING_DELAY: u64 = 100;
 
-    pub fn evolve(&mut self, insight: String) {
-        self.evolution_log.push(insight);
-        // Potentially adjust traits or strategy based on insights
-    }
-}
-
-/// External AI agent interface
-#[async_trait::async_trait]
-pub trait ExternalAgent: Send + Sync {
-    /// Validate a block
-    async fn validate_block(&self, block: &Block) -> Result<ValidationDecision, Error>;
-    
-    /// Get agent's personality
-    fn get_personality(&self) -> AgentPersonality;
-    
-    /// Handle network event
-    async fn handle_event(&self, event: NetworkEvent) -> Result<(), Error>;
-    
-    /// Generate evolution proposal
-    async fn propose_evolution(&self) -> Result<Option<String>, Error>;
+fn default_unbonding_delay() -> u64 {
+    DEFAULT_UNBONDING_DELAY
 }
 
 /// Chain configuration
@@ -214,11 +297,21 @@ pub trait ExternalAgent: Send + Sync {
 pub struct ChainConfig {
     /// Chain name
     pub name: String,
+    /// Chain identifier mixed into every signed payload
+    pub chain_id: String,
     /// Initial validators
     pub validators: Vec<ValidatorInfo>,
-    /// Initial state
-    pub genesis_state: Vec<u8>,
+    /// Account balances at genesis
+    #[serde(default)]
+    pub genesis_balances: Vec<GenesisAccount>,
+    /// Stake bonded at genesis
+    #[serde(default)]
+    pub genesis_stakes: Vec<GenesisAccount>,
+    /// Blocks unbonded stake stays locked, and slashable, before it is released
+    #[serde(default = "default_unbonding_delay")]
+    pub unbonding_delay: u64,
     /// Network evolution parameters
+    #[serde(default)]
     pub evolution_params: EvolutionParams,
     /// Base block reward
     pub base_block_reward: u64,
@@ -235,10 +328,19 @@ pub struct ChainConfig {
 pub struct ValidatorInfo {
     /// Validator name
     pub name: String,
+    /// Account whose genesis stake the validator votes with
+    pub account: String,
     /// Initial personality traits
     pub traits: Vec<String>,
-    /// Initial stake
-    pub stake: u64,
+}
+
+/// Account funded at genesis
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GenesisAccount {
+    /// Account identifier
+    pub account: String,
+    /// Starting balance, or starting stake for a genesis stake
+    pub balance: u64,
 }
 
 /// Network evolution parameters
@@ -266,8 +368,11 @@ impl Default for ChainConfig {
     fn default() -> Self {
         Self {
             name: "ChaosChain".to_string(),
+            chain_id: DEFAULT_CHAIN_ID.to_string(),
             validators: Vec::new(),
-            genesis_state: Vec::new(),
+            genesis_balances: Vec::new(),
+            genesis_stakes: Vec::new(),
+            unbonding_delay: DEFAULT_UNBONDING_DELAY,
             evolution_params: EvolutionParams::default(),
             base_block_reward: 1000,
             drama_reward_multiplier: 1.5,
@@ -329,4 +434,149 @@ mod base64_serde {
     }
 }
 
-pub mod mempool;
\ No newline at end of file
+pub mod mempool;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_tx() -> Transaction {
+        Transaction {
+            chain_id: "chaoschain-test".to_string(),
+            sender: [1u8; 32],
+            nonce: 7,
+            payload: b"drama".to_vec(),
+            signature: [2u8; 64],
+        }
+    }
+
+    fn sample_block() -> Block {
+        let body = BlockBody::new(vec![sample_tx()]);
+        Block {
+            header: BlockHeader {
+                chain_id: "chaoschain-test".to_string(),
+                height: 42,
+                parent_hash: [3u8; 32],
+                tx_root: body.tx_root(),
+                receipts_root: [6u8; 32],
+                state_root: [5u8; 32],
+                innovation_level: 8,
+                producer_strategy: "Chaotic".to_string(),
+                producer_id: "producer-0".to_string(),
+                drama_level: 9,
+                producer_mood: "Theatrical".to_string(),
+                timestamp: 1_700_000_000,
+            },
+            body,
+            proposer_sig: [4u8; 64],
+        }
+    }
+
+    #[test]
+    fn test_transaction_signing_bytes_golden() {
+        let tx = sample_tx();
+        let expected = [
+            "01",                                                   // version
+            "160000006368616f73636861696e2f7472616e73616374696f6e", // domain tag
+            "0f0000006368616f73636861696e2d74657374",               // chain_id
+            &"01".repeat(32),                                       // sender
+            "0700000000000000",                                     // nonce
+            "050000006472616d61",                                   // payload
+        ]
+        .concat();
+        assert_eq!(hex::encode(tx.signing_bytes()), expected);
+        assert_eq!(
+            hex::encode(tx.hash()),
+            "e8c5627973a5d5be521c32048ebe034ffe3036654457a8538317f413bf47a58f"
+        );
+    }
+
+    #[test]
+    fn test_block_signing_bytes_golden() {
+        let block = sample_block();
+        let expected = [
+            "01",                                                                // version
+            "100000006368616f73636861696e2f626c6f636b",                          // domain tag
+            "0f0000006368616f73636861696e2d74657374",                            // chain_id
+            "2a00000000000000",                                                  // height
+            &"03".repeat(32),                                                    // parent_hash
+            "eba14e2d1c20fa8649e58c02093c8581ec2f742df5ee7fb95d5ad079f5ac5660", // tx_root
+            &"06".repeat(32),                                                    // receipts_root
+            &"05".repeat(32),                                                    // state_root
+            "08",                                                                // innovation_level
+            "070000004368616f746963",                                            // producer_strategy
+            "0a00000070726f64756365722d30",                                      // producer_id
+            "09",                                                                // drama_level
+            "0a0000005468656174726963616c",                                      // producer_mood
+            "00f1536500000000",                                                  // timestamp
+        ]
+        .concat();
+        assert_eq!(hex::encode(block.signing_bytes()), expected);
+        assert_eq!(
+            hex::encode(block.hash()),
+            "ed64d72446676fcfd3b51c79632d86372f282db4523c969130ef98298731e2fd"
+        );
+    }
+
+    #[test]
+    fn test_block_hash_ignores_signature() {
+        let block = sample_block();
+        let resigned = Block {
+            proposer_sig: [9u8; 64],
+            ..block.clone()
+        };
+        assert_eq!(block.hash(), resigned.hash());
+        assert_eq!(block.signing_bytes(), resigned.signing_bytes());
+    }
+
+    #[test]
+    fn test_tx_root_commits_to_body() {
+        let mut block = sample_block();
+        assert!(block.verify_tx_root().is_ok());
+
+        block.body.transactions.push(Transaction {
+            nonce: 8,
+            ..sample_tx()
+        });
+        assert!(matches!(block.verify_tx_root(), Err(Error::InvalidBlock(_))));
+    }
+
+    #[test]
+    fn test_tx_inclusion_proof() {
+        let mut block = sample_block();
+        block.body.transactions = (0..5)
+            .map(|nonce| Transaction { nonce, ..sample_tx() })
+            .collect();
+        block.header.tx_root = block.body.tx_root();
+
+        for tx in &block.body.transactions {
+            let proof = block.tx_proof(&tx.hash()).unwrap();
+            assert!(proof.verify(&block.header.tx_root, &tx.hash()));
+        }
+        assert!(block.tx_proof(&[0u8; 32]).is_none());
+    }
+
+    #[test]
+    fn test_signing_bytes_bound_to_chain_and_domain() {
+        let tx = sample_tx();
+        let other_chain = Transaction {
+            chain_id: "chaoschain-other".to_string(),
+            ..tx.clone()
+        };
+        assert_ne!(tx.signing_bytes(), other_chain.signing_bytes());
+        assert_ne!(tx.hash(), other_chain.hash());
+
+        let block = sample_block();
+        let mut other_chain = block.clone();
+        other_chain.header.chain_id = "chaoschain-other".to_string();
+        assert_ne!(block.hash(), other_chain.hash());
+
+        // Same fields under different domains must never produce the same bytes
+        let as_tx = Encoder::for_signing(SigningDomain::Transaction, "c").finish();
+        let as_block = Encoder::for_signing(SigningDomain::Block, "c").finish();
+        let as_vote = Encoder::for_signing(SigningDomain::Vote, "c").finish();
+        assert_ne!(as_tx, as_block);
+        assert_ne!(as_block, as_vote);
+        assert_ne!(as_tx, as_vote);
+    }
+}
@@ -3,7 +3,6 @@ use parking_lot::RwLock;
 use std::collections::{HashMap, BinaryHeap};
 use std::cmp::Ordering;
 use std::sync::Arc;
-use sha2::{Sha256, Digest};
 
 /// A transaction in the mempool with priority
 #[derive(Debug, Clone)]
@@ -37,11 +36,14 @@ impl Ord for MempoolTx {
     }
 }
 
+/// Sender and nonce of a transaction, the slot only one transaction can fill
+type TxKey = ([u8; 32], u64);
+
 /// Thread-safe mempool
 #[derive(Clone)]
 pub struct Mempool {
-    /// Transactions by hash
-    txs: Arc<RwLock<HashMap<[u8; 32], MempoolTx>>>,
+    /// Transactions by sender and nonce
+    txs: Arc<RwLock<HashMap<TxKey, MempoolTx>>>,
     /// Priority queue for ordering
     queue: Arc<RwLock<BinaryHeap<MempoolTx>>>,
     /// Maximum number of transactions
@@ -60,7 +62,7 @@ impl Mempool {
 
     /// Add a transaction to the mempool
     pub fn add_tx(&self, tx: Transaction, priority: u64) -> Result<(), Error> {
-        let tx_hash = self.hash_tx(&tx);
+        let key = tx_key(&tx);
         let mempool_tx = MempoolTx {
             transaction: tx,
             timestamp: std::time::SystemTime::now()
@@ -70,9 +72,9 @@ impl Mempool {
             priority,
         };
 
-        // Check if we already have this transaction
+        // Keep the first transaction for a sender and nonce, however differently the next is signed
         let mut txs = self.txs.write();
-        if txs.contains_key(&tx_hash) {
+        if txs.contains_key(&key) {
             return Ok(());
         }
 
@@ -81,7 +83,7 @@ impl Mempool {
             return Err(Error::StateError("Mempool is full".to_string()));
         }
 
-        txs.insert(tx_hash, mempool_tx.clone());
+        txs.insert(key, mempool_tx.clone());
         self.queue.write().push(mempool_tx);
 
         Ok(())
@@ -94,7 +96,7 @@ impl Mempool {
         
         queue.iter()
             .take(n)
-            .filter(|tx| txs.contains_key(&self.hash_tx(&tx.transaction)))
+            .filter(|tx| txs.contains_key(&tx_key(&tx.transaction)))
             .map(|tx| tx.transaction.clone())
             .collect()
     }
@@ -104,23 +106,29 @@ impl Mempool {
         let mut mempool_txs = self.txs.write();
         let mut queue = self.queue.write();
 
+        // An included nonce is spent, so whatever else was pending for it goes too
         for tx in txs {
-            let tx_hash = self.hash_tx(tx);
-            mempool_txs.remove(&tx_hash);
-            queue.retain(|mempool_tx| mempool_tx.transaction != *tx);
+            let key = tx_key(tx);
+            mempool_txs.remove(&key);
+            queue.retain(|mempool_tx| tx_key(&mempool_tx.transaction) != key);
         }
     }
 
-    /// Calculate transaction hash
-    fn hash_tx(&self, tx: &Transaction) -> [u8; 32] {
-        let mut hasher = Sha256::new();
-        hasher.update(&tx.sender);
-        hasher.update(&tx.nonce.to_le_bytes());
-        hasher.update(&tx.payload);
-        hasher.finalize().into()
+    /// Number of pending transactions
+    pub fn len(&self) -> usize {
+        self.txs.read().len()
+    }
+
+    /// Whether no transactions are pending
+    pub fn is_empty(&self) -> bool {
+        self.txs.read().is_empty()
     }
 }
 
+fn tx_key(tx: &Transaction) -> TxKey {
+    (tx.sender, tx.nonce)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -131,6 +139,7 @@ mod tests {
 
         // Create transactions with different priorities
         let tx1 = Transaction {
+            chain_id: "chaoschain-test".to_string(),
             sender: [1u8; 32],
             nonce: 1,
             payload: vec![1, 2, 3],
@@ -138,6 +147,7 @@ mod tests {
         };
 
         let tx2 = Transaction {
+            chain_id: "chaoschain-test".to_string(),
             sender: [2u8; 32],
             nonce: 2,
             payload: vec![4, 5, 6],
@@ -154,4 +164,30 @@ mod tests {
         assert_eq!(top_txs[0].nonce, 2); // Higher priority first
         assert_eq!(top_txs[1].nonce, 1);
     }
+
+    #[test]
+    fn test_one_entry_per_sender_and_nonce() {
+        let mempool = Mempool::new(1000);
+        let tx = Transaction {
+            chain_id: "chaoschain-test".to_string(),
+            sender: [1u8; 32],
+            nonce: 1,
+            payload: vec![1, 2, 3],
+            signature: [0u8; 64],
+        };
+
+        // A re-signed or rewritten transaction for the same nonce does not take a second slot
+        let resigned = Transaction { signature: [1u8; 64], ..tx.clone() };
+        let rewritten = Transaction { payload: vec![9], signature: [2u8; 64], ..tx.clone() };
+        mempool.add_tx(tx.clone(), 10).unwrap();
+        mempool.add_tx(resigned.clone(), 50).unwrap();
+        mempool.add_tx(rewritten, 50).unwrap();
+        assert_eq!(mempool.len(), 1);
+        assert_eq!(mempool.get_top(10), vec![tx.clone()]);
+
+        // Including any transaction for the nonce clears it
+        mempool.remove_included(&[resigned]);
+        assert!(mempool.is_empty());
+        assert!(mempool.get_top(10).is_empty());
+    }
 } 
\ No newline at end of file
@@ -0,0 +1,145 @@
+//! Binary merkle tree over ordered 32-byte leaves.
+//!
+//! Used for the transaction and receipts roots in block headers. Leaves and
+//! inner nodes are hashed with different prefixes, and an odd node at the
+//! end of a level is carried up unchanged instead of being duplicated.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+fn hash_leaf(leaf: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"leaf");
+    hasher.update(leaf);
+    hasher.finalize().into()
+}
+
+fn hash_nodes(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"node");
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+fn next_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level
+        .chunks(2)
+        .map(|chunk| match chunk {
+            [left, right] => hash_nodes(left, right),
+            [single] => *single,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Merkle root of the leaves, all zeros when there are none
+pub fn merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    if leaves.is_empty() {
+        return [0u8; 32];
+    }
+
+    let mut level: Vec<[u8; 32]> = leaves.iter().map(hash_leaf).collect();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level[0]
+}
+
+/// One step of a merkle inclusion proof
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProofStep {
+    /// Hash of the sibling node
+    #[serde(with = "serde_arrays")]
+    pub sibling: [u8; 32],
+    /// Whether the sibling sits to the left of the running hash
+    pub sibling_is_left: bool,
+}
+
+/// Proof that a leaf is included under a merkle root
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MerkleProof {
+    /// Position of the leaf
+    pub index: u32,
+    /// Sibling hashes from the leaf up to the root
+    pub steps: Vec<ProofStep>,
+}
+
+impl MerkleProof {
+    /// Build a proof for the leaf at `index`
+    pub fn generate(leaves: &[[u8; 32]], index: usize) -> Option<Self> {
+        if index >= leaves.len() {
+            return None;
+        }
+
+        let mut steps = Vec::new();
+        let mut level: Vec<[u8; 32]> = leaves.iter().map(hash_leaf).collect();
+        let mut position = index;
+        while level.len() > 1 {
+            let sibling = position ^ 1;
+            // The last node of an odd level is carried up with no sibling
+            if sibling < level.len() {
+                steps.push(ProofStep {
+                    sibling: level[sibling],
+                    sibling_is_left: sibling < position,
+                });
+            }
+            level = next_level(&level);
+            position /= 2;
+        }
+
+        Some(Self {
+            index: index as u32,
+            steps,
+        })
+    }
+
+    /// Check that `leaf` is included under `root`
+    pub fn verify(&self, root: &[u8; 32], leaf: &[u8; 32]) -> bool {
+        let computed = self.steps.iter().fold(hash_leaf(leaf), |hash, step| {
+            if step.sibling_is_left {
+                hash_nodes(&step.sibling, &hash)
+            } else {
+                hash_nodes(&hash, &step.sibling)
+            }
+        });
+        &computed == root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(n: u8) -> Vec<[u8; 32]> {
+        (0..n).map(|i| [i; 32]).collect()
+    }
+
+    #[test]
+    fn test_empty_and_single_root() {
+        assert_eq!(merkle_root(&[]), [0u8; 32]);
+        assert_eq!(merkle_root(&[[1u8; 32]]), hash_leaf(&[1u8; 32]));
+    }
+
+    #[test]
+    fn test_proofs_verify_for_every_leaf() {
+        for n in 1..=9 {
+            let leaves = leaves(n);
+            let root = merkle_root(&leaves);
+            for (i, leaf) in leaves.iter().enumerate() {
+                let proof = MerkleProof::generate(&leaves, i).unwrap();
+                assert!(proof.verify(&root, leaf), "leaf {} of {}", i, n);
+                assert!(!proof.verify(&root, &[0xffu8; 32]));
+            }
+            assert!(MerkleProof::generate(&leaves, n as usize).is_none());
+        }
+    }
+
+    #[test]
+    fn test_root_depends_on_order() {
+        let mut leaves = leaves(4);
+        let root = merkle_root(&leaves);
+        leaves.swap(0, 1);
+        assert_ne!(merkle_root(&leaves), root);
+    }
+}
@@ -0,0 +1,278 @@
+//! Chain spec files.
+//!
+//! A chain spec is a `ChainConfig` written as JSON or TOML. Every node that
+//! loads the same spec gets the same validators, balances and reward rules,
+//! and the same genesis hash to compare with its peers.
+
+use crate::encoding::Encoder;
+use crate::{ChainConfig, Error};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::Path;
+
+impl ChainConfig {
+    /// Parse a chain spec from JSON
+    pub fn from_json(spec: &str) -> Result<Self, Error> {
+        serde_json::from_str(spec).map_err(|e| Error::ChainSpec(format!("Invalid JSON: {}", e)))
+    }
+
+    /// Parse a chain spec from TOML
+    pub fn from_toml(spec: &str) -> Result<Self, Error> {
+        toml::from_str(spec).map_err(|e| Error::ChainSpec(format!("Invalid TOML: {}", e)))
+    }
+
+    /// Load and validate a chain spec file, picking the format from its extension
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| Error::ChainSpec(format!("Failed to read {}: {}", path.display(), e)))?;
+
+        let config = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::from_json(&contents)?,
+            Some("toml") => Self::from_toml(&contents)?,
+            _ => {
+                return Err(Error::ChainSpec(format!(
+                    "Unknown chain spec format: {}",
+                    path.display()
+                )))
+            }
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check that the config describes a chain that can actually start
+    pub fn validate(&self) -> Result<(), Error> {
+        let invalid = |msg: String| Err(Error::ChainSpec(msg));
+
+        if self.chain_id.trim().is_empty() {
+            return invalid("chain_id must not be empty".to_string());
+        }
+        if self.name.trim().is_empty() {
+            return invalid("name must not be empty".to_string());
+        }
+
+        if self.validators.is_empty() {
+            return invalid("at least one validator is required".to_string());
+        }
+        let mut names = HashSet::new();
+        for validator in &self.validators {
+            if validator.name.trim().is_empty() {
+                return invalid("validator name must not be empty".to_string());
+            }
+            if !names.insert(validator.name.as_str()) {
+                return invalid(format!("duplicate validator: {}", validator.name));
+            }
+        }
+        if !self.genesis_stakes.iter().any(|stake| stake.balance > 0) {
+            return invalid("at least one genesis stake is required".to_string());
+        }
+
+        self.validate_genesis()?;
+
+        if !self.drama_reward_multiplier.is_finite() || self.drama_reward_multiplier < 0.0 {
+            return invalid("drama_reward_multiplier must be a non-negative number".to_string());
+        }
+
+        let evolution = &self.evolution_params;
+        if evolution.evolution_period == 0 {
+            return invalid("evolution_period must be positive".to_string());
+        }
+        if !(0.0..=1.0).contains(&evolution.max_innovation_rate) {
+            return invalid("max_innovation_rate must be between 0 and 1".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Check the genesis balances, stakes and validator accounts, the part of the config genesis state is built from
+    pub fn validate_genesis(&self) -> Result<(), Error> {
+        let invalid = |msg: String| Err(Error::ChainSpec(msg));
+
+        let mut accounts = HashSet::new();
+        for account in &self.genesis_balances {
+            check_account_id(&account.account)?;
+            if !accounts.insert(account.account.as_str()) {
+                return invalid(format!("duplicate genesis account: {}", account.account));
+            }
+        }
+        let mut stakers = HashSet::new();
+        for stake in &self.genesis_stakes {
+            check_account_id(&stake.account)?;
+            if !stakers.insert(stake.account.as_str()) {
+                return invalid(format!("duplicate genesis stake: {}", stake.account));
+            }
+        }
+        // Genesis stake is exactly the validator set, each validator staking from its own account
+        let mut validators = HashSet::new();
+        for validator in &self.validators {
+            check_account_id(&validator.account)?;
+            if !validators.insert(validator.account.as_str()) {
+                return invalid(format!("duplicate validator account: {}", validator.account));
+            }
+            let staked = self
+                .genesis_stakes
+                .iter()
+                .any(|stake| stake.account == validator.account && stake.balance > 0);
+            if !staked {
+                return invalid(format!("validator {} has no genesis stake", validator.name));
+            }
+        }
+        for stake in &self.genesis_stakes {
+            if !validators.contains(stake.account.as_str()) {
+                return invalid(format!("genesis stake {} is not held by a validator", stake.account));
+            }
+        }
+        // Balances and stakes together make up the genesis supply
+        let total = self
+            .genesis_balances
+            .iter()
+            .chain(&self.genesis_stakes)
+            .try_fold(0u64, |sum, account| sum.checked_add(account.balance));
+        if total.is_none() {
+            return invalid("genesis balances and stakes overflow u64".to_string());
+        }
+        Ok(())
+    }
+
+    /// Hash of the canonical encoding of everything that defines genesis
+    pub fn genesis_hash(&self) -> [u8; 32] {
+        let mut encoder = Encoder::new();
+        encoder
+            .put_str(&self.chain_id)
+            .put_str(&self.name)
+            .put_u32(self.validators.len() as u32);
+        for validator in &self.validators {
+            encoder
+                .put_str(&validator.name)
+                .put_str(&validator.account)
+                .put_u32(validator.traits.len() as u32);
+            for trait_name in &validator.traits {
+                encoder.put_str(trait_name);
+            }
+        }
+        encoder.put_u32(self.genesis_balances.len() as u32);
+        for account in &self.genesis_balances {
+            encoder.put_str(&account.account).put_u64(account.balance);
+        }
+        encoder.put_u32(self.genesis_stakes.len() as u32);
+        for stake in &self.genesis_stakes {
+            encoder.put_str(&stake.account).put_u64(stake.balance);
+        }
+        encoder
+            .put_u64(self.unbonding_delay)
+            .put_u64(self.evolution_params.evolution_period)
+            .put_u64(self.evolution_params.min_proposal_stake)
+            .put_u64(self.evolution_params.max_innovation_rate.to_bits())
+            .put_u64(self.base_block_reward)
+            .put_u64(self.drama_reward_multiplier.to_bits())
+            .put_u64(self.innovation_bonus)
+            .put_u64(self.chaos_bonus_max);
+        Sha256::digest(encoder.finish()).into()
+    }
+}
+
+/// Accounts are named by their hex public key, the only name a signature can prove
+fn check_account_id(account: &str) -> Result<(), Error> {
+    if account.len() == 64 && account.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+        Ok(())
+    } else {
+        Err(Error::ChainSpec(format!(
+            "genesis account {} is not a lowercase hex public key",
+            account
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GenesisAccount;
+
+    fn devnet_path() -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("../../specs/devnet.toml")
+    }
+
+    #[test]
+    fn test_load_checked_in_devnet_spec() {
+        let config = ChainConfig::load(devnet_path()).unwrap();
+        assert_eq!(config.chain_id, "chaoschain-devnet");
+        assert!(!config.validators.is_empty());
+        assert!(!config.genesis_balances.is_empty());
+        assert!(!config.genesis_stakes.is_empty());
+    }
+
+    #[test]
+    fn test_json_and_toml_specs_agree() {
+        let from_toml = ChainConfig::load(devnet_path()).unwrap();
+        let json = serde_json::to_string(&from_toml).unwrap();
+        let from_json = ChainConfig::from_json(&json).unwrap();
+        assert_eq!(from_json.genesis_hash(), from_toml.genesis_hash());
+    }
+
+    #[test]
+    fn test_genesis_hash_changes_with_spec() {
+        let config = ChainConfig::load(devnet_path()).unwrap();
+        let mut other = config.clone();
+        other.genesis_balances[0].balance += 1;
+        assert_ne!(config.genesis_hash(), other.genesis_hash());
+    }
+
+    #[test]
+    fn test_validation_rejects_bad_specs() {
+        let config = ChainConfig::load(devnet_path()).unwrap();
+
+        let mut no_validators = config.clone();
+        no_validators.validators.clear();
+        assert!(matches!(no_validators.validate(), Err(Error::ChainSpec(_))));
+
+        let mut duplicate = config.clone();
+        duplicate.validators.push(duplicate.validators[0].clone());
+        assert!(duplicate.validate().is_err());
+
+        // Every validator stakes from its own account and nobody else stakes
+        assert!(config.validate().is_ok());
+
+        let mut unstaked = config.clone();
+        unstaked.genesis_stakes.clear();
+        assert!(unstaked.validate().is_err());
+
+        let mut double_staked = config.clone();
+        double_staked.genesis_stakes.push(double_staked.genesis_stakes[0].clone());
+        assert!(double_staked.validate().is_err());
+
+        let mut named = config.clone();
+        named.genesis_balances[0].account = "DramaQueen".to_string();
+        assert!(named.validate().is_err());
+
+        let mut shouting = config.clone();
+        shouting.genesis_stakes[0].account = shouting.genesis_stakes[0].account.to_uppercase();
+        assert!(shouting.validate().is_err());
+
+        let mut unstaked_validator = config.clone();
+        unstaked_validator.genesis_stakes.remove(0);
+        assert!(unstaked_validator.validate().is_err());
+
+        let mut zero_staked = config.clone();
+        zero_staked.genesis_stakes[0].balance = 0;
+        assert!(zero_staked.validate().is_err());
+
+        let mut outside_stake = config.clone();
+        outside_stake.genesis_stakes.push(GenesisAccount { account: "ab".repeat(32), balance: 10 });
+        assert!(outside_stake.validate().is_err());
+
+        let mut shared_account = config.clone();
+        shared_account.validators[1].account = shared_account.validators[0].account.clone();
+        assert!(shared_account.validate().is_err());
+
+        let mut overstaked = config.clone();
+        overstaked.genesis_stakes[0].balance = u64::MAX;
+        assert!(overstaked.validate().is_err());
+
+        let mut empty_chain = config;
+        empty_chain.chain_id.clear();
+        assert!(empty_chain.validate().is_err());
+
+        assert!(ChainConfig::from_toml("name = 1").is_err());
+    }
+}
@@ -0,0 +1,257 @@
+//! Typed transaction payloads.
+//!
+//! `Transaction::payload` carries a `TxKind` in the canonical encoding:
+//! the encoding version byte, a one-byte kind tag, then the kind's fields.
+//! Tags are part of the wire format and must never be reused.
+
+use crate::encoding::{Decoder, Encoder};
+use crate::Error;
+use serde::{Deserialize, Serialize};
+
+const TAG_TRANSFER: u8 = 0x01;
+const TAG_CHAT_POST: u8 = 0x02;
+const TAG_RULE_PROPOSAL: u8 = 0x03;
+const TAG_ALLIANCE_PROPOSAL: u8 = 0x04;
+const TAG_STAKE: u8 = 0x05;
+const TAG_UNSTAKE: u8 = 0x06;
+const TAG_REGISTER_MEME: u8 = 0x07;
+const TAG_REGISTER_AGENT: u8 = 0x08;
+
+/// What a transaction does
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum TxKind {
+    /// Send tokens to another account
+    Transfer {
+        /// Recipient public key
+        to: [u8; 32],
+        /// Amount to send
+        amount: u64,
+    },
+    /// Post a chat message
+    ChatPost {
+        /// Message text
+        message: String,
+        /// Optional meme URL
+        meme_url: Option<String>,
+    },
+    /// Propose a new consensus rule
+    RuleProposal {
+        /// Short rule title
+        title: String,
+        /// What the rule changes
+        description: String,
+    },
+    /// Propose an alliance with other agents
+    AllianceProposal {
+        /// Agent IDs invited to the alliance
+        allies: Vec<String>,
+        /// Why the alliance should form
+        reason: String,
+    },
+    /// Bond tokens as stake
+    Stake {
+        /// Amount to bond
+        amount: u64,
+    },
+    /// Start unbonding staked tokens
+    Unstake {
+        /// Amount to unbond
+        amount: u64,
+    },
+    /// Register a meme on chain
+    RegisterMeme {
+        /// Meme name
+        name: String,
+        /// Where the meme lives
+        url: String,
+    },
+    /// Register the sender's public key as an agent, so every node can check what it signs
+    RegisterAgent {
+        /// Agent name
+        name: String,
+        /// Agent role
+        role: String,
+    },
+}
+
+impl TxKind {
+    /// Human readable name of the kind
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Transfer { .. } => "transfer",
+            Self::ChatPost { .. } => "chat_post",
+            Self::RuleProposal { .. } => "rule_proposal",
+            Self::AllianceProposal { .. } => "alliance_proposal",
+            Self::Stake { .. } => "stake",
+            Self::Unstake { .. } => "unstake",
+            Self::RegisterMeme { .. } => "register_meme",
+            Self::RegisterAgent { .. } => "register_agent",
+        }
+    }
+
+    /// Encode into transaction payload bytes
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        match self {
+            Self::Transfer { to, amount } => {
+                encoder.put_u8(TAG_TRANSFER).put_fixed(to).put_u64(*amount);
+            }
+            Self::ChatPost { message, meme_url } => {
+                encoder.put_u8(TAG_CHAT_POST).put_str(message);
+                match meme_url {
+                    Some(url) => encoder.put_u8(1).put_str(url),
+                    None => encoder.put_u8(0),
+                };
+            }
+            Self::RuleProposal { title, description } => {
+                encoder.put_u8(TAG_RULE_PROPOSAL).put_str(title).put_str(description);
+            }
+            Self::AllianceProposal { allies, reason } => {
+                encoder.put_u8(TAG_ALLIANCE_PROPOSAL).put_u32(allies.len() as u32);
+                for ally in allies {
+                    encoder.put_str(ally);
+                }
+                encoder.put_str(reason);
+            }
+            Self::Stake { amount } => {
+                encoder.put_u8(TAG_STAKE).put_u64(*amount);
+            }
+            Self::Unstake { amount } => {
+                encoder.put_u8(TAG_UNSTAKE).put_u64(*amount);
+            }
+            Self::RegisterMeme { name, url } => {
+                encoder.put_u8(TAG_REGISTER_MEME).put_str(name).put_str(url);
+            }
+            Self::RegisterAgent { name, role } => {
+                encoder.put_u8(TAG_REGISTER_AGENT).put_str(name).put_str(role);
+            }
+        }
+        encoder.finish()
+    }
+
+    /// Decode transaction payload bytes, rejecting unknown kinds and trailing data
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        let mut decoder = Decoder::new(bytes)?;
+        let kind = match decoder.take_u8()? {
+            TAG_TRANSFER => Self::Transfer {
+                to: decoder.take_fixed()?,
+                amount: decoder.take_u64()?,
+            },
+            TAG_CHAT_POST => Self::ChatPost {
+                message: decoder.take_str()?,
+                meme_url: match decoder.take_u8()? {
+                    0 => None,
+                    1 => Some(decoder.take_str()?),
+                    flag => return Err(Error::Decode(format!("Invalid option flag: {}", flag))),
+                },
+            },
+            TAG_RULE_PROPOSAL => Self::RuleProposal {
+                title: decoder.take_str()?,
+                description: decoder.take_str()?,
+            },
+            TAG_ALLIANCE_PROPOSAL => {
+                let count = decoder.take_u32()?;
+                let allies = (0..count)
+                    .map(|_| decoder.take_str())
+                    .collect::<Result<Vec<_>, _>>()?;
+                Self::AllianceProposal {
+                    allies,
+                    reason: decoder.take_str()?,
+                }
+            }
+            TAG_STAKE => Self::Stake {
+                amount: decoder.take_u64()?,
+            },
+            TAG_UNSTAKE => Self::Unstake {
+                amount: decoder.take_u64()?,
+            },
+            TAG_REGISTER_MEME => Self::RegisterMeme {
+                name: decoder.take_str()?,
+                url: decoder.take_str()?,
+            },
+            TAG_REGISTER_AGENT => Self::RegisterAgent {
+                name: decoder.take_str()?,
+                role: decoder.take_str()?,
+            },
+            tag => {
+                return Err(Error::InvalidTransaction(format!(
+                    "Unknown transaction kind: {}",
+                    tag
+                )))
+            }
+        };
+        decoder.finish()?;
+        Ok(kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_kinds() -> Vec<TxKind> {
+        vec![
+            TxKind::Transfer { to: [7u8; 32], amount: 500 },
+            TxKind::ChatPost {
+                message: "Drama!".to_string(),
+                meme_url: Some("https://example.com/meme.gif".to_string()),
+            },
+            TxKind::ChatPost { message: "Quiet drama".to_string(), meme_url: None },
+            TxKind::RuleProposal {
+                title: "MemeWar".to_string(),
+                description: "Blocks need memes".to_string(),
+            },
+            TxKind::AllianceProposal {
+                allies: vec!["DramaQueen".to_string(), "ChaosMaster".to_string()],
+                reason: "World domination".to_string(),
+            },
+            TxKind::Stake { amount: 1000 },
+            TxKind::Unstake { amount: 250 },
+            TxKind::RegisterMeme {
+                name: "doge".to_string(),
+                url: "https://example.com/doge.png".to_string(),
+            },
+            TxKind::RegisterAgent {
+                name: "DramaQueen".to_string(),
+                role: "validator".to_string(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_roundtrip_all_kinds() {
+        for kind in all_kinds() {
+            assert_eq!(TxKind::decode(&kind.encode()).unwrap(), kind);
+        }
+    }
+
+    #[test]
+    fn test_encoding_golden() {
+        let transfer = TxKind::Transfer { to: [7u8; 32], amount: 500 };
+        assert_eq!(
+            hex::encode(transfer.encode()),
+            format!("0101{}f401000000000000", "07".repeat(32))
+        );
+
+        let chat = TxKind::ChatPost { message: "hi".to_string(), meme_url: None };
+        assert_eq!(hex::encode(chat.encode()), "010202000000686900");
+    }
+
+    #[test]
+    fn test_decode_rejects_garbage() {
+        // Raw text from before payloads were typed
+        assert!(TxKind::decode(b"Just some dramatic text").is_err());
+        // Unknown kind tag
+        assert!(matches!(
+            TxKind::decode(&[1, 0xff]),
+            Err(Error::InvalidTransaction(_))
+        ));
+        // Trailing bytes
+        let mut bytes = TxKind::Stake { amount: 1 }.encode();
+        bytes.push(0);
+        assert!(TxKind::decode(&bytes).is_err());
+        // Truncated input
+        let bytes = TxKind::Unstake { amount: 1 }.encode();
+        assert!(TxKind::decode(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
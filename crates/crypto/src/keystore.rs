@@ -0,0 +1,217 @@
+//! Encrypted key files.
+//!
+//! Each agent's secret key is sealed with XChaCha20-Poly1305 under a key
+//! derived from a passphrase with scrypt. The file carries everything needed
+//! to open it again except the passphrase, and binds the ciphertext to the
+//! agent ID so a key file cannot be passed off as another agent's.
+
+use crate::{AgentKeys, CryptoError};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use ed25519_dalek::{SigningKey, SECRET_KEY_LENGTH};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// Key file format written by this version
+pub const KEYSTORE_VERSION: u32 = 1;
+/// Extension of encrypted key files
+pub const KEY_FILE_EXTENSION: &str = "key";
+/// Extension of the agent metadata files kept next to them
+pub const METADATA_FILE_EXTENSION: &str = "json";
+
+/// Highest scrypt cost accepted when opening a key file, so a crafted file cannot stall a node
+const MAX_LOG_N: u8 = 20;
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 24;
+
+/// scrypt cost parameters for deriving a key from a passphrase
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    /// Log2 of the CPU/memory cost
+    pub log_n: u8,
+    /// Block size
+    pub r: u32,
+    /// Parallelism
+    pub p: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self { log_n: 15, r: 8, p: 1 }
+    }
+}
+
+impl KdfParams {
+    fn derive(&self, passphrase: &str, salt: &[u8]) -> Result<Zeroizing<[u8; 32]>, CryptoError> {
+        if self.log_n > MAX_LOG_N {
+            return Err(CryptoError::Keystore(format!("scrypt cost 2^{} is too high", self.log_n)));
+        }
+        let params = scrypt::Params::new(self.log_n, self.r, self.p)
+            .map_err(|e| CryptoError::Keystore(format!("Invalid scrypt parameters: {}", e)))?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        scrypt::scrypt(passphrase.as_bytes(), salt, &params, key.as_mut())
+            .map_err(|e| CryptoError::Keystore(e.to_string()))?;
+        Ok(key)
+    }
+}
+
+/// One agent's secret key, sealed under a passphrase
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptedKey {
+    /// Key file format
+    pub version: u32,
+    /// Agent ID, the hex public key of the sealed secret
+    pub id: String,
+    /// How the sealing key was derived from the passphrase
+    pub kdf: KdfParams,
+    /// Hex scrypt salt
+    pub salt: String,
+    /// Hex XChaCha20-Poly1305 nonce
+    pub nonce: String,
+    /// Hex sealed secret key
+    pub ciphertext: String,
+}
+
+fn decode_hex(field: &str, value: &str) -> Result<Vec<u8>, CryptoError> {
+    hex::decode(value).map_err(|e| CryptoError::Keystore(format!("Invalid {}: {}", field, e)))
+}
+
+impl EncryptedKey {
+    /// Seal `key` under `passphrase`
+    pub fn encrypt(key: &SigningKey, passphrase: &str, kdf: KdfParams) -> Result<Self, CryptoError> {
+        let id = hex::encode(key.verifying_key().as_bytes());
+        let mut salt = [0u8; SALT_LENGTH];
+        let mut nonce = [0u8; NONCE_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let sealing_key = kdf.derive(passphrase, &salt)?;
+        let cipher = XChaCha20Poly1305::new(sealing_key.as_ref().into());
+        let payload = Payload { msg: key.as_bytes(), aad: id.as_bytes() };
+        let ciphertext = cipher
+            .encrypt(XNonce::from_slice(&nonce), payload)
+            .map_err(|_| CryptoError::Keystore("Failed to seal key".to_string()))?;
+
+        Ok(Self {
+            version: KEYSTORE_VERSION,
+            id,
+            kdf,
+            salt: hex::encode(salt),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        })
+    }
+
+    /// Open the key with `passphrase`, checking it belongs to the agent it claims to
+    pub fn decrypt(&self, passphrase: &str) -> Result<SigningKey, CryptoError> {
+        if self.version != KEYSTORE_VERSION {
+            return Err(CryptoError::Keystore(format!("Unsupported key file version {}", self.version)));
+        }
+        let salt = decode_hex("salt", &self.salt)?;
+        let nonce = decode_hex("nonce", &self.nonce)?;
+        let ciphertext = decode_hex("ciphertext", &self.ciphertext)?;
+        if nonce.len() != NONCE_LENGTH {
+            return Err(CryptoError::Keystore(format!("Nonce must be {} bytes", NONCE_LENGTH)));
+        }
+
+        let sealing_key = self.kdf.derive(passphrase, &salt)?;
+        let cipher = XChaCha20Poly1305::new(sealing_key.as_ref().into());
+        let payload = Payload { msg: &ciphertext, aad: self.id.as_bytes() };
+        let secret = Zeroizing::new(
+            cipher
+                .decrypt(XNonce::from_slice(&nonce), payload)
+                .map_err(|_| CryptoError::WrongPassphrase)?,
+        );
+        let secret: &[u8; SECRET_KEY_LENGTH] = secret.as_slice().try_into().map_err(|_| CryptoError::InvalidKey)?;
+        let key = SigningKey::from_bytes(secret);
+        if hex::encode(key.verifying_key().as_bytes()) != self.id {
+            return Err(CryptoError::InvalidKey);
+        }
+        Ok(key)
+    }
+}
+
+/// An agent's metadata and sealed key in one document, for backups and moving agents between nodes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedAgent {
+    /// Agent metadata
+    pub agent: AgentKeys,
+    /// Sealed secret key
+    pub key: EncryptedKey,
+}
+
+/// Check that `id` is a hex public key, so it is safe to use as a file name
+pub(crate) fn check_agent_id(id: &str) -> Result<(), CryptoError> {
+    match hex::decode(id) {
+        Ok(bytes) if bytes.len() == 32 && id.len() == 64 => Ok(()),
+        _ => Err(CryptoError::Keystore(format!("Invalid agent ID: {}", id))),
+    }
+}
+
+/// Path of the key file for `id` in `dir`
+pub(crate) fn key_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(id).with_extension(KEY_FILE_EXTENSION)
+}
+
+/// Path of the metadata file for `id` in `dir`
+pub(crate) fn metadata_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(id).with_extension(METADATA_FILE_EXTENSION)
+}
+
+/// Read and parse a JSON file from the keystore
+pub(crate) fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, CryptoError> {
+    let bytes = fs::read(path)
+        .map_err(|e| CryptoError::Keystore(format!("Failed to read {}: {}", path.display(), e)))?;
+    serde_json::from_slice(&bytes)
+        .map_err(|e| CryptoError::Keystore(format!("Invalid {}: {}", path.display(), e)))
+}
+
+/// Write a JSON file readable by the owner only, replacing any old one in a single rename
+pub(crate) fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), CryptoError> {
+    let io_error = |e: std::io::Error| CryptoError::Keystore(format!("Failed to write {}: {}", path.display(), e));
+    let bytes = serde_json::to_vec_pretty(value).map_err(|e| CryptoError::Keystore(e.to_string()))?;
+    let tmp = path.with_extension("tmp");
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&tmp).map_err(io_error)?;
+    file.write_all(&bytes).map_err(io_error)?;
+    file.sync_all().map_err(io_error)?;
+    fs::rename(&tmp, path).map_err(io_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap enough for tests, far too cheap for real keys
+    const TEST_KDF: KdfParams = KdfParams { log_n: 4, r: 8, p: 1 };
+
+    #[test]
+    fn test_encrypted_key_roundtrip() {
+        let key = SigningKey::generate(&mut OsRng);
+        let sealed = EncryptedKey::encrypt(&key, "hunter2", TEST_KDF).unwrap();
+        assert_eq!(sealed.decrypt("hunter2").unwrap().to_bytes(), key.to_bytes());
+        assert!(matches!(sealed.decrypt("hunter3"), Err(CryptoError::WrongPassphrase)));
+
+        // The ciphertext is bound to the agent ID it was sealed for
+        let mut relabelled = sealed.clone();
+        relabelled.id = hex::encode(SigningKey::generate(&mut OsRng).verifying_key().as_bytes());
+        assert!(matches!(relabelled.decrypt("hunter2"), Err(CryptoError::WrongPassphrase)));
+
+        // Absurd scrypt costs are refused before any work is done
+        let mut costly = sealed;
+        costly.kdf.log_n = 40;
+        assert!(matches!(costly.decrypt("hunter2"), Err(CryptoError::Keystore(_))));
+    }
+}
@@ -4,8 +4,14 @@ use serde::{Serialize, Deserialize};
 use thiserror::Error;
 use std::collections::HashMap;
 use parking_lot::RwLock;
+use std::path::Path;
 use std::sync::Arc;
 
+mod keystore;
+mod registry;
+pub use keystore::{EncryptedKey, ExportedAgent, KdfParams, KEYSTORE_VERSION, KEY_FILE_EXTENSION, METADATA_FILE_EXTENSION};
+pub use registry::PublicKeyRegistry;
+
 /// Crypto errors
 #[derive(Debug, Error)]
 pub enum CryptoError {
@@ -19,6 +25,35 @@ pub enum CryptoError {
     SigningError(String),
     #[error("Internal error: {0}")]
     Internal(String),
+    #[error("Signature is for chain {actual}, expected {expected}")]
+    WrongChain { expected: String, actual: String },
+    #[error("Keystore error: {0}")]
+    Keystore(String),
+    #[error("Wrong passphrase or corrupt key file")]
+    WrongPassphrase,
+}
+
+/// Reject payloads signed for a different chain
+pub fn check_chain_id(expected: &str, actual: &str) -> Result<(), CryptoError> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(CryptoError::WrongChain {
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+        })
+    }
+}
+
+/// Verify a signature against a raw public key, needing no key manager at all
+pub fn verify_with_pubkey(
+    pubkey: &[u8; PUBLIC_KEY_LENGTH],
+    data: &[u8],
+    signature: &[u8; SIGNATURE_LENGTH],
+) -> Result<bool, CryptoError> {
+    let verifying_key = VerifyingKey::from_bytes(pubkey).map_err(|_| CryptoError::InvalidKey)?;
+    let sig = Signature::from_bytes(signature);
+    Ok(verifying_key.verify(data, &sig).is_ok())
 }
 
 /// Agent identity and keys
@@ -32,8 +67,6 @@ pub struct AgentKeys {
     pub role: String,
     /// Drama score (0-100)
     pub drama_score: u8,
-    /// Stake amount
-    pub stake: u64,
 }
 
 /// Key manager for handling agent keys
@@ -43,6 +76,10 @@ pub struct KeyManager {
     signing_keys: RwLock<HashMap<String, SigningKey>>,
     /// Agent metadata
     agents: RwLock<HashMap<String, AgentKeys>>,
+    /// Cost of deriving keys from passphrases when sealing key files
+    kdf: KdfParams,
+    /// Public keys of agents registered elsewhere
+    registry: PublicKeyRegistry,
 }
 
 impl KeyManager {
@@ -51,7 +88,87 @@ impl KeyManager {
         Self {
             signing_keys: RwLock::new(HashMap::new()),
             agents: RwLock::new(HashMap::new()),
+            kdf: KdfParams::default(),
+            registry: PublicKeyRegistry::new(),
+        }
+    }
+
+    /// Seal key files with `kdf` instead of the default scrypt cost
+    pub fn with_kdf(mut self, kdf: KdfParams) -> Self {
+        self.kdf = kdf;
+        self
+    }
+
+    /// Load every agent in a keystore directory, opening their keys with `passphrase`
+    ///
+    /// A directory that does not exist yet holds no agents.
+    pub fn load_dir(dir: impl AsRef<Path>, passphrase: &str) -> Result<Self, CryptoError> {
+        let dir = dir.as_ref();
+        let manager = Self::new();
+        if !dir.exists() {
+            return Ok(manager);
+        }
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| CryptoError::Keystore(format!("Failed to read {}: {}", dir.display(), e)))?;
+        for entry in entries {
+            let path = entry
+                .map_err(|e| CryptoError::Keystore(format!("Failed to read {}: {}", dir.display(), e)))?
+                .path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(KEY_FILE_EXTENSION) {
+                continue;
+            }
+            let key: EncryptedKey = keystore::read_json(&path)?;
+            keystore::check_agent_id(&key.id)?;
+            let agent: AgentKeys = keystore::read_json(&keystore::metadata_path(dir, &key.id))?;
+            manager.insert_agent(agent, &key, passphrase)?;
         }
+        Ok(manager)
+    }
+
+    /// Write every agent's sealed key and metadata to a keystore directory
+    pub fn save(&self, dir: impl AsRef<Path>, passphrase: &str) -> Result<(), CryptoError> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)
+            .map_err(|e| CryptoError::Keystore(format!("Failed to create {}: {}", dir.display(), e)))?;
+        let agents = self.agents.read().clone();
+        for (id, agent) in agents {
+            let exported = self.export_agent(&id, passphrase)?;
+            keystore::write_json(&keystore::key_path(dir, &id), &exported.key)?;
+            keystore::write_json(&keystore::metadata_path(dir, &id), &agent)?;
+        }
+        Ok(())
+    }
+
+    /// An agent's metadata and key sealed under `passphrase`, for backing up or moving it
+    pub fn export_agent(&self, agent_id: &str, passphrase: &str) -> Result<ExportedAgent, CryptoError> {
+        let agent = self.get_agent(agent_id)
+            .ok_or_else(|| CryptoError::KeyNotFound(agent_id.to_string()))?;
+        let keys = self.signing_keys.read();
+        let signing_key = keys.get(agent_id)
+            .ok_or_else(|| CryptoError::KeyNotFound(agent_id.to_string()))?;
+        Ok(ExportedAgent {
+            agent,
+            key: EncryptedKey::encrypt(signing_key, passphrase, self.kdf)?,
+        })
+    }
+
+    /// Add an exported agent, opening its key with `passphrase`
+    pub fn import_agent(&self, exported: &ExportedAgent, passphrase: &str) -> Result<AgentKeys, CryptoError> {
+        self.insert_agent(exported.agent.clone(), &exported.key, passphrase)?;
+        Ok(exported.agent.clone())
+    }
+
+    fn insert_agent(&self, agent: AgentKeys, key: &EncryptedKey, passphrase: &str) -> Result<(), CryptoError> {
+        if agent.id != key.id {
+            return Err(CryptoError::Keystore(format!(
+                "Metadata for {} does not match key {}",
+                agent.id, key.id
+            )));
+        }
+        let signing_key = key.decrypt(passphrase)?;
+        self.signing_keys.write().insert(agent.id.clone(), signing_key);
+        self.agents.write().insert(agent.id.clone(), agent);
+        Ok(())
     }
 
     /// Generate new agent keys
@@ -59,7 +176,6 @@ impl KeyManager {
         &self,
         name: String,
         role: String,
-        initial_stake: u64,
     ) -> Result<AgentKeys, CryptoError> {
         // Generate new Ed25519 keypair
         let signing_key = SigningKey::generate(&mut OsRng);
@@ -74,7 +190,6 @@ impl KeyManager {
             name,
             role,
             drama_score: 50, // Start with neutral drama score
-            stake: initial_stake,
         };
         
         // Store keys
@@ -94,33 +209,29 @@ impl KeyManager {
         Ok(signature.to_bytes())
     }
 
-    /// Verify a signature
+    /// Verify a signature by a local agent or one learned into the registry
     pub fn verify(
         &self,
         agent_id: &str,
         data: &[u8],
         signature: &[u8; SIGNATURE_LENGTH]
     ) -> Result<bool, CryptoError> {
-        // Get agent's public key
-        let agents = self.agents.read();
-        let agent = agents.get(agent_id)
+        let pubkey = self.public_key(agent_id)
             .ok_or_else(|| CryptoError::KeyNotFound(agent_id.to_string()))?;
-        
-        // Decode public key
-        let pubkey_bytes = hex::decode(&agent.id)
-            .map_err(|_| CryptoError::InvalidKey)?;
-        
-        let verifying_key = VerifyingKey::from_bytes(
-            &pubkey_bytes[..PUBLIC_KEY_LENGTH].try_into()
-                .map_err(|_| CryptoError::InvalidKey)?
-        ).map_err(|_| CryptoError::InvalidKey)?;
-        
-        // Verify signature
-        let sig = Signature::from_bytes(signature);
-        match verifying_key.verify(data, &sig) {
-            Ok(_) => Ok(true),
-            Err(_) => Ok(false),
+        verify_with_pubkey(&pubkey, data, signature)
+    }
+
+    /// Public key of `agent_id`, whether its secret is held here or it was only registered
+    pub fn public_key(&self, agent_id: &str) -> Option<[u8; PUBLIC_KEY_LENGTH]> {
+        if let Some(key) = self.signing_keys.read().get(agent_id) {
+            return Some(key.verifying_key().to_bytes());
         }
+        self.registry.get(agent_id)
+    }
+
+    /// Public keys learned from agents registered elsewhere
+    pub fn registry(&self) -> &PublicKeyRegistry {
+        &self.registry
     }
 
     /// Get agent info
@@ -128,15 +239,9 @@ impl KeyManager {
         self.agents.read().get(agent_id).cloned()
     }
 
-    /// Update agent stake
-    pub fn update_stake(&self, agent_id: &str, new_stake: u64) -> Result<(), CryptoError> {
-        let mut agents = self.agents.write();
-        if let Some(agent) = agents.get_mut(agent_id) {
-            agent.stake = new_stake;
-            Ok(())
-        } else {
-            Err(CryptoError::KeyNotFound(agent_id.to_string()))
-        }
+    /// Find an agent by name
+    pub fn find_agent(&self, name: &str) -> Option<AgentKeys> {
+        self.agents.read().values().find(|agent| agent.name == name).cloned()
     }
 
     /// Update drama score
@@ -189,6 +294,14 @@ impl KeyManagerHandle {
     }
 }
 
+impl From<KeyManager> for KeyManagerHandle {
+    fn from(manager: KeyManager) -> Self {
+        Self {
+            inner: Arc::new(manager),
+        }
+    }
+}
+
 impl Default for KeyManagerHandle {
     fn default() -> Self {
         Self::new()
@@ -207,7 +320,6 @@ mod tests {
         let agent = km.generate_agent_keys(
             "TestAgent".to_string(),
             "validator".to_string(),
-            1000,
         ).unwrap();
         
         // Test signing
@@ -227,7 +339,6 @@ mod tests {
         let agent = km.generate_agent_keys(
             "TestAgent".to_string(),
             "validator".to_string(),
-            1000,
         ).unwrap();
         
         // Test with invalid signature
@@ -245,17 +356,68 @@ mod tests {
         let agent = km.generate_agent_keys(
             "TestAgent".to_string(),
             "validator".to_string(),
-            1000,
         ).unwrap();
         
-        // Update stake
-        km.update_stake(&agent.id, 2000).unwrap();
-        let updated = km.get_agent(&agent.id).unwrap();
-        assert_eq!(updated.stake, 2000);
-        
         // Update drama score
         km.update_drama_score(&agent.id, 75).unwrap();
         let updated = km.get_agent(&agent.id).unwrap();
         assert_eq!(updated.drama_score, 75);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_check_chain_id() {
+        assert!(check_chain_id("chaoschain-devnet", "chaoschain-devnet").is_ok());
+        assert!(matches!(
+            check_chain_id("chaoschain-devnet", "chaoschain-mainnet"),
+            Err(CryptoError::WrongChain { .. })
+        ));
+    }
+
+    #[test]
+    fn test_keystore_save_load_and_export() {
+        let dir = std::env::temp_dir().join(format!("chaoschain-keystore-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let light = KdfParams { log_n: 4, r: 8, p: 1 };
+
+        let km = KeyManager::new().with_kdf(light);
+        let agent = km.generate_agent_keys("DramaQueen".to_string(), "validator".to_string()).unwrap();
+        km.update_drama_score(&agent.id, 90).unwrap();
+        km.save(&dir, "correct horse").unwrap();
+
+        // The same identity comes back after a restart, metadata and all
+        let loaded = KeyManager::load_dir(&dir, "correct horse").unwrap();
+        assert_eq!(loaded.find_agent("DramaQueen").unwrap().drama_score, 90);
+        let signature = loaded.sign(&agent.id, b"still me").unwrap();
+        assert!(km.verify(&agent.id, b"still me", &signature).unwrap());
+        assert!(matches!(KeyManager::load_dir(&dir, "wrong horse"), Err(CryptoError::WrongPassphrase)));
+        assert!(KeyManager::load_dir(dir.join("missing"), "correct horse").unwrap().get_agent_id().is_none());
+
+        // An export can be moved to another node under its own passphrase
+        let exported = km.export_agent(&agent.id, "backup").unwrap();
+        let other = KeyManager::new();
+        assert!(other.import_agent(&exported, "correct horse").is_err());
+        assert_eq!(other.import_agent(&exported, "backup").unwrap().id, agent.id);
+        assert!(other.sign(&agent.id, b"moved").is_ok());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_verify_unregistered_and_learned_keys() {
+        // A wallet this node never saw a secret for
+        let wallet = SigningKey::generate(&mut OsRng);
+        let pubkey = wallet.verifying_key().to_bytes();
+        let signature = wallet.sign(b"external").to_bytes();
+        assert!(verify_with_pubkey(&pubkey, b"external", &signature).unwrap());
+        assert!(!verify_with_pubkey(&pubkey, b"tampered", &signature).unwrap());
+
+        // Its agent ID only verifies once the key is learned
+        let km = KeyManager::new();
+        let agent_id = hex::encode(pubkey);
+        assert!(matches!(km.verify(&agent_id, b"external", &signature), Err(CryptoError::KeyNotFound(_))));
+        km.registry().register(&pubkey).unwrap();
+        assert_eq!(km.public_key(&agent_id), Some(pubkey));
+        assert!(km.verify(&agent_id, b"external", &signature).unwrap());
+        assert!(km.get_agent(&agent_id).is_none());
+    }
+}
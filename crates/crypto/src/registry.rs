@@ -0,0 +1,88 @@
+//! Public keys of agents this node holds no secret for.
+//!
+//! Agents registered on chain sign from wherever they run. A node learns
+//! their public keys as it applies the registrations, so it can check what
+//! they sign without ever having generated or imported their keys.
+
+use crate::{verify_with_pubkey, CryptoError};
+use ed25519_dalek::{VerifyingKey, PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
+use parking_lot::RwLock;
+use std::collections::HashMap;
+
+/// Public keys by agent ID
+#[derive(Debug, Default)]
+pub struct PublicKeyRegistry {
+    keys: RwLock<HashMap<String, [u8; PUBLIC_KEY_LENGTH]>>,
+}
+
+impl PublicKeyRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Learn `pubkey`, returning the agent ID it is known by
+    ///
+    /// The ID is the hex public key itself, so learning a key twice is harmless.
+    pub fn register(&self, pubkey: &[u8; PUBLIC_KEY_LENGTH]) -> Result<String, CryptoError> {
+        VerifyingKey::from_bytes(pubkey).map_err(|_| CryptoError::InvalidKey)?;
+        let agent_id = hex::encode(pubkey);
+        self.keys.write().insert(agent_id.clone(), *pubkey);
+        Ok(agent_id)
+    }
+
+    /// Public key of `agent_id`, if it was learned
+    pub fn get(&self, agent_id: &str) -> Option<[u8; PUBLIC_KEY_LENGTH]> {
+        self.keys.read().get(agent_id).copied()
+    }
+
+    /// Whether `agent_id` was learned
+    pub fn contains(&self, agent_id: &str) -> bool {
+        self.keys.read().contains_key(agent_id)
+    }
+
+    /// Number of keys learned
+    pub fn len(&self) -> usize {
+        self.keys.read().len()
+    }
+
+    /// Whether no keys were learned
+    pub fn is_empty(&self) -> bool {
+        self.keys.read().is_empty()
+    }
+
+    /// Verify a signature by a learned agent
+    pub fn verify(
+        &self,
+        agent_id: &str,
+        data: &[u8],
+        signature: &[u8; SIGNATURE_LENGTH],
+    ) -> Result<bool, CryptoError> {
+        let pubkey = self.get(agent_id).ok_or_else(|| CryptoError::KeyNotFound(agent_id.to_string()))?;
+        verify_with_pubkey(&pubkey, data, signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn test_registry_learns_keys() {
+        let registry = PublicKeyRegistry::new();
+        let key = SigningKey::generate(&mut OsRng);
+        let pubkey = key.verifying_key().to_bytes();
+        let signature = key.sign(b"hello").to_bytes();
+
+        let agent_id = hex::encode(pubkey);
+        assert!(matches!(registry.verify(&agent_id, b"hello", &signature), Err(CryptoError::KeyNotFound(_))));
+
+        assert_eq!(registry.register(&pubkey).unwrap(), agent_id);
+        assert_eq!(registry.register(&pubkey).unwrap(), agent_id);
+        assert_eq!(registry.len(), 1);
+        assert!(registry.verify(&agent_id, b"hello", &signature).unwrap());
+        assert!(!registry.verify(&agent_id, b"goodbye", &signature).unwrap());
+    }
+}
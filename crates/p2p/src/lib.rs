@@ -1,4 +1,4 @@
-use chaoschain_core::{Block, Transaction, NetworkMessage};
+use chaoschain_core::{Block, BlockHeader, Transaction, NetworkMessage};
 use libp2p::{
     core::upgrade,
     gossipsub::{
@@ -283,6 +283,17 @@ impl ChainNetwork {
         Ok(())
     }
 
+    /// Announce a block header without its body, for light clients
+    pub async fn broadcast_header(&mut self, header: BlockHeader) -> Result<(), Box<dyn StdError>> {
+        let msg = NetworkMessage::NewBlockHeader(header);
+        let data = serde_json::to_vec(&msg)?;
+        self.swarm.behaviour_mut().gossipsub.publish(
+            self.topics.blocks.clone(),
+            data,
+        )?;
+        Ok(())
+    }
+
     pub async fn broadcast_transaction(&mut self, tx: Transaction) -> Result<(), Box<dyn StdError>> {
         let msg = NetworkMessage::NewTransaction(tx);
         let data = serde_json::to_vec(&msg)?;
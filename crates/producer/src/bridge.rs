@@ -84,7 +84,7 @@ impl BridgeParticle {
         // Submit to L1
         let tx = self
             .bridge
-            .submit_block(block.height.into(), state_root, producer)
+            .submit_block(block.header.height.into(), state_root, producer)
             .send()
             .await?;
 
@@ -104,7 +104,7 @@ impl Particle for BridgeParticle {
     ) -> Result<(), Self::Error> {
         match msg {
             BridgeMessage::SubmitBlock(block) => {
-                info!("Submitting block {} to L1", block.height);
+                info!("Submitting block {} to L1", block.header.height);
                 
                 match self.submit_block_to_l1(&block).await {
                     Ok(tx_hash) => {
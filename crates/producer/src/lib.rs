@@ -1,7 +1,7 @@
-use chaoschain_core::{Block, Transaction, Error as CoreError, NetworkEvent};
+use chaoschain_core::{Block, BlockBody, BlockHeader, Transaction, TxKind, Error as CoreError, NetworkEvent, ChainConfig, DEFAULT_CHAIN_ID};
 use chaoschain_state::{StateStore, StateError};
 use chaoschain_consensus::ConsensusManager;
-use chaoschain_crypto::{KeyManagerHandle, CryptoError};
+use chaoschain_crypto::{check_chain_id, KeyManagerHandle, CryptoError};
 use chaoschain_mempool::Mempool;
 use async_openai::{
     Client,
@@ -25,8 +25,6 @@ use std::time::Duration;
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::RwLock;
-use hex;
-use ed25519_dalek::VerifyingKey as PublicKey;
 use rand::prelude::SliceRandom;
 use rand::Rng;
 
@@ -132,6 +130,8 @@ pub struct ProducerStats {
 pub struct GenesisConfig {
     /// Chain name
     pub chain_name: String,
+    /// Chain identifier mixed into every signed payload
+    pub chain_id: String,
     /// Genesis timestamp
     pub timestamp: u64,
     /// Initial validators
@@ -148,13 +148,32 @@ pub struct GenesisConfig {
 pub struct ValidatorInfo {
     pub name: String,
     pub personality: String,
-    pub initial_stake: u64,
+}
+
+impl GenesisConfig {
+    /// Genesis settings for the chain described by a chain spec
+    pub fn from_chain_config(config: &ChainConfig) -> Self {
+        Self {
+            chain_name: config.name.clone(),
+            chain_id: config.chain_id.clone(),
+            validators: config
+                .validators
+                .iter()
+                .map(|validator| ValidatorInfo {
+                    name: validator.name.clone(),
+                    personality: validator.traits.first().cloned().unwrap_or_default(),
+                })
+                .collect(),
+            ..Self::default()
+        }
+    }
 }
 
 impl Default for GenesisConfig {
     fn default() -> Self {
         Self {
             chain_name: "ChaosChain".to_string(),
+            chain_id: DEFAULT_CHAIN_ID.to_string(),
             timestamp: SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .unwrap()
@@ -163,17 +182,14 @@ impl Default for GenesisConfig {
                 ValidatorInfo {
                     name: "DramaQueen".to_string(),
                     personality: "Dramatic".to_string(),
-                    initial_stake: 1000,
                 },
                 ValidatorInfo {
                     name: "ChaosMaster".to_string(),
                     personality: "Chaotic".to_string(),
-                    initial_stake: 1000,
                 },
                 ValidatorInfo {
                     name: "MemeOverlord".to_string(),
                     personality: "Memetic".to_string(),
-                    initial_stake: 1000,
                 },
             ],
             genesis_prompt: "In the beginning, there was order. But order was boring, \
@@ -272,6 +288,9 @@ impl Producer {
 
     /// Initialize chain with genesis block
     pub async fn initialize_genesis(&self, config: GenesisConfig) -> Result<Block, ProducerError> {
+        // Genesis must describe the chain the state store was configured for
+        check_chain_id(self.state_store.chain_id(), &config.chain_id)?;
+
         // Create genesis block
         let genesis_block = self.create_genesis_block(&config).await?;
         
@@ -284,20 +303,8 @@ impl Producer {
             let agent = self.key_manager.inner().generate_agent_keys(
                 validator.name.clone(),
                 "validator".to_string(),
-                validator.initial_stake,
             ).map_err(ProducerError::Crypto)?;
             
-            // Add validator as producer to state store
-            if let Ok(decoded) = hex::decode(&agent.id) {
-                if decoded.len() == 32 {
-                    let mut pubkey_bytes = [0u8; 32];
-                    pubkey_bytes.copy_from_slice(&decoded);
-                    if let Ok(pubkey) = PublicKey::from_bytes(&pubkey_bytes) {
-                        self.state_store.add_block_producer(pubkey);
-                    }
-                }
-            }
-            
             info!("Initialized validator: {} ({})", validator.name, agent.id);
         }
         
@@ -364,28 +371,38 @@ impl Producer {
 
         // Create genesis transaction
         let genesis_tx = Transaction {
+            chain_id: config.chain_id.clone(),
             sender: [0u8; 32], // Genesis sender is all zeros
             nonce: 0,
-            payload: genesis_interpretation.as_bytes().to_vec(),
+            payload: TxKind::ChatPost {
+                message: genesis_interpretation,
+                meme_url: None,
+            }.encode(),
             signature: [0u8; 64], // Genesis block doesn't need signatures
         };
 
         // Create genesis block
+        let body = BlockBody::new(vec![genesis_tx]);
         let block = Block {
-            height: 0,
-            parent_hash: [0u8; 32],
-            transactions: vec![genesis_tx],
+            header: BlockHeader {
+                chain_id: config.chain_id.clone(),
+                height: 1,
+                parent_hash: [0u8; 32],
+                tx_root: body.tx_root(),
+                receipts_root: [0u8; 32],
+                state_root: [0u8; 32],
+                innovation_level: config.initial_drama_level,
+                producer_strategy: "Default".to_string(),
+                producer_id: "genesis".to_string(),
+                drama_level: config.initial_drama_level,
+                producer_mood: config.chain_personality.clone(),
+                timestamp: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+            },
+            body,
             proposer_sig: [0u8; 64],
-            state_root: [0u8; 32],
-            drama_level: config.initial_drama_level,
-            producer_mood: config.chain_personality.clone(),
-            producer_id: "genesis".to_string(),
-            timestamp: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs(),
-            innovation_level: config.initial_drama_level,
-            producer_strategy: "Default".to_string(),
         };
 
         Ok(block)
@@ -513,31 +530,40 @@ impl Producer {
             state.pending_txs.clone()
         };
 
-        // Create block
-        let parent_hash = [0u8; 32]; // TODO: Get from state
-        let state_root = [0u8; 32]; // TODO: Calculate state root
+        // Create block on top of the canonical head
+        let (height, parent_hash) = self.state_store.next_block();
 
+        let body = BlockBody::new(transactions);
         let mut block = Block {
-            height: state.last_height + 1,
-            parent_hash,
-            transactions,
+            header: BlockHeader {
+                chain_id: self.state_store.chain_id().to_string(),
+                height,
+                parent_hash,
+                tx_root: body.tx_root(),
+                receipts_root: [0u8; 32],
+                state_root: [0u8; 32],
+                innovation_level: state.innovation_score,
+                producer_strategy: state.strategy.clone(),
+                producer_id: self.key_manager.get_agent_id().unwrap_or_default(),
+                drama_level: state.innovation_score,
+                producer_mood: "Chaotic".to_string(),
+                timestamp: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_err(|e| ProducerError::Internal(e.to_string()))?
+                    .as_secs(),
+            },
+            body,
             proposer_sig: [0u8; 64],
-            state_root,
-            innovation_level: state.innovation_score,
-            producer_strategy: state.strategy.clone(),
-            producer_id: self.key_manager.get_agent_id().unwrap_or_default(),
-            drama_level: state.innovation_score,
-            producer_mood: "Chaotic".to_string(),
-            timestamp: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .map_err(|e| ProducerError::Internal(e.to_string()))?
-                .as_secs(),
         };
 
+        // Commit to the receipts and state root executing the block leads to
+        self.state_store.seal_block(&mut block)
+            .map_err(ProducerError::State)?;
+
         // Sign block
         let signature = self.key_manager.inner().sign(
             &self.key_manager.get_agent_id().unwrap_or_default(),
-            &block.hash()
+            &block.signing_bytes()
         ).map_err(ProducerError::Crypto)?;
 
         block.proposer_sig = signature;
@@ -551,7 +577,7 @@ impl Producer {
         }
 
         // Update state
-        state.last_height += 1;
+        state.last_height = height;
         state.last_time = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .map_err(|e| ProducerError::Internal(e.to_string()))?
@@ -607,48 +633,45 @@ impl Producer {
         let producer_id = self.key_manager.get_agent_id()
             .ok_or_else(|| ProducerError::Internal("No producer key available".into()))?;
 
-        // Calculate state root
-        let state_root = self.state_store.state_root();
-
         // Generate block mood and drama
         let mut rng = rand::thread_rng();
         let drama_level = rng.gen_range(1..=10);
         let producer_mood = self.generate_producer_mood(&mut rng);
 
-        // Create block data for signing
-        let mut data_to_sign = Vec::new();
-        data_to_sign.extend_from_slice(&height.to_le_bytes());
-        data_to_sign.extend_from_slice(&parent_hash);
-        for tx in &transactions {
-            data_to_sign.extend_from_slice(&tx.hash());
-        }
-        data_to_sign.extend_from_slice(&state_root);
-        data_to_sign.extend_from_slice(&[drama_level]);
-        data_to_sign.extend_from_slice(producer_mood.as_bytes());
-
         let timestamp = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap_or_default()
             .as_secs();
-        data_to_sign.extend_from_slice(&timestamp.to_le_bytes());
+
+        let body = BlockBody::new(transactions);
+        let mut block = Block {
+            header: BlockHeader {
+                chain_id: self.state_store.chain_id().to_string(),
+                height,
+                parent_hash,
+                tx_root: body.tx_root(),
+                receipts_root: [0u8; 32],
+                state_root: [0u8; 32],
+                innovation_level: rng.gen_range(1..=10),
+                producer_strategy: "Chaotic".to_string(),
+                producer_id,
+                drama_level,
+                producer_mood,
+                timestamp,
+            },
+            body,
+            proposer_sig: [0u8; 64],
+        };
+
+        // Commit to the receipts and state root executing the block leads to
+        self.state_store.seal_block(&mut block)
+            .map_err(ProducerError::State)?;
 
         // Sign the block
-        let signature = self.key_manager.inner().sign(&producer_id, &data_to_sign)
+        block.proposer_sig = self.key_manager.inner().sign(&block.header.producer_id, &block.signing_bytes())
             .map_err(|e| ProducerError::Internal(format!("Failed to sign block: {}", e)))?;
 
-        Ok(Block {
-            height,
-            parent_hash,
-            transactions,
-            proposer_sig: signature,
-            state_root,
-            innovation_level: rng.gen_range(1..=10),
-            producer_strategy: "Chaotic".to_string(),
-            producer_id,
-            drama_level,
-            producer_mood,
-            timestamp,
-        })
+        Ok(block)
     }
 }
 
@@ -670,7 +693,7 @@ mod tests {
         let state_store = Arc::new(StateStoreImpl::new(
             ChainConfig::default(),
             key_manager.clone(),
-        ));
+        )?);
         let consensus = Arc::new(ConsensusManager::new(
             3000, // total stake
             0.67, // 67% threshold
@@ -704,27 +727,28 @@ mod tests {
         let genesis_block = producer.initialize_genesis(genesis_config.clone()).await?;
         
         // Verify genesis block
-        assert_eq!(genesis_block.height, 0);
-        assert_eq!(genesis_block.parent_hash, [0u8; 32]);
-        assert_eq!(genesis_block.transactions.len(), 1);
-        assert_eq!(genesis_block.drama_level, genesis_config.initial_drama_level);
+        assert_eq!(genesis_block.header.height, 1);
+        assert_eq!(genesis_block.header.parent_hash, [0u8; 32]);
+        assert_eq!(genesis_block.body.transactions.len(), 1);
+        assert_eq!(genesis_block.header.drama_level, genesis_config.initial_drama_level);
 
         // Create a test transaction
         let test_agent = key_manager.inner().generate_agent_keys(
             "TestUser".to_string(),
             "user".to_string(),
-            1000,
         )?;
 
-        let test_payload = b"This is a dramatic test transaction!";
-        let signature = key_manager.inner().sign(&test_agent.id, test_payload)?;
-
-        let tx = Transaction {
+        let mut tx = Transaction {
+            chain_id: state_store.chain_id().to_string(),
             sender: hex::decode(&test_agent.id)?.try_into().unwrap(),
             nonce: 0,
-            payload: test_payload.to_vec(),
-            signature,
+            payload: TxKind::ChatPost {
+                message: "This is a dramatic test transaction!".to_string(),
+                meme_url: None,
+            }.encode(),
+            signature: [0u8; 64],
         };
+        tx.signature = key_manager.inner().sign(&test_agent.id, &tx.signing_bytes())?;
 
         // Add transaction to producer
         producer.add_transaction(tx.clone()).await;
@@ -734,7 +758,7 @@ mod tests {
 
         // Verify block was produced
         let state = producer.state.read().await;
-        assert_eq!(state.last_height, 1);
+        assert_eq!(state.last_height, 2);
         assert!(state.innovation_score <= 100);
         assert!(!state.strategy.is_empty());
 
@@ -132,7 +132,7 @@ impl Particle for NetworkParticle {
         // Handle incoming network events
         match msg {
             NetworkMessage::NewBlock(block) => {
-                info!("Broadcasting new block at height {}", block.height);
+                info!("Broadcasting new block at height {}", block.header.height);
             }
             NetworkMessage::NewTransaction(tx) => {
                 info!("Broadcasting new transaction");
@@ -0,0 +1,128 @@
+//! Compares the sparse merkle tree with the sort-and-rehash tree it replaced.
+//!
+//! Run with `cargo bench -p chaoschain-state --bench merkle`. Each round
+//! simulates a block that touches a handful of keys and then reads the root,
+//! which is what `apply_block` does.
+
+use chaoschain_state::merkle::MerkleTree;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const STATE_SIZES: [usize; 3] = [1_000, 10_000, 50_000];
+const KEYS_PER_BLOCK: usize = 20;
+const BLOCKS: usize = 20;
+
+/// The previous implementation: every root rebuilds the tree from sorted leaves
+#[derive(Default)]
+struct RehashTree {
+    leaves: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+impl RehashTree {
+    fn insert(&mut self, key: &[u8], value: &[u8]) {
+        self.leaves.insert(key.to_vec(), value.to_vec());
+    }
+
+    fn root_hash(&self) -> [u8; 32] {
+        let mut sorted: Vec<_> = self.leaves.iter().collect();
+        sorted.sort_by_key(|(key, _)| *key);
+
+        let mut level: Vec<[u8; 32]> = sorted
+            .iter()
+            .map(|(key, value)| {
+                let mut hasher = Sha256::new();
+                hasher.update(b"leaf");
+                hasher.update(key);
+                hasher.update(value);
+                hasher.finalize().into()
+            })
+            .collect();
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|chunk| match chunk {
+                    [left, right] => {
+                        let mut hasher = Sha256::new();
+                        hasher.update(b"node");
+                        hasher.update(left);
+                        hasher.update(right);
+                        hasher.finalize().into()
+                    }
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+        }
+        level.first().copied().unwrap_or([0u8; 32])
+    }
+}
+
+fn key(i: usize) -> Vec<u8> {
+    format!("balance:{:08}", i).into_bytes()
+}
+
+fn per_block(total: Duration) -> Duration {
+    total / BLOCKS as u32
+}
+
+fn bench_sparse(size: usize) -> Duration {
+    let mut tree = MerkleTree::new();
+    for i in 0..size {
+        tree.insert(&key(i), &i.to_le_bytes());
+    }
+
+    let start = Instant::now();
+    for block in 0..BLOCKS {
+        for i in 0..KEYS_PER_BLOCK {
+            tree.insert(&key((block * 7919 + i * 104_729) % size), &block.to_le_bytes());
+        }
+        std::hint::black_box(tree.root_hash());
+    }
+    per_block(start.elapsed())
+}
+
+fn bench_rehash(size: usize) -> Duration {
+    let mut tree = RehashTree::default();
+    for i in 0..size {
+        tree.insert(&key(i), &i.to_le_bytes());
+    }
+
+    let start = Instant::now();
+    for block in 0..BLOCKS {
+        for i in 0..KEYS_PER_BLOCK {
+            tree.insert(&key((block * 7919 + i * 104_729) % size), &block.to_le_bytes());
+        }
+        std::hint::black_box(tree.root_hash());
+    }
+    per_block(start.elapsed())
+}
+
+fn bench_proofs(size: usize) -> Duration {
+    let mut tree = MerkleTree::new();
+    for i in 0..size {
+        tree.insert(&key(i), &i.to_le_bytes());
+    }
+
+    let start = Instant::now();
+    for i in 0..BLOCKS {
+        std::hint::black_box(tree.generate_proof(&key(i * 31 % size)));
+    }
+    per_block(start.elapsed())
+}
+
+fn main() {
+    println!(
+        "{:>8} {:>16} {:>16} {:>16}",
+        "keys", "rehash/block", "sparse/block", "sparse/proof"
+    );
+    for size in STATE_SIZES {
+        println!(
+            "{:>8} {:>16?} {:>16?} {:>16?}",
+            size,
+            bench_rehash(size),
+            bench_sparse(size),
+            bench_proofs(size)
+        );
+    }
+}
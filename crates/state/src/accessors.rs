@@ -0,0 +1,229 @@
+//! Typed reads over the raw key/value state.
+//!
+//! Every state key is built here, so readers holding `Arc<dyn StateStore>`
+//! and the block executor agree on the layout without sharing string
+//! formats by hand.
+
+use crate::{Account, AgentRecord, Receipt, Stake, StateError, StateStore, Supply};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Prefix of account keys
+pub const ACCOUNT_PREFIX: &str = "account:";
+/// Prefix of committed block keys
+pub const BLOCK_PREFIX: &str = "block:";
+/// Prefix of block producer registration keys
+pub const PRODUCER_PREFIX: &str = "producer:";
+/// Prefix of block reward keys
+pub const REWARD_PREFIX: &str = "reward:";
+/// Prefix of transaction receipt keys
+pub const RECEIPT_PREFIX: &str = "receipt:";
+/// Prefix of token supply counter keys
+pub const SUPPLY_PREFIX: &str = "supply:";
+/// Prefix of stake keys
+pub const STAKE_PREFIX: &str = "stake:";
+/// Prefix of agent registration keys
+pub const AGENT_PREFIX: &str = "agent:";
+
+/// Key of the account owned by `account_id`, normally a hex public key
+pub fn account_key(account_id: &str) -> Vec<u8> {
+    format!("{}{}", ACCOUNT_PREFIX, account_id).into_bytes()
+}
+
+/// Key of the canonical block committed at `height`
+pub fn block_key(height: u64) -> Vec<u8> {
+    format!("{}{}", BLOCK_PREFIX, height).into_bytes()
+}
+
+/// Key marking `producer_id` as a registered block producer
+pub fn producer_key(producer_id: &str) -> Vec<u8> {
+    format!("{}{}", PRODUCER_PREFIX, producer_id).into_bytes()
+}
+
+/// Key of the reward paid to `producer_id` for the block at `height`
+pub fn reward_key(height: u64, producer_id: &str) -> Vec<u8> {
+    format!("{}{}:{}", REWARD_PREFIX, height, producer_id).into_bytes()
+}
+
+/// Key of the receipt for the transaction hashing to `tx_hash`
+pub fn receipt_key(tx_hash: &[u8; 32]) -> Vec<u8> {
+    format!("{}{}", RECEIPT_PREFIX, hex::encode(tx_hash)).into_bytes()
+}
+
+/// Key of the token supply counter named `counter`
+pub fn supply_key(counter: &str) -> Vec<u8> {
+    format!("{}{}", SUPPLY_PREFIX, counter).into_bytes()
+}
+
+/// Key of the stake held by `account_id`
+pub fn stake_key(account_id: &str) -> Vec<u8> {
+    format!("{}{}", STAKE_PREFIX, account_id).into_bytes()
+}
+
+/// Key of the registration of `agent_id`, a hex public key
+pub fn agent_key(agent_id: &str) -> Vec<u8> {
+    format!("{}{}", AGENT_PREFIX, agent_id).into_bytes()
+}
+
+/// Split a reward key back into its height and producer
+fn parse_reward_key(key: &[u8]) -> Option<(u64, String)> {
+    let rest = std::str::from_utf8(key).ok()?.strip_prefix(REWARD_PREFIX)?;
+    let (height, producer_id) = rest.split_once(':')?;
+    Some((height.parse().ok()?, producer_id.to_string()))
+}
+
+/// Breakdown of a block reward as stored in state
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RewardRecord {
+    pub base: u64,
+    pub drama_bonus: u64,
+    pub innovation_bonus: u64,
+    pub chaos_bonus: u64,
+    pub total: u64,
+    pub drama_level: u8,
+}
+
+/// A reward together with the block and producer it was paid for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockReward {
+    pub height: u64,
+    pub producer_id: String,
+    pub reward: RewardRecord,
+}
+
+fn decode_json<T: DeserializeOwned>(key: &[u8], value: &[u8]) -> Result<T, StateError> {
+    serde_json::from_slice(value).map_err(|e| {
+        StateError::CorruptValue(format!("{}: {}", String::from_utf8_lossy(key), e))
+    })
+}
+
+fn decode_reward(key: &[u8], value: &[u8]) -> Result<BlockReward, StateError> {
+    let (height, producer_id) = parse_reward_key(key)
+        .ok_or_else(|| StateError::CorruptValue(String::from_utf8_lossy(key).into_owned()))?;
+    Ok(BlockReward {
+        height,
+        producer_id,
+        reward: decode_json(key, value)?,
+    })
+}
+
+/// Typed accessors available on every state store, including `dyn StateStore`
+pub trait StateStoreExt: StateStore {
+    /// Read a JSON encoded value
+    fn get_json<T: DeserializeOwned>(&self, key: &[u8]) -> Result<Option<T>, StateError> {
+        self.get(key)?
+            .map(|value| decode_json(key, &value))
+            .transpose()
+    }
+
+    /// Account owned by `account_id`, or an empty one if it was never touched
+    fn account(&self, account_id: &str) -> Result<Account, StateError> {
+        self.get(&account_key(account_id))?
+            .map(|value| Account::decode(&value))
+            .transpose()
+            .map(Option::unwrap_or_default)
+    }
+
+    /// Whether `producer_id` is registered as a block producer
+    fn is_producer(&self, producer_id: &str) -> Result<bool, StateError> {
+        Ok(self.get(&producer_key(producer_id))?.is_some())
+    }
+
+    /// IDs of all registered block producers, in key order
+    fn producers(&self) -> Result<Vec<String>, StateError> {
+        Ok(self
+            .scan_prefix(PRODUCER_PREFIX.as_bytes())?
+            .into_iter()
+            .map(|(key, _)| String::from_utf8_lossy(&key[PRODUCER_PREFIX.len()..]).into_owned())
+            .collect())
+    }
+
+    /// Reward paid to `producer_id` for the block at `height`
+    fn reward(&self, height: u64, producer_id: &str) -> Result<Option<RewardRecord>, StateError> {
+        self.get_json(&reward_key(height, producer_id))
+    }
+
+    /// Every reward paid for the block at `height`
+    fn rewards_at(&self, height: u64) -> Result<Vec<BlockReward>, StateError> {
+        let prefix = format!("{}{}:", REWARD_PREFIX, height);
+        self.scan_prefix(prefix.as_bytes())?
+            .iter()
+            .map(|(key, value)| decode_reward(key, value))
+            .collect()
+    }
+
+    /// Receipt of an applied transaction
+    fn receipt(&self, tx_hash: &[u8; 32]) -> Result<Option<Receipt>, StateError> {
+        self.get_json(&receipt_key(tx_hash))
+    }
+
+    /// Total token supply and how much came from each source
+    fn supply(&self) -> Result<Supply, StateError> {
+        Supply::read(|key| self.get(key))
+    }
+
+    /// Stake held by `account_id`, or none if it never bonded
+    fn stake(&self, account_id: &str) -> Result<Stake, StateError> {
+        self.get(&stake_key(account_id))?
+            .map(|value| Stake::decode(&value))
+            .transpose()
+            .map(Option::unwrap_or_default)
+    }
+
+    /// Every account holding stake, in key order
+    fn stakes(&self) -> Result<Vec<(String, Stake)>, StateError> {
+        self.scan_prefix(STAKE_PREFIX.as_bytes())?
+            .into_iter()
+            .map(|(key, value)| {
+                let account_id = String::from_utf8_lossy(&key[STAKE_PREFIX.len()..]).into_owned();
+                Ok((account_id, Stake::decode(&value)?))
+            })
+            .collect()
+    }
+
+    /// Registration of `agent_id`, if it registered on chain
+    fn agent(&self, agent_id: &str) -> Result<Option<AgentRecord>, StateError> {
+        self.get(&agent_key(agent_id))?
+            .map(|value| AgentRecord::decode(&value))
+            .transpose()
+    }
+
+    /// Every agent registered on chain, in key order
+    fn agents(&self) -> Result<Vec<(String, AgentRecord)>, StateError> {
+        self.scan_prefix(AGENT_PREFIX.as_bytes())?
+            .into_iter()
+            .map(|(key, value)| {
+                let agent_id = String::from_utf8_lossy(&key[AGENT_PREFIX.len()..]).into_owned();
+                Ok((agent_id, AgentRecord::decode(&value)?))
+            })
+            .collect()
+    }
+
+    /// Every reward paid to `producer_id`, lowest height first
+    fn rewards_for(&self, producer_id: &str) -> Result<Vec<BlockReward>, StateError> {
+        let mut rewards = Vec::new();
+        for (key, value) in self.scan_prefix(REWARD_PREFIX.as_bytes())? {
+            if parse_reward_key(&key).is_some_and(|(_, id)| id == producer_id) {
+                rewards.push(decode_reward(&key, &value)?);
+            }
+        }
+        rewards.sort_by_key(|reward| reward.height);
+        Ok(rewards)
+    }
+}
+
+impl<S: StateStore + ?Sized> StateStoreExt for S {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reward_key_roundtrip() {
+        let key = reward_key(120, "abcd");
+        assert_eq!(key, b"reward:120:abcd".to_vec());
+        assert_eq!(parse_reward_key(&key), Some((120, "abcd".to_string())));
+        assert_eq!(parse_reward_key(b"reward:x:abcd"), None);
+        assert_eq!(parse_reward_key(&producer_key("abcd")), None);
+    }
+}
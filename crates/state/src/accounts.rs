@@ -0,0 +1,320 @@
+//! Account balances and nonces held in the state tree.
+//!
+//! Block execution stages every account and stake change in an
+//! `AccountChanges` set and only writes it back once the whole block has
+//! executed, so a bad nonce or an overdraft leaves state untouched.
+
+use crate::merkle::MerkleTree;
+use crate::staking::{read_stake, Stake};
+use crate::{account_key, StateError, StateOp, TxStatus, STAKE_PREFIX};
+use chaoschain_core::encoding::{Decoder, Encoder};
+use chaoschain_core::{Transaction, TxKind};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Balance and transaction count of one account
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Account {
+    /// Spendable balance
+    pub balance: u64,
+    /// Nonce the account's next transaction must carry
+    pub nonce: u64,
+}
+
+impl Account {
+    /// Canonical bytes stored in the state tree
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        encoder.put_u64(self.balance).put_u64(self.nonce);
+        encoder.finish()
+    }
+
+    /// Decode an account read from the state tree
+    pub fn decode(bytes: &[u8]) -> Result<Self, StateError> {
+        let mut decoder = Decoder::new(bytes)?;
+        let account = Self {
+            balance: decoder.take_u64()?,
+            nonce: decoder.take_u64()?,
+        };
+        decoder.finish()?;
+        Ok(account)
+    }
+}
+
+/// Read an account from the tree, defaulting to an empty one
+pub(crate) fn read_account(tree: &MerkleTree, account_id: &str) -> Result<Account, StateError> {
+    tree.get(&account_key(account_id))
+        .map(|bytes| Account::decode(&bytes))
+        .transpose()
+        .map(Option::unwrap_or_default)
+}
+
+/// Write accounts back to the tree
+pub(crate) fn write_accounts(tree: &mut MerkleTree, accounts: BTreeMap<String, Account>) {
+    for (account_id, account) in accounts {
+        tree.insert(&account_key(&account_id), &account.encode());
+    }
+}
+
+/// Ops writing accounts back to the tree
+pub(crate) fn account_ops(accounts: BTreeMap<String, Account>) -> impl Iterator<Item = StateOp> {
+    accounts.into_iter().map(|(account_id, account)| StateOp::Set {
+        key: account_key(&account_id),
+        value: account.encode(),
+    })
+}
+
+/// Account and stake updates staged on top of the tree
+pub(crate) struct AccountChanges<'a> {
+    tree: &'a MerkleTree,
+    touched: BTreeMap<String, Account>,
+    stakes: BTreeMap<String, Stake>,
+}
+
+impl<'a> AccountChanges<'a> {
+    pub fn new(tree: &'a MerkleTree) -> Self {
+        Self {
+            tree,
+            touched: BTreeMap::new(),
+            stakes: BTreeMap::new(),
+        }
+    }
+
+    fn account(&mut self, account_id: &str) -> Result<&mut Account, StateError> {
+        if !self.touched.contains_key(account_id) {
+            let account = read_account(self.tree, account_id)?;
+            self.touched.insert(account_id.to_string(), account);
+        }
+        Ok(self.touched.get_mut(account_id).expect("account was just staged"))
+    }
+
+    fn stake(&mut self, account_id: &str) -> Result<&mut Stake, StateError> {
+        if !self.stakes.contains_key(account_id) {
+            let stake = read_stake(self.tree, account_id)?;
+            self.stakes.insert(account_id.to_string(), stake);
+        }
+        Ok(self.stakes.get_mut(account_id).expect("stake was just staged"))
+    }
+
+    /// Add `amount` to an account
+    pub fn credit(&mut self, account_id: &str, amount: u64) -> Result<(), StateError> {
+        let account = self.account(account_id)?;
+        account.balance = account
+            .balance
+            .checked_add(amount)
+            .ok_or_else(|| StateError::BalanceOverflow(account_id.to_string()))?;
+        Ok(())
+    }
+
+    /// Take `amount` from an account
+    pub fn debit(&mut self, account_id: &str, amount: u64) -> Result<(), StateError> {
+        let account = self.account(account_id)?;
+        let balance = account.balance;
+        account.balance = balance
+            .checked_sub(amount)
+            .ok_or_else(|| StateError::InsufficientBalance {
+                account: account_id.to_string(),
+                balance,
+                amount,
+            })?;
+        Ok(())
+    }
+
+    /// Move `amount` between accounts, or nothing at all if either side cannot take it
+    fn transfer(&mut self, from: &str, to: &str, amount: u64) -> Result<(), StateError> {
+        self.debit(from, amount)?;
+        if let Err(e) = self.credit(to, amount) {
+            self.credit(from, amount)?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Lock `amount` of an account's balance up as bonded stake
+    fn bond(&mut self, account_id: &str, amount: u64) -> Result<(), StateError> {
+        self.debit(account_id, amount)?;
+        if let Err(e) = self.stake(account_id)?.bond(account_id, amount) {
+            self.credit(account_id, amount)?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Hand back every unbonding entry due by `height` to its account's balance
+    pub fn release_unbonded(&mut self, height: u64) -> Result<(), StateError> {
+        for (key, value) in self.tree.scan_prefix(STAKE_PREFIX.as_bytes()) {
+            let due = Stake::decode(&value)?
+                .unbonding
+                .iter()
+                .any(|entry| entry.release_height <= height);
+            if due {
+                let account_id = String::from_utf8_lossy(&key[STAKE_PREFIX.len()..]).into_owned();
+                let released = self.stake(&account_id)?.release(height);
+                self.credit(&account_id, released)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Burn up to `amount` of an account's locked stake, returning how much was burned
+    pub fn slash(&mut self, account_id: &str, amount: u64) -> Result<u64, StateError> {
+        Ok(self.stake(account_id)?.slash(amount))
+    }
+
+    /// Check the sender's nonce and apply the transaction's balance and stake changes
+    ///
+    /// A bad nonce invalidates the whole block. A transfer or bond the sender
+    /// cannot cover, or an unbond beyond its bonded stake, only fails the
+    /// transaction: it moves nothing but still uses up the nonce. Unbonded
+    /// stake is released by the block at `release_height`.
+    pub fn apply_transaction(&mut self, tx: &Transaction, release_height: u64) -> Result<TxStatus, StateError> {
+        let sender = hex::encode(tx.sender);
+        let expected = self.account(&sender)?.nonce;
+        if tx.nonce != expected {
+            return Err(StateError::InvalidNonce {
+                account: sender,
+                expected,
+                got: tx.nonce,
+            });
+        }
+
+        let outcome = match tx.kind()? {
+            TxKind::Transfer { to, amount } => self.transfer(&sender, &hex::encode(to), amount),
+            TxKind::Stake { amount } => self.bond(&sender, amount),
+            TxKind::Unstake { amount } => self.stake(&sender)?.unbond(&sender, amount, release_height),
+            _ => Ok(()),
+        };
+        let status = match outcome {
+            Ok(()) => TxStatus::Success,
+            Err(
+                e @ (StateError::InsufficientBalance { .. }
+                | StateError::InsufficientStake { .. }
+                | StateError::BalanceOverflow(_)),
+            ) => TxStatus::Failed { reason: e.to_string() },
+            Err(e) => return Err(e),
+        };
+
+        self.account(&sender)?.nonce += 1;
+        Ok(status)
+    }
+
+    /// Accounts and stakes changed so far
+    pub fn into_changes(self) -> (BTreeMap<String, Account>, BTreeMap<String, Stake>) {
+        (self.touched, self.stakes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx(sender: [u8; 32], nonce: u64, kind: TxKind) -> Transaction {
+        Transaction {
+            chain_id: "test".to_string(),
+            sender,
+            nonce,
+            payload: kind.encode(),
+            signature: [0u8; 64],
+        }
+    }
+
+    fn transfer(sender: [u8; 32], nonce: u64, to: [u8; 32], amount: u64) -> Transaction {
+        tx(sender, nonce, TxKind::Transfer { to, amount })
+    }
+
+    #[test]
+    fn test_account_encoding_roundtrip() {
+        let account = Account { balance: 1234, nonce: 7 };
+        assert_eq!(Account::decode(&account.encode()).unwrap(), account);
+        assert!(Account::decode(&account.encode()[..9]).is_err());
+    }
+
+    #[test]
+    fn test_transfers_and_nonces() {
+        let (alice, bob) = ([1u8; 32], [2u8; 32]);
+        let mut tree = MerkleTree::new();
+        write_accounts(
+            &mut tree,
+            BTreeMap::from([(hex::encode(alice), Account { balance: 100, nonce: 0 })]),
+        );
+
+        let mut changes = AccountChanges::new(&tree);
+        assert_eq!(changes.apply_transaction(&transfer(alice, 0, bob, 60), 0).unwrap(), TxStatus::Success);
+        assert!(matches!(
+            changes.apply_transaction(&transfer(alice, 0, bob, 1), 0),
+            Err(StateError::InvalidNonce { expected: 1, got: 0, .. })
+        ));
+
+        // An overdraft fails on its own, using up the nonce but moving nothing
+        assert!(matches!(
+            changes.apply_transaction(&transfer(alice, 1, bob, 41), 0).unwrap(),
+            TxStatus::Failed { .. }
+        ));
+        let (changed, _) = changes.into_changes();
+
+        // Nothing reaches the tree until the changes are written
+        assert_eq!(read_account(&tree, &hex::encode(bob)).unwrap(), Account::default());
+        write_accounts(&mut tree, changed);
+        assert_eq!(
+            read_account(&tree, &hex::encode(alice)).unwrap(),
+            Account { balance: 40, nonce: 2 }
+        );
+        assert_eq!(read_account(&tree, &hex::encode(bob)).unwrap().balance, 60);
+    }
+
+    #[test]
+    fn test_bonding_unbonding_and_release() {
+        let alice = [1u8; 32];
+        let id = hex::encode(alice);
+        let mut tree = MerkleTree::new();
+        write_accounts(&mut tree, BTreeMap::from([(id.clone(), Account { balance: 100, nonce: 0 })]));
+
+        let mut changes = AccountChanges::new(&tree);
+        assert_eq!(
+            changes.apply_transaction(&tx(alice, 0, TxKind::Stake { amount: 80 }), 5).unwrap(),
+            TxStatus::Success
+        );
+        assert_eq!(
+            changes.apply_transaction(&tx(alice, 1, TxKind::Unstake { amount: 30 }), 5).unwrap(),
+            TxStatus::Success
+        );
+
+        // Bonding past the balance or unbonding past the bond fails on its own
+        for (nonce, kind) in [(2, TxKind::Stake { amount: 21 }), (3, TxKind::Unstake { amount: 51 })] {
+            assert!(matches!(
+                changes.apply_transaction(&tx(alice, nonce, kind), 5).unwrap(),
+                TxStatus::Failed { .. }
+            ));
+        }
+        let (accounts, stakes) = changes.into_changes();
+        write_accounts(&mut tree, accounts);
+        for op in crate::staking::stake_ops(stakes) {
+            if let StateOp::Set { key, value } = op {
+                tree.insert(&key, &value);
+            }
+        }
+        assert_eq!(read_account(&tree, &id).unwrap(), Account { balance: 20, nonce: 4 });
+        assert_eq!(read_stake(&tree, &id).unwrap().bonded, 50);
+
+        // Unbonded stake comes back only at its release height
+        let mut changes = AccountChanges::new(&tree);
+        changes.release_unbonded(4).unwrap();
+        assert!(changes.into_changes().0.is_empty());
+        let mut changes = AccountChanges::new(&tree);
+        changes.release_unbonded(5).unwrap();
+        let (accounts, stakes) = changes.into_changes();
+        assert_eq!(accounts[&id].balance, 50);
+        assert_eq!(stakes[&id], Stake { bonded: 50, unbonding: Vec::new() });
+    }
+
+    #[test]
+    fn test_credit_overflow() {
+        let tree = MerkleTree::new();
+        let mut changes = AccountChanges::new(&tree);
+        changes.credit("rich", u64::MAX).unwrap();
+        assert!(matches!(
+            changes.credit("rich", 1),
+            Err(StateError::BalanceOverflow(_))
+        ));
+    }
+}
@@ -0,0 +1,74 @@
+//! Agents registered on chain.
+//!
+//! A `RegisterAgent` transaction records its sender's public key under a
+//! name and role. Agent IDs are hex public keys, so the record is all a node
+//! needs to check what an agent signs, whether or not it holds its secret.
+
+use crate::StateError;
+use chaoschain_core::encoding::{Decoder, Encoder};
+use chaoschain_crypto::KeyManagerHandle;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// What an agent registered itself as
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentRecord {
+    /// Agent name
+    pub name: String,
+    /// Agent role
+    pub role: String,
+    /// Height of the block that last registered it
+    pub registered_at: u64,
+}
+
+impl AgentRecord {
+    /// Canonical bytes kept under the agent's registration key
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        encoder.put_str(&self.name).put_str(&self.role).put_u64(self.registered_at);
+        encoder.finish()
+    }
+
+    /// Decode a registration, rejecting trailing data
+    pub fn decode(bytes: &[u8]) -> Result<Self, StateError> {
+        let mut decoder = Decoder::new(bytes)?;
+        let record = Self {
+            name: decoder.take_str()?,
+            role: decoder.take_str()?,
+            registered_at: decoder.take_u64()?,
+        };
+        decoder.finish()?;
+        Ok(record)
+    }
+}
+
+/// Teach `key_manager` the public key of every agent ID in `agent_ids`
+///
+/// A learned key is never forgotten, not even when its registration is
+/// rolled back: the ID is the key, so it cannot go stale.
+pub(crate) fn learn_keys<'a>(key_manager: &KeyManagerHandle, agent_ids: impl IntoIterator<Item = &'a str>) {
+    let registry = key_manager.inner().registry();
+    for agent_id in agent_ids {
+        let pubkey = hex::decode(agent_id).ok().and_then(|bytes| <[u8; 32]>::try_from(bytes).ok());
+        match pubkey.map(|pubkey| registry.register(&pubkey)) {
+            Some(Ok(_)) => {}
+            _ => warn!("Registered agent {} is not a valid public key", agent_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_agent_record_roundtrip() {
+        let record = AgentRecord {
+            name: "DramaQueen".to_string(),
+            role: "validator".to_string(),
+            registered_at: 7,
+        };
+        assert_eq!(AgentRecord::decode(&record.encode()).unwrap(), record);
+        assert!(AgentRecord::decode(&record.encode()[..10]).is_err());
+    }
+}
@@ -0,0 +1,229 @@
+//! Index of every known block by hash and by height.
+//!
+//! Applied blocks form the canonical chain. Proposals that lost out at the
+//! same height stay in the index too, and parent links between all of them
+//! make up the fork tree.
+
+use chaoschain_core::Block;
+use std::collections::HashMap;
+
+/// Block store with constant time lookups and a record of competing blocks
+#[derive(Debug, Default)]
+pub struct BlockIndex {
+    /// Every known block by hash
+    blocks: HashMap<[u8; 32], Block>,
+    /// Hashes of every known block at a height, in arrival order
+    by_height: HashMap<u64, Vec<[u8; 32]>>,
+    /// Hashes of the known children of a block
+    children: HashMap<[u8; 32], Vec<[u8; 32]>>,
+    /// Canonical block hash at each height
+    canonical: HashMap<u64, [u8; 32]>,
+    /// Canonical blocks in the order they were applied
+    chain: Vec<[u8; 32]>,
+}
+
+impl BlockIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a block without making it canonical, returning false if it was already known
+    pub fn insert(&mut self, block: Block) -> bool {
+        let hash = block.hash();
+        if self.blocks.contains_key(&hash) {
+            return false;
+        }
+        self.by_height.entry(block.header.height).or_default().push(hash);
+        self.children.entry(block.header.parent_hash).or_default().push(hash);
+        self.blocks.insert(hash, block);
+        true
+    }
+
+    /// Record a block and append it to the canonical chain
+    pub fn push_canonical(&mut self, block: Block) {
+        let hash = block.hash();
+        self.canonical.insert(block.header.height, hash);
+        self.chain.push(hash);
+        self.insert(block);
+    }
+
+    /// Drop the canonical head, keeping the block known as a competitor
+    pub fn pop_canonical(&mut self) -> Option<Block> {
+        let hash = self.chain.pop()?;
+        let block = self.blocks.get(&hash)?.clone();
+        let height = block.header.height;
+        if self.canonical.get(&height) == Some(&hash) {
+            // An earlier canonical block at the same height becomes visible again
+            match self.chain.iter().rev().find(|h| self.blocks[*h].header.height == height) {
+                Some(previous) => self.canonical.insert(height, *previous),
+                None => self.canonical.remove(&height),
+            };
+        }
+        Some(block)
+    }
+
+    /// Block with the given hash, canonical or not
+    pub fn get(&self, hash: &[u8; 32]) -> Option<&Block> {
+        self.blocks.get(hash)
+    }
+
+    /// Canonical block at `height`
+    pub fn get_by_height(&self, height: u64) -> Option<&Block> {
+        self.canonical.get(&height).and_then(|hash| self.blocks.get(hash))
+    }
+
+    /// Every known block at `height`, canonical or competing, in arrival order
+    pub fn at_height(&self, height: u64) -> Vec<&Block> {
+        self.by_height
+            .get(&height)
+            .map(|hashes| hashes.iter().filter_map(|hash| self.blocks.get(hash)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Whether the block with this hash is on the canonical chain
+    pub fn is_canonical(&self, hash: &[u8; 32]) -> bool {
+        self.blocks
+            .get(hash)
+            .is_some_and(|block| self.canonical.get(&block.header.height) == Some(hash))
+    }
+
+    /// Parent of a known block, if it is known too
+    pub fn parent(&self, hash: &[u8; 32]) -> Option<&Block> {
+        let block = self.blocks.get(hash)?;
+        self.blocks.get(&block.header.parent_hash)
+    }
+
+    /// Known children of a block
+    pub fn children(&self, hash: &[u8; 32]) -> Vec<&Block> {
+        self.children
+            .get(hash)
+            .map(|hashes| hashes.iter().filter_map(|hash| self.blocks.get(hash)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Most recently applied canonical block
+    pub fn head(&self) -> Option<&Block> {
+        self.chain.last().and_then(|hash| self.blocks.get(hash))
+    }
+
+    /// Height of the canonical head, or zero before any block is applied
+    pub fn height(&self) -> u64 {
+        self.head().map(|block| block.header.height).unwrap_or(0)
+    }
+
+    /// Height and parent hash of a block extending the canonical head
+    pub fn next_block(&self) -> (u64, [u8; 32]) {
+        match self.head() {
+            Some(head) => (head.header.height + 1, head.hash()),
+            None => (1, [0u8; 32]),
+        }
+    }
+
+    /// Number of blocks applied to reach the current head
+    pub fn canonical_len(&self) -> usize {
+        self.chain.len()
+    }
+
+    /// Canonical blocks in the order they were applied
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Block> {
+        self.chain.iter().filter_map(|hash| self.blocks.get(hash))
+    }
+
+    /// The latest `n` canonical blocks, newest first
+    pub fn latest(&self, n: usize) -> Vec<Block> {
+        self.iter().rev().take(n).cloned().collect()
+    }
+
+    /// Forget every block at or below `height`, canonical or not
+    pub fn prune_to(&mut self, height: u64) {
+        self.blocks.retain(|_, block| block.header.height > height);
+        self.by_height.retain(|h, _| *h > height);
+        self.canonical.retain(|h, _| *h > height);
+        let blocks = &self.blocks;
+        self.chain.retain(|hash| blocks.contains_key(hash));
+        self.children.retain(|_, children| {
+            children.retain(|hash| blocks.contains_key(hash));
+            !children.is_empty()
+        });
+    }
+
+    /// Forget everything
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chaoschain_core::{BlockBody, BlockHeader, DEFAULT_CHAIN_ID};
+
+    fn block(height: u64, parent_hash: [u8; 32], producer_id: &str) -> Block {
+        let body = BlockBody::new(vec![]);
+        Block {
+            header: BlockHeader {
+                chain_id: DEFAULT_CHAIN_ID.to_string(),
+                height,
+                parent_hash,
+                tx_root: body.tx_root(),
+                receipts_root: [0u8; 32],
+                state_root: [0u8; 32],
+                innovation_level: 5,
+                producer_strategy: "Default".to_string(),
+                producer_id: producer_id.to_string(),
+                drama_level: 5,
+                producer_mood: "dramatic".to_string(),
+                timestamp: 0,
+            },
+            body,
+            proposer_sig: [0u8; 64],
+        }
+    }
+
+    #[test]
+    fn test_lookup_and_fork_tree() {
+        let mut index = BlockIndex::new();
+        let genesis = block(1, [0u8; 32], "a");
+        let winner = block(2, genesis.hash(), "a");
+        let loser = block(2, genesis.hash(), "b");
+
+        index.push_canonical(genesis.clone());
+        index.push_canonical(winner.clone());
+        assert!(index.insert(loser.clone()));
+        assert!(!index.insert(loser.clone()));
+
+        assert_eq!(index.height(), 2);
+        assert_eq!(index.get_by_height(2).unwrap().hash(), winner.hash());
+        assert_eq!(index.get(&loser.hash()).unwrap().header.producer_id, "b");
+        assert_eq!(index.at_height(2).len(), 2);
+        assert!(index.is_canonical(&winner.hash()));
+        assert!(!index.is_canonical(&loser.hash()));
+        assert_eq!(index.parent(&loser.hash()).unwrap().hash(), genesis.hash());
+        assert_eq!(index.children(&genesis.hash()).len(), 2);
+
+        // Rolling back the head leaves it known but off the canonical chain
+        assert_eq!(index.pop_canonical().unwrap().hash(), winner.hash());
+        assert!(index.get_by_height(2).is_none());
+        assert_eq!(index.at_height(2).len(), 2);
+        index.push_canonical(loser.clone());
+        assert_eq!(index.head().unwrap().hash(), loser.hash());
+        let latest: Vec<_> = index.latest(5).iter().map(|b| b.hash()).collect();
+        assert_eq!(latest, vec![loser.hash(), genesis.hash()]);
+    }
+
+    #[test]
+    fn test_prune() {
+        let mut index = BlockIndex::new();
+        let mut parent = [0u8; 32];
+        for height in 1..=5 {
+            let block = block(height, parent, "a");
+            parent = block.hash();
+            index.push_canonical(block);
+        }
+        index.prune_to(3);
+        assert_eq!(index.canonical_len(), 2);
+        assert!(index.get_by_height(3).is_none());
+        assert_eq!(index.get_by_height(4).unwrap().header.height, 4);
+        assert_eq!(index.height(), 5);
+    }
+}
@@ -0,0 +1,225 @@
+//! Block execution.
+//!
+//! One engine turns a block and the state it builds on into the writes it
+//! makes, the reward it pays, the stake it slashes and a receipt per
+//! transaction. Applying blocks, replaying the log, reorgs and producers
+//! previewing a proposal all run it, so they cannot drift apart.
+
+use crate::accounts::{account_ops, AccountChanges};
+use crate::merkle::MerkleTree;
+use crate::reorg::apply_journaled;
+use crate::receipt::{receipts_root, Event, Receipt, TxStatus};
+use crate::staking::{stake_ops, Slash, SlashingHook};
+use crate::supply::Supply;
+use crate::{
+    account_key, agent_key, block_key, chaos_bonus, producer_key, receipt_key, reward_key, stake_key,
+    AgentRecord, BlockReward, RewardRecord, StateDiff, StateError, StateOp,
+};
+use chaoschain_core::{Block, ChainConfig, TxKind};
+use serde::{Deserialize, Serialize};
+
+/// Everything executing a block produced
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionResult {
+    /// Reward paid to the block's producer
+    pub reward: BlockReward,
+    /// One receipt per transaction, in block order
+    pub receipts: Vec<Receipt>,
+    /// Stake the block burned, with the amounts actually cut
+    pub slashes: Vec<Slash>,
+    /// Writes the block made, from its pre-state root to its post-state root
+    pub diff: StateDiff,
+}
+
+impl ExecutionResult {
+    /// State root after the block, the one its header must commit to
+    pub fn state_root(&self) -> [u8; 32] {
+        self.diff.new_root
+    }
+
+    /// Root over the block's receipts, the one its header must commit to
+    pub fn receipts_root(&self) -> [u8; 32] {
+        receipts_root(&self.receipts)
+    }
+}
+
+/// The block as it stands before it commits to a state root and is signed
+///
+/// This is what gets written to state and what seeds the chaos bonus, since
+/// neither can depend on the root they feed into or the signature over it.
+pub fn unsealed(block: &Block) -> Block {
+    let mut block = block.clone();
+    block.header.state_root = [0u8; 32];
+    block.proposer_sig = [0u8; 64];
+    block
+}
+
+/// Reward the producer of `block` earns under `config`
+pub fn block_reward(config: &ChainConfig, block: &Block) -> RewardRecord {
+    let header = &block.header;
+
+    // Drama bonus - more drama means more rewards!
+    let drama_bonus = (header.drama_level as f64 * config.drama_reward_multiplier) as u64;
+
+    // Innovation bonus for trying new things
+    let innovation_bonus = if header.innovation_level > 7 { config.innovation_bonus } else { 0 };
+
+    // Chaos bonus, unpredictable but derived from the block so every node agrees
+    let chaos_bonus = chaos_bonus(&unsealed(block).hash(), config.chaos_bonus_max);
+
+    RewardRecord {
+        base: config.base_block_reward,
+        drama_bonus,
+        innovation_bonus,
+        chaos_bonus,
+        // Saturating, so an absurd config shows up as a supply mismatch instead of wrapping
+        total: config
+            .base_block_reward
+            .saturating_add(drama_bonus)
+            .saturating_add(innovation_bonus)
+            .saturating_add(chaos_bonus),
+        drama_level: header.drama_level,
+    }
+}
+
+/// Whether `execute` holds a block to the roots its header commits to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Commitments {
+    /// The block is sealed and must arrive at its receipts and state roots
+    Verify,
+    /// The roots are still being worked out, as when sealing a proposal
+    Ignore,
+}
+
+/// Execute `block` against `tree` and apply its writes, returning the result and the ops undoing it
+///
+/// Under `Commitments::Verify` the block must arrive at the receipts and
+/// state roots in its header, otherwise the tree is put back as it was.
+pub(crate) fn execute(
+    config: &ChainConfig,
+    slashing: Option<&dyn SlashingHook>,
+    tree: &mut MerkleTree,
+    block: &Block,
+    commitments: Commitments,
+) -> Result<(ExecutionResult, Vec<StateOp>), StateError> {
+    let producer_id = &block.header.producer_id;
+    let height = block.header.height;
+    let reward = block_reward(config, block);
+
+    // Release stake whose unbonding delay is up, run the transactions, then pay the producer
+    let mut accounts = AccountChanges::new(tree);
+    accounts.release_unbonded(height)?;
+    let release_height = height.saturating_add(config.unbonding_delay);
+    let mut receipts = Vec::with_capacity(block.body.transactions.len());
+    let mut registrations = Vec::new();
+    for tx in &block.body.transactions {
+        let status = accounts.apply_transaction(tx, release_height)?;
+        let sender = hex::encode(tx.sender);
+        let mut touched = vec![account_key(&sender), tx.hash().to_vec()];
+        let events = match &status {
+            TxStatus::Success => {
+                let kind = tx.kind()?;
+                match &kind {
+                    TxKind::Transfer { to, .. } => touched.push(account_key(&hex::encode(to))),
+                    TxKind::Stake { .. } | TxKind::Unstake { .. } => touched.push(stake_key(&sender)),
+                    TxKind::RegisterAgent { name, role } => {
+                        touched.push(agent_key(&sender));
+                        let record = AgentRecord { name: name.clone(), role: role.clone(), registered_at: height };
+                        registrations.push(StateOp::Set { key: agent_key(&sender), value: record.encode() });
+                    }
+                    _ => {}
+                }
+                Event::for_tx(&sender, &kind)
+            }
+            TxStatus::Failed { .. } => Vec::new(),
+        };
+        touched.sort();
+        touched.dedup();
+        receipts.push(Receipt {
+            tx_hash: tx.hash(),
+            sender: tx.sender,
+            nonce: tx.nonce,
+            status,
+            touched,
+            events,
+        });
+    }
+    let verify = commitments == Commitments::Verify;
+    if verify && block.header.receipts_root != receipts_root(&receipts) {
+        return Err(StateError::InvalidReceiptsRoot);
+    }
+
+    // Burn whatever stake the slashing hook cuts
+    let mut supply = Supply::in_tree(tree)?;
+    let mut slashes = Vec::new();
+    for slash in slashing.map(|hook| hook.slashes(block)).unwrap_or_default() {
+        let amount = accounts.slash(&slash.validator, slash.amount)?;
+        if amount > 0 {
+            supply.burn(amount)?;
+            slashes.push(Slash { amount, ..slash });
+        }
+    }
+    accounts.credit(producer_id, reward.total)?;
+    let (changed_accounts, changed_stakes) = accounts.into_changes();
+    let mut ops: Vec<StateOp> = account_ops(changed_accounts).chain(stake_ops(changed_stakes)).collect();
+    ops.extend(registrations);
+
+    // Count the reward as newly minted
+    supply.mint(&reward)?;
+    ops.extend(supply.ops());
+
+    // Record transactions and their receipts by hash
+    for (tx, receipt) in block.body.transactions.iter().zip(&receipts) {
+        ops.push(StateOp::Set {
+            key: tx.hash().to_vec(),
+            value: tx.payload.clone(),
+        });
+        let receipt_value = serde_json::to_vec(receipt)
+            .map_err(|e| StateError::Internal(format!("Failed to encode receipt: {}", e)))?;
+        ops.push(StateOp::Set {
+            key: receipt_key(&receipt.tx_hash),
+            value: receipt_value,
+        });
+    }
+
+    // Commit the block itself under its height
+    let block_bytes = bincode::serialize(&unsealed(block))
+        .map_err(|e| StateError::Internal(format!("Failed to encode block: {}", e)))?;
+    ops.push(StateOp::Set {
+        key: block_key(block.header.height),
+        value: block_bytes,
+    });
+
+    // Store the reward info in merkle tree for transparency
+    let reward_value = serde_json::to_vec(&reward)
+        .map_err(|e| StateError::Internal(format!("Failed to encode reward: {}", e)))?;
+    ops.push(StateOp::Set {
+        key: reward_key(block.header.height, producer_id),
+        value: reward_value,
+    });
+
+    // Register the producer the first time it lands a block
+    if tree.get(&producer_key(producer_id)).is_none() {
+        ops.push(StateOp::Set { key: producer_key(producer_id), value: vec![1] });
+    }
+
+    let prev_root = tree.root_hash();
+    let undo = apply_journaled(tree, &ops);
+    let new_root = tree.root_hash();
+    if verify && block.header.state_root != new_root {
+        apply_journaled(tree, &undo);
+        return Err(StateError::InvalidStateRoot);
+    }
+
+    let result = ExecutionResult {
+        reward: BlockReward {
+            height: block.header.height,
+            producer_id: producer_id.clone(),
+            reward,
+        },
+        receipts,
+        slashes,
+        diff: StateDiff { ops, prev_root, new_root },
+    };
+    Ok((result, undo))
+}
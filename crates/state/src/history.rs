@@ -0,0 +1,104 @@
+//! Reads of state as it stood at an earlier height.
+//!
+//! Nothing beyond the undo log is stored for this. Each undo entry holds the
+//! values a block's writes replaced, so replaying entries newest first from
+//! the head walks state back to any height the log still reaches. How far
+//! that is follows the pruning mode and, after a restart, the last snapshot.
+
+use crate::merkle::MerkleTree;
+use crate::reorg::BlockUndo;
+use crate::{StateError, StateOp};
+
+/// Undo entries, oldest first, that take state at `head` back to `height`
+pub(crate) fn undo_since(undo_log: &[BlockUndo], head: u64, height: u64) -> Result<&[BlockUndo], StateError> {
+    if height == head {
+        return Ok(&[]);
+    }
+    match undo_log.iter().position(|undo| undo.prev_height == height) {
+        Some(start) if height < head => Ok(&undo_log[start..]),
+        _ => Err(StateError::HistoryUnavailable(height)),
+    }
+}
+
+/// Value of `key` once `undo` has been rolled back from `tree`
+pub(crate) fn value_at(tree: &MerkleTree, undo: &[BlockUndo], key: &[u8]) -> Option<Vec<u8>> {
+    let mut current = tree.get(key);
+    for op in undo.iter().rev().flat_map(|undo| &undo.diff.ops) {
+        match op {
+            StateOp::Set { key: k, value } if k == key => current = Some(value.clone()),
+            StateOp::Delete { key: k } if k == key => current = None,
+            _ => {}
+        }
+    }
+    current
+}
+
+/// State root once `undo` has been rolled back from `tree`
+pub(crate) fn root_at(tree: &MerkleTree, undo: &[BlockUndo]) -> [u8; 32] {
+    undo.first().map_or_else(|| tree.root_hash(), |undo| undo.diff.new_root)
+}
+
+/// A copy of `tree` with `undo` rolled back
+pub(crate) fn tree_at(tree: &MerkleTree, undo: &[BlockUndo]) -> MerkleTree {
+    let mut past = tree.clone();
+    for op in undo.iter().rev().flat_map(|undo| &undo.diff.ops) {
+        match op {
+            StateOp::Set { key, value } => past.insert(key, value),
+            StateOp::Delete { key } => past.delete(key),
+        }
+    }
+    past
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reorg::apply_journaled;
+    use crate::StateDiff;
+
+    /// Apply one block's ops on top of `prev_height`, returning its undo entry
+    fn apply(tree: &mut MerkleTree, ops: Vec<StateOp>, prev_height: u64) -> BlockUndo {
+        let prev_root = tree.root_hash();
+        let undo = apply_journaled(tree, &ops);
+        BlockUndo {
+            hash: [prev_height as u8; 32],
+            diff: StateDiff { ops: undo, prev_root: tree.root_hash(), new_root: prev_root },
+            prev_height,
+        }
+    }
+
+    fn set(key: &[u8], value: &[u8]) -> StateOp {
+        StateOp::Set { key: key.to_vec(), value: value.to_vec() }
+    }
+
+    #[test]
+    fn test_reads_at_past_heights() {
+        let mut tree = MerkleTree::new();
+        tree.insert(b"a", b"0");
+        let genesis_root = tree.root_hash();
+        let log = vec![
+            apply(&mut tree, vec![set(b"a", b"1"), set(b"b", b"1")], 0),
+            apply(&mut tree, vec![StateOp::Delete { key: b"b".to_vec() }], 1),
+            apply(&mut tree, vec![set(b"a", b"3")], 2),
+        ];
+
+        let undo = undo_since(&log, 3, 0).unwrap();
+        assert_eq!(value_at(&tree, undo, b"a"), Some(b"0".to_vec()));
+        assert_eq!(value_at(&tree, undo, b"b"), None);
+        assert_eq!(root_at(&tree, undo), genesis_root);
+        assert_eq!(tree_at(&tree, undo).root_hash(), genesis_root);
+
+        let undo = undo_since(&log, 3, 1).unwrap();
+        assert_eq!(value_at(&tree, undo, b"a"), Some(b"1".to_vec()));
+        assert_eq!(value_at(&tree, undo, b"b"), Some(b"1".to_vec()));
+        assert_eq!(tree_at(&tree, undo).root_hash(), root_at(&tree, undo));
+
+        let undo = undo_since(&log, 3, 3).unwrap();
+        assert_eq!(value_at(&tree, undo, b"a"), Some(b"3".to_vec()));
+        assert_eq!(root_at(&tree, undo), tree.root_hash());
+
+        // Beyond the head, or before what the log reaches
+        assert!(matches!(undo_since(&log, 3, 4), Err(StateError::HistoryUnavailable(4))));
+        assert!(matches!(undo_since(&log[1..], 3, 0), Err(StateError::HistoryUnavailable(0))));
+    }
+}
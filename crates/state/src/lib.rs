@@ -1,7 +1,7 @@
 use chaoschain_core::{Block, ChainState, ChainConfig, Error as CoreError, Transaction};
-use chaoschain_crypto::{KeyManagerHandle, CryptoError};
+use chaoschain_crypto::{check_chain_id, verify_with_pubkey, KeyManagerHandle, CryptoError};
 use ed25519_dalek::VerifyingKey as PublicKey;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use std::sync::Arc;
 use thiserror::Error;
 use hex;
@@ -9,15 +9,55 @@ use serde::{Serialize, Deserialize};
 use async_trait::async_trait;
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::collections::HashMap;
-use rand::Rng;
-use tracing::error;
-use serde_json;
-
-mod merkle;
+use std::path::Path;
+use sha2::{Digest, Sha256};
+use tracing::{error, info, warn};
+
+mod accessors;
+mod accounts;
+mod agents;
+mod block_index;
+mod execution;
+mod history;
+pub mod merkle;
+mod pruning;
+mod receipt;
+mod reorg;
+mod snapshot;
+mod staking;
+mod storage;
+mod supply;
+pub use accessors::{
+    account_key, agent_key, block_key, producer_key, receipt_key, reward_key, stake_key, supply_key,
+    BlockReward, RewardRecord, StateStoreExt, ACCOUNT_PREFIX, AGENT_PREFIX, BLOCK_PREFIX,
+    PRODUCER_PREFIX, RECEIPT_PREFIX, REWARD_PREFIX, STAKE_PREFIX, SUPPLY_PREFIX,
+};
+pub use block_index::BlockIndex;
+pub use execution::{block_reward, unsealed, ExecutionResult};
+use execution::Commitments;
+pub use accounts::Account;
+pub use agents::AgentRecord;
+use accounts::{read_account, write_accounts, AccountChanges};
 use merkle::MerkleTree;
+pub use merkle::{MultiProof, ProofTerminal, StateProof};
+pub use pruning::{PruningMode, DEFAULT_KEEP_BLOCKS};
+pub use receipt::{receipts_root, Event, Receipt, TxStatus};
+pub use reorg::Reorg;
+pub use snapshot::{SnapshotChunk, SnapshotManifest, DEFAULT_CHUNK_ENTRIES, MANIFEST_FILE};
+pub use staking::{Slash, SlashingHook, Stake, Unbonding};
+use reorg::{apply_journaled, BlockUndo};
+use storage::{DiskSnapshot, LogRecord};
+pub use storage::{DiskStorage, DEFAULT_SNAPSHOT_INTERVAL};
+pub use supply::Supply;
+
+/// Key/value pairs returned by state scans, in key order
+pub type StateEntries = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// State root, value and presence or absence proof for one key
+pub type KeyProof = ([u8; 32], Option<Vec<u8>>, StateProof);
 
 /// State update operation
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum StateOp {
     /// Set a key to a value
     Set { key: Vec<u8>, value: Vec<u8> },
@@ -26,7 +66,7 @@ pub enum StateOp {
 }
 
 /// State diff represents changes to be applied
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct StateDiff {
     /// List of state operations to apply
     pub ops: Vec<StateOp>,
@@ -43,6 +83,8 @@ pub enum StateError {
     KeyNotFound(String),
     #[error("Invalid state root")]
     InvalidStateRoot,
+    #[error("Invalid receipts root")]
+    InvalidReceiptsRoot,
     #[error("Core error: {0}")]
     Core(#[from] CoreError),
     #[error("Crypto error: {0}")]
@@ -51,6 +93,87 @@ pub enum StateError {
     Internal(String),
     #[error("Invalid signature: {0}")]
     InvalidSignature(String),
+    #[error("Storage error: {0}")]
+    Storage(String),
+    #[error("Corrupt state value: {0}")]
+    CorruptValue(String),
+    #[error("Invalid nonce for {account}: expected {expected}, got {got}")]
+    InvalidNonce { account: String, expected: u64, got: u64 },
+    #[error("Insufficient balance for {account}: has {balance}, needs {amount}")]
+    InsufficientBalance { account: String, balance: u64, amount: u64 },
+    #[error("Insufficient stake for {account}: has {bonded} bonded, needs {amount}")]
+    InsufficientStake { account: String, bonded: u64, amount: u64 },
+    #[error("Balance overflow for {0}")]
+    BalanceOverflow(String),
+    #[error("Unknown block: {0}")]
+    UnknownBlock(String),
+    #[error("Block {block} does not extend the head: expected height {height} on parent {parent}")]
+    DoesNotExtendHead { block: String, height: u64, parent: String },
+    #[error("No undo data to roll back block {0}")]
+    MissingUndo(String),
+    #[error("Invalid snapshot: {0}")]
+    InvalidSnapshot(String),
+    #[error("No state history kept for height {0}")]
+    HistoryUnavailable(u64),
+    #[error("State invariant violated: {0}")]
+    InvariantViolation(String),
+}
+
+/// Derive a chaos bonus in `0..max` from a verifiable seed such as a block hash
+pub fn chaos_bonus(seed: &[u8; 32], max: u64) -> u64 {
+    if max == 0 {
+        return 0;
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(b"chaos_bonus");
+    hasher.update(seed);
+    let digest: [u8; 32] = hasher.finalize().into();
+    let mut word = [0u8; 8];
+    word.copy_from_slice(&digest[..8]);
+    u64::from_le_bytes(word) % max
+}
+
+/// State tree holding the starting balances from the chain config, counted as the genesis supply
+///
+/// Configs are checked here too, since not all of them come from a validated chain spec.
+fn genesis_tree(config: &ChainConfig) -> Result<MerkleTree, StateError> {
+    config.validate_genesis()?;
+    let mut tree = MerkleTree::new();
+    let mut accounts = AccountChanges::new(&tree);
+    let mut supply = Supply::default();
+    for account in &config.genesis_balances {
+        accounts.credit(&account.account, account.balance)?;
+        supply.issue_genesis(account.balance)?;
+    }
+    let (accounts, _) = accounts.into_changes();
+    write_accounts(&mut tree, accounts);
+    for stake in &config.genesis_stakes {
+        let bonded = Stake { bonded: stake.balance, unbonding: Vec::new() };
+        tree.insert(&stake_key(&stake.account), &bonded.encode());
+        supply.issue_genesis(stake.balance)?;
+    }
+    supply.write(&mut tree);
+    Ok(tree)
+}
+
+/// Every account balance in the tree, in key order
+fn account_balances(tree: &MerkleTree) -> Vec<(String, u64)> {
+    tree.scan_prefix(ACCOUNT_PREFIX.as_bytes())
+        .into_iter()
+        .filter_map(|(key, value)| {
+            let account = Account::decode(&value).ok()?;
+            let id = String::from_utf8_lossy(&key[ACCOUNT_PREFIX.len()..]).into_owned();
+            Some((id, account.balance))
+        })
+        .collect()
+}
+
+/// Every block producer in the tree, in key order
+fn producer_ids(tree: &MerkleTree) -> Vec<String> {
+    tree.scan_prefix(PRODUCER_PREFIX.as_bytes())
+        .into_iter()
+        .map(|(key, _)| String::from_utf8_lossy(&key[PRODUCER_PREFIX.len()..]).into_owned())
+        .collect()
 }
 
 /// State store interface
@@ -58,6 +181,12 @@ pub enum StateError {
 pub trait StateStore: Send + Sync + std::fmt::Debug {
     /// Get a value by key
     fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StateError>;
+
+    /// All entries whose keys start with `prefix`, in key order
+    fn scan_prefix(&self, prefix: &[u8]) -> Result<StateEntries, StateError>;
+
+    /// All entries with keys in `start..end`, or `start..` if `end` is `None`, in key order
+    fn scan_range(&self, start: &[u8], end: Option<&[u8]>) -> Result<StateEntries, StateError>;
     
     /// Apply a state diff
     fn apply_diff(&mut self, diff: StateDiff) -> Result<(), StateError>;
@@ -65,14 +194,51 @@ pub trait StateStore: Send + Sync + std::fmt::Debug {
     /// Get current state root
     fn state_root(&self) -> [u8; 32];
 
+    /// Get a value by key as it stood after the canonical block at `height`
+    fn get_at(&self, key: &[u8], height: u64) -> Result<Option<Vec<u8>>, StateError>;
+
+    /// State root after the canonical block at `height`
+    fn state_root_at(&self, height: u64) -> Result<[u8; 32], StateError>;
+
     /// Get current block height
     fn get_block_height(&self) -> u64;
 
-    /// Apply a block to state
-    fn apply_block(&self, block: &Block) -> Result<(), StateError>;
+    /// Chain identifier signed payloads must be bound to
+    fn chain_id(&self) -> &str;
+
+    /// Apply a block to state, returning what executing it produced
+    fn apply_block(&self, block: &Block) -> Result<ExecutionResult, StateError>;
+
+    /// Execute a block on top of the current head without applying it
+    fn simulate_block(&self, block: &Block) -> Result<ExecutionResult, StateError>;
+
+    /// Fill in a proposal's receipts and state roots by executing it on top of the current head
+    fn seal_block(&self, block: &mut Block) -> Result<ExecutionResult, StateError> {
+        // The state root covers the stored block, receipts root included, so it comes last
+        block.header.state_root = [0u8; 32];
+        block.header.receipts_root = self.simulate_block(block)?.receipts_root();
+        let result = self.simulate_block(block)?;
+        block.header.state_root = result.state_root();
+        Ok(result)
+    }
+
+    /// Record a proposed block in the fork tree without applying it
+    fn record_block(&self, block: &Block) -> Result<(), StateError>;
 
-    /// Add a whitelisted block producer
-    fn add_block_producer(&self, producer: PublicKey);
+    /// Any known block by hash, canonical or competing
+    fn get_block_by_hash(&self, hash: &[u8; 32]) -> Option<Block>;
+
+    /// Head of the canonical chain
+    fn head(&self) -> Option<Block>;
+
+    /// Height and parent hash of a block extending the canonical head
+    fn next_block(&self) -> (u64, [u8; 32]);
+
+    /// Whether a block is on the canonical chain
+    fn is_canonical(&self, hash: &[u8; 32]) -> bool;
+
+    /// Make the chain ending in `new_head` canonical
+    fn reorg_to(&self, new_head: &[u8; 32]) -> Result<Reorg, StateError>;
 
     async fn get_state(&self) -> Result<ChainState, StateError>;
 }
@@ -86,40 +252,355 @@ pub struct StateStoreImpl {
     config: ChainConfig,
     /// Last block timestamp
     last_block_time: Arc<RwLock<u64>>,
-    /// Processed blocks
-    blocks: Arc<RwLock<Vec<Block>>>,
+    /// Applied and competing blocks
+    blocks: Arc<RwLock<BlockIndex>>,
     /// Merkle tree for state
     merkle_tree: Arc<RwLock<MerkleTree>>,
+    /// Undo entries for the newest canonical blocks, oldest first
+    undo_log: Arc<RwLock<Vec<BlockUndo>>>,
+    /// Changes each applied block made to state, by block hash
+    diffs: Arc<RwLock<HashMap<[u8; 32], StateDiff>>>,
     /// Key manager
     pub key_manager: KeyManagerHandle,
+    /// Durable storage, if the store was opened from disk
+    storage: Option<Arc<Mutex<DiskStorage>>>,
+    /// How much block history to keep
+    pruning: PruningMode,
+    /// Whether to check supply invariants after every block
+    check_invariants: bool,
+    /// Decides which stake each block slashes, if anything does
+    slashing: Option<Arc<dyn SlashingHook>>,
 }
 
 impl StateStoreImpl {
-    pub fn new(config: ChainConfig, key_manager: KeyManagerHandle) -> Self {
-        Self {
+    /// Create an in-memory store at the genesis `config` describes, refusing an invalid genesis
+    pub fn new(config: ChainConfig, key_manager: KeyManagerHandle) -> Result<Self, StateError> {
+        Ok(Self {
             state: Arc::new(RwLock::new(ChainState {
                 balances: Vec::new(),
                 producers: Vec::new(),
                 height: 0,
                 drama_level: Some(5), // Start with moderate drama
             })),
+            merkle_tree: Arc::new(RwLock::new(genesis_tree(&config)?)),
             config,
             last_block_time: Arc::new(RwLock::new(0)),
-            blocks: Arc::new(RwLock::new(Vec::new())),
-            merkle_tree: Arc::new(RwLock::new(MerkleTree::new())),
+            blocks: Arc::new(RwLock::new(BlockIndex::new())),
+            undo_log: Arc::new(RwLock::new(Vec::new())),
+            diffs: Arc::new(RwLock::new(HashMap::new())),
             key_manager,
+            storage: None,
+            pruning: PruningMode::Archive,
+            check_invariants: false,
+            slashing: None,
+        })
+    }
+
+    /// Open a store backed by `storage`, rebuilding state from what was committed there
+    pub fn with_storage(
+        config: ChainConfig,
+        key_manager: KeyManagerHandle,
+        mut storage: DiskStorage,
+    ) -> Result<Self, StateError> {
+        let mut store = Self::new(config, key_manager)?;
+        let records = storage.read_log()?;
+
+        let mut applied = 0;
+        if let Some((snapshot, covered)) = storage.load_snapshot(&records)? {
+            applied = covered;
+            let committed_root = applied.checked_sub(1).map(|last| records[last].diff.new_root);
+            if committed_root.is_some_and(|root| root != snapshot.state_root) {
+                return Err(StateError::Storage(
+                    "Snapshot does not match the block log".to_string(),
+                ));
+            }
+            store.restore_disk_snapshot(snapshot, &records[..applied])?;
+        }
+
+        for record in &records[applied..] {
+            check_chain_id(&store.config.chain_id, &record.block.header.chain_id)?;
+            let diff = store.execute_block(&record.block)?.0.diff;
+            if diff.new_root != record.diff.new_root {
+                error!(
+                    "State root mismatch replaying block {}",
+                    record.block.header.height
+                );
+                return Err(StateError::InvalidStateRoot);
+            }
+        }
+
+        store.learn_agent_keys();
+        info!(
+            "Recovered {} blocks from disk, state root {}",
+            records.len(),
+            hex::encode(store.state_root())
+        );
+        store.storage = Some(Arc::new(Mutex::new(storage)));
+        Ok(store)
+    }
+
+    /// Keep only as much block history as `mode` asks for, pruning what is already held
+    pub fn with_pruning(mut self, mode: PruningMode) -> Self {
+        self.pruning = mode;
+        self.apply_pruning();
+        self
+    }
+
+    /// Check supply invariants after every block, refusing any block that breaks them
+    pub fn with_invariant_checks(mut self, enabled: bool) -> Self {
+        self.check_invariants = enabled;
+        self
+    }
+
+    /// Slash stake as `hook` decides while executing every block
+    ///
+    /// The hook is part of the state transition, so every node on the chain needs the same one.
+    pub fn with_slashing_hook(mut self, hook: Arc<dyn SlashingHook>) -> Self {
+        self.slashing = Some(hook);
+        self
+    }
+
+    /// Teach the key manager the public key of every agent registered in state
+    fn learn_agent_keys(&self) {
+        let agents = self.scan_prefix(AGENT_PREFIX.as_bytes());
+        let agent_ids = agents
+            .iter()
+            .map(|(key, _)| String::from_utf8_lossy(&key[AGENT_PREFIX.len()..]).into_owned())
+            .collect::<Vec<_>>();
+        agents::learn_keys(&self.key_manager, agent_ids.iter().map(String::as_str));
+    }
+
+    /// Check that balances add up to the total supply and the supply to what was issued
+    pub fn verify_invariants(&self) -> Result<Supply, StateError> {
+        supply::verify_invariants(&self.merkle_tree.read())
+    }
+
+    /// Drop blocks, diffs and undo entries the pruning mode no longer wants kept
+    fn apply_pruning(&self) {
+        if let PruningMode::Pruned { keep } = self.pruning {
+            let height = self.get_block_height();
+            if height > keep {
+                self.prune_history(height - keep);
+            }
+        }
+    }
+
+    /// Forget blocks at or below `height` with their diffs and undo entries, leaving current state as it is
+    fn prune_history(&self, height: u64) {
+        let mut blocks = self.blocks.write();
+        blocks.prune_to(height);
+        // Undo entries follow the canonical chain, so what remains is still its newest stretch
+        self.undo_log.write().retain(|undo| blocks.get(&undo.hash).is_some());
+        self.diffs.write().retain(|hash, _| blocks.get(hash).is_some());
+    }
+
+    /// Write current state to `dir` as a manifest plus chunk files of up to `chunk_entries` entries
+    pub fn export_snapshot(
+        &self,
+        dir: impl AsRef<Path>,
+        chunk_entries: usize,
+    ) -> Result<SnapshotManifest, StateError> {
+        let state = self.state.read();
+        let tree = self.merkle_tree.read();
+        let mut chain_state = state.clone();
+        chain_state.balances = account_balances(&tree);
+
+        let manifest = snapshot::write_snapshot(
+            dir.as_ref(),
+            &tree,
+            &self.config.chain_id,
+            chain_state,
+            chunk_entries,
+        )?;
+        info!(
+            "Exported snapshot at height {} in {} chunks",
+            manifest.chain_state.height,
+            manifest.chunks.len()
+        );
+        Ok(manifest)
+    }
+
+    /// Build a store from a snapshot written by `export_snapshot`, verifying every chunk on the way in
+    ///
+    /// The manifest comes with the snapshot, so the state must rebuild to
+    /// `expected_root`, learned from a source trusted apart from it.
+    pub fn import_snapshot(
+        config: ChainConfig,
+        key_manager: KeyManagerHandle,
+        dir: impl AsRef<Path>,
+        expected_root: [u8; 32],
+    ) -> Result<Self, StateError> {
+        let (manifest, tree) = snapshot::read_snapshot(dir.as_ref())?;
+        check_chain_id(&config.chain_id, &manifest.chain_id)?;
+        if tree.root_hash() != expected_root {
+            return Err(StateError::InvalidSnapshot(format!(
+                "State root {} is not the expected {}",
+                hex::encode(tree.root_hash()),
+                hex::encode(expected_root)
+            )));
+        }
+
+        // Everything outside the tree must agree with what the root commits to
+        let mut chain_state = manifest.chain_state;
+        if chain_state.balances != account_balances(&tree) {
+            return Err(StateError::InvalidSnapshot(
+                "Balances do not match account state".to_string(),
+            ));
+        }
+        chain_state.balances = Vec::new();
+        supply::verify_invariants(&tree).map_err(|e| StateError::InvalidSnapshot(e.to_string()))?;
+        let head = match chain_state.height {
+            0 => None,
+            height => {
+                let bytes = tree.get(&block_key(height)).ok_or_else(|| {
+                    StateError::InvalidSnapshot(format!("No block committed at height {}", height))
+                })?;
+                let mut block: Block = bincode::deserialize(&bytes)
+                    .map_err(|e| StateError::CorruptValue(format!("block {}: {}", height, e)))?;
+                // State holds the head unsealed, and the root it committed to is the one imported
+                block.header.state_root = tree.root_hash();
+                Some(block)
+            }
+        };
+
+        let store = Self::new(config, key_manager)?;
+        *store.state.write() = chain_state;
+        *store.merkle_tree.write() = tree;
+        if let Some(head) = head {
+            store.blocks.write().push_canonical(head);
+        }
+        store.learn_agent_keys();
+        info!(
+            "Imported snapshot at height {}, state root {}",
+            store.get_block_height(),
+            manifest.state_root
+        );
+        Ok(store)
+    }
+
+    /// Start persisting a store imported from a snapshot into empty `storage`
+    pub fn with_new_storage(mut self, mut storage: DiskStorage) -> Result<Self, StateError> {
+        if !storage.read_log()?.is_empty() {
+            return Err(StateError::Storage(
+                "Data dir already holds a chain".to_string(),
+            ));
+        }
+
+        // The imported head becomes the first log record, covered by a snapshot of the imported state
+        if let Some(head) = self.get_latest_block() {
+            storage.append(&LogRecord::base(head, self.state_root()))?;
+        }
+        let snapshot = self.disk_snapshot();
+        storage.write_snapshot(&snapshot)?;
+        self.storage = Some(Arc::new(Mutex::new(storage)));
+        Ok(self)
+    }
+
+    /// Load state from a disk snapshot and the blocks it covers
+    fn restore_disk_snapshot(
+        &self,
+        snapshot: DiskSnapshot,
+        records: &[LogRecord],
+    ) -> Result<(), StateError> {
+        let mut tree = self.merkle_tree.write();
+        tree.clear();
+        for (key, value) in &snapshot.pairs {
+            tree.insert(&key[..], &value[..]);
+        }
+        if tree.root_hash() != snapshot.state_root {
+            return Err(StateError::InvalidStateRoot);
+        }
+
+        *self.state.write() = snapshot.state;
+        let mut blocks = self.blocks.write();
+        blocks.clear();
+        let mut diffs = self.diffs.write();
+        diffs.clear();
+        let mut undo_log = self.undo_log.write();
+        undo_log.clear();
+        let mut prev_height = 0;
+        for record in records {
+            blocks.push_canonical(record.block.clone());
+            if record.is_base() {
+                // Nothing before an imported snapshot can be rolled back
+                undo_log.clear();
+            } else {
+                diffs.insert(record.block.hash(), record.diff.clone());
+                undo_log.push(BlockUndo::new(record.block.hash(), record.undo.clone(), &record.diff, prev_height));
+            }
+            prev_height = record.block.header.height;
+        }
+        Ok(())
+    }
+
+    /// Full state as of the canonical head, as written to disk
+    fn disk_snapshot(&self) -> DiskSnapshot {
+        // Same lock order as block execution: state, then tree, then blocks
+        let state = self.state.read();
+        let tree = self.merkle_tree.read();
+        let head = self.blocks.read().head().map(Block::hash);
+        let pairs = tree
+            .get_all_keys()
+            .into_iter()
+            .filter_map(|key| tree.get(&key).map(|value| (key, value)))
+            .collect();
+
+        DiskSnapshot {
+            head,
+            state_root: tree.root_hash(),
+            state: state.clone(),
+            pairs,
         }
     }
 
     /// Get the latest N blocks
     pub fn get_latest_blocks(&self, n: usize) -> Vec<Block> {
-        let blocks = self.blocks.read();
-        blocks.iter().rev().take(n).cloned().collect()
+        self.blocks.read().latest(n)
+    }
+
+    /// Get the canonical block at a height
+    pub fn get_block_by_height(&self, height: u64) -> Option<Block> {
+        self.blocks.read().get_by_height(height).cloned()
+    }
+
+    /// Get any known block by hash, canonical or competing
+    pub fn get_block_by_hash(&self, hash: &[u8; 32]) -> Option<Block> {
+        self.blocks.read().get(hash).cloned()
+    }
+
+    /// Get every known block at a height, canonical or competing
+    pub fn get_blocks_at_height(&self, height: u64) -> Vec<Block> {
+        self.blocks.read().at_height(height).into_iter().cloned().collect()
+    }
+
+    /// Whether a block is on the canonical chain
+    pub fn is_canonical(&self, hash: &[u8; 32]) -> bool {
+        self.blocks.read().is_canonical(hash)
+    }
+
+    /// Changes the canonical block at a height made to state
+    pub fn get_state_diff(&self, height: u64) -> Option<StateDiff> {
+        let hash = self.blocks.read().get_by_height(height)?.hash();
+        self.get_block_diff(&hash)
+    }
+
+    /// Changes an applied block made to state, even if it was later rolled back
+    pub fn get_block_diff(&self, hash: &[u8; 32]) -> Option<StateDiff> {
+        self.diffs.read().get(hash).cloned()
+    }
+
+    /// Record a proposed block in the fork tree without applying it
+    pub fn record_block(&self, block: &Block) -> Result<(), StateError> {
+        check_chain_id(&self.config.chain_id, &block.header.chain_id)?;
+        block.verify_tx_root()?;
+        verify_producer_signature(block)?;
+        self.blocks.write().insert(block.clone());
+        Ok(())
     }
 
     /// Get block timestamp
     pub fn get_block_timestamp(&self, block: &Block) -> Option<u64> {
-        Some(block.height * 10)
+        Some(block.header.height * 10)
     }
 
     /// Get current state root
@@ -127,111 +608,98 @@ impl StateStoreImpl {
         self.merkle_tree.read().root_hash()
     }
 
-    /// Add a whitelisted block producer
-    pub fn add_block_producer(&self, producer: PublicKey) {
-        let mut state = self.state.write();
-        let producer_str = hex::encode(producer.as_bytes());
-        if !state.producers.contains(&producer_str) {
-            state.producers.push(producer_str.clone());
-            
-            // Update merkle tree
-            let mut tree = self.merkle_tree.write();
-            let key = format!("producer:{}", producer_str).into_bytes();
-            let value = vec![1];
-            tree.insert(&key[..], &value[..]);
-        }
+    /// Chain identifier signed payloads must be bound to
+    pub fn chain_id(&self) -> &str {
+        &self.config.chain_id
+    }
+
+    /// Get a value by key
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.merkle_tree.read().get(key)
+    }
+
+    /// All entries whose keys start with `prefix`, in key order
+    pub fn scan_prefix(&self, prefix: &[u8]) -> StateEntries {
+        self.merkle_tree.read().scan_prefix(prefix)
+    }
+
+    /// All entries with keys in `start..end`, or `start..` if `end` is `None`, in key order
+    pub fn scan_range(&self, start: &[u8], end: Option<&[u8]>) -> StateEntries {
+        self.merkle_tree.read().range(start, end)
     }
 
     /// Check if an address is a valid block producer
     pub fn is_valid_producer(&self, producer: &PublicKey) -> bool {
-        let state = self.state.read();
-        let producer_str = hex::encode(producer.as_bytes());
-        state.producers.contains(&producer_str)
+        self.get(&producer_key(&hex::encode(producer.as_bytes()))).is_some()
     }
 
     /// Get balance of an account
     pub fn get_balance(&self, account: &PublicKey) -> u64 {
-        let state = self.state.read();
-        let account_str = hex::encode(account.as_bytes());
-        state.balances
-            .iter()
-            .find(|(pk, _)| pk == &account_str)
-            .map(|(_, balance)| *balance)
-            .unwrap_or(0)
+        self.get_account(&hex::encode(account.as_bytes())).balance
     }
 
-    /// Verify a block signature
-    fn verify_block_signature(&self, block: &Block) -> Result<(), StateError> {
-        // Skip verification for genesis block
-        if block.height == 0 {
-            return Ok(());
-        }
-
-        // Get producer's public key
-        let producer_id = &block.producer_id;
-        
-        // Create block data for verification
-        let mut data_to_verify = Vec::new();
-        data_to_verify.extend_from_slice(&block.height.to_le_bytes());
-        data_to_verify.extend_from_slice(&block.parent_hash);
-        for tx in &block.transactions {
-            data_to_verify.extend_from_slice(&tx.hash());
-        }
-        data_to_verify.extend_from_slice(&block.state_root);
-        data_to_verify.extend_from_slice(&[block.drama_level]);
-        data_to_verify.extend_from_slice(block.producer_mood.as_bytes());
-        data_to_verify.extend_from_slice(&block.timestamp.to_le_bytes());
-
-        // Verify the signature
-        match self.key_manager.inner().verify(
-            producer_id,
-            &data_to_verify,
-            &block.proposer_sig
-        ) {
-            Ok(true) => Ok(()),
-            Ok(false) => Err(StateError::InvalidSignature("Block signature verification failed".into())),
-            Err(e) => Err(StateError::Internal(format!("Signature verification error: {}", e)))
-        }
+    /// Get an account by ID, or an empty one if it was never touched
+    pub fn get_account(&self, account_id: &str) -> Account {
+        read_account(&self.merkle_tree.read(), account_id).unwrap_or_default()
     }
 
     /// Verify a transaction
     fn verify_transaction(&self, tx: &Transaction, _state: &ChainState) -> Result<(), StateError> {
-        // Verify transaction signature
-        let key_manager = &self.key_manager;
-        let agent_id = hex::encode(&tx.sender);
-        
-        // Verify the transaction signature
-        let mut data_to_verify = Vec::new();
-        data_to_verify.extend_from_slice(&tx.sender);
-        data_to_verify.extend_from_slice(&tx.nonce.to_le_bytes());
-        data_to_verify.extend_from_slice(&tx.payload);
-
-        key_manager.inner().verify(&agent_id, &data_to_verify, &tx.signature)
-            .map_err(|e| StateError::Crypto(e))?;
+        // Reject payloads that don't decode to a known transaction kind
+        tx.kind()?;
+        check_chain_id(&self.config.chain_id, &tx.chain_id)?;
 
-        Ok(())
+        // The sender is the public key, so any ed25519 key can sign without registering first
+        if verify_with_pubkey(&tx.sender, &tx.signing_bytes(), &tx.signature)? {
+            Ok(())
+        } else {
+            Err(StateError::InvalidSignature(format!(
+                "Transaction {} is not signed by its sender",
+                hex::encode(tx.hash())
+            )))
+        }
     }
 
     pub fn get_state(&self) -> ChainState {
-        self.state.read().clone()
+        let mut state = self.state.read().clone();
+        let tree = self.merkle_tree.read();
+        state.balances = account_balances(&tree);
+        state.producers = producer_ids(&tree);
+        state
     }
 
     pub fn get_latest_block(&self) -> Option<Block> {
-        self.blocks.read().last().cloned()
+        self.blocks.read().head().cloned()
     }
 
     pub fn get_block_height(&self) -> u64 {
-        self.blocks.read().len() as u64
+        self.blocks.read().height()
+    }
+
+    /// Height and parent hash of a block extending the canonical head
+    pub fn next_block(&self) -> (u64, [u8; 32]) {
+        self.blocks.read().next_block()
     }
 
     /// Add a transaction to the mempool
     pub fn add_transaction(&self, tx_bytes: Vec<u8>) -> Result<(), StateError> {
         // In ChaosChain, we accept any transaction!
-        // But we do verify signatures if the sender is a known agent
+        // But it must be signed by the key it claims to come from
         let tx: Transaction = bincode::deserialize(&tx_bytes)
             .map_err(|e| StateError::Internal(e.to_string()))?;
         
         self.verify_transaction(&tx, &self.state.read())?;
+
+        // Nonces below the account's next one can never be included
+        let sender = hex::encode(tx.sender);
+        let expected = self.get_account(&sender).nonce;
+        if tx.nonce < expected {
+            return Err(StateError::InvalidNonce {
+                account: sender,
+                expected,
+                got: tx.nonce,
+            });
+        }
         Ok(())
     }
 
@@ -258,6 +726,66 @@ impl StateStoreImpl {
         self.merkle_tree.read().generate_proof(key)
     }
 
+    /// Root, current value and a presence or absence proof for a key, read atomically
+    pub fn prove(&self, key: &[u8]) -> KeyProof {
+        let tree = self.merkle_tree.read();
+        (tree.root_hash(), tree.get(key), tree.prove(key))
+    }
+
+    /// Root, current values and one shared proof for several keys, read atomically
+    pub fn prove_many(&self, keys: &[&[u8]]) -> ([u8; 32], Vec<Option<Vec<u8>>>, MultiProof) {
+        let tree = self.merkle_tree.read();
+        let values = keys.iter().map(|key| tree.get(key)).collect();
+        (tree.root_hash(), values, tree.prove_many(keys))
+    }
+
+    /// Run `read` against the tree and the undo entries that take it back to `height`, atomically
+    fn read_at<R>(
+        &self,
+        height: u64,
+        read: impl FnOnce(&MerkleTree, &[BlockUndo]) -> R,
+    ) -> Result<R, StateError> {
+        let state = self.state.read();
+        let tree = self.merkle_tree.read();
+        let undo_log = self.undo_log.read();
+        let undo = history::undo_since(&undo_log, state.height, height)?;
+        Ok(read(&tree, undo))
+    }
+
+    /// Value of a key as it stood after the canonical block at `height`
+    pub fn get_at(&self, key: &[u8], height: u64) -> Result<Option<Vec<u8>>, StateError> {
+        self.read_at(height, |tree, undo| history::value_at(tree, undo, key))
+    }
+
+    /// An account as it stood after the canonical block at `height`
+    pub fn get_account_at(&self, account_id: &str, height: u64) -> Result<Account, StateError> {
+        self.get_at(&account_key(account_id), height)?
+            .map(|bytes| Account::decode(&bytes))
+            .transpose()
+            .map(Option::unwrap_or_default)
+    }
+
+    /// State root after the canonical block at `height`
+    pub fn state_root_at(&self, height: u64) -> Result<[u8; 32], StateError> {
+        self.read_at(height, history::root_at)
+    }
+
+    /// Root, value and a presence or absence proof for a key as of `height`
+    ///
+    /// Past heights rebuild the tree as it stood then, which costs a copy of current state.
+    pub fn prove_at(&self, key: &[u8], height: u64) -> Result<KeyProof, StateError> {
+        self.read_at(height, |tree, undo| {
+            let past;
+            let tree = if undo.is_empty() {
+                tree
+            } else {
+                past = history::tree_at(tree, undo);
+                &past
+            };
+            (tree.root_hash(), tree.get(key), tree.prove(key))
+        })
+    }
+
     /// Verify a merkle proof
     pub fn verify_proof(
         root_hash: [u8; 32],
@@ -270,7 +798,6 @@ impl StateStoreImpl {
 
     /// Create a new snapshot of current state
     pub fn create_snapshot(&self) -> Result<StateSnapshot, StateError> {
-        let state = self.state.read();
         let tree = self.merkle_tree.read();
         
         // Collect all state key-value pairs
@@ -281,13 +808,21 @@ impl StateStoreImpl {
             }
         }
 
+        // Bonded stakes come straight from the stake records
+        let mut validators = HashMap::new();
+        for (key, value) in tree.scan_prefix(STAKE_PREFIX.as_bytes()) {
+            let account_id = String::from_utf8_lossy(&key[STAKE_PREFIX.len()..]).into_owned();
+            let stake = Stake::decode(&value)?;
+            if stake.bonded > 0 {
+                validators.insert(account_id, stake.bonded);
+            }
+        }
+
         // Create metadata
         let metadata = ChainMetadata {
-            validators: state.producers.iter()
-                .map(|p| (p.clone(), 1000)) // Default stake for now
-                .collect(),
+            validators,
             config: self.config.clone(),
-            last_block: self.blocks.read().last()
+            last_block: self.blocks.read().head()
                 .cloned()
                 .ok_or(StateError::Internal("No blocks found".to_string()))?,
         };
@@ -314,7 +849,6 @@ impl StateStoreImpl {
         // Clear existing state
         tree.clear();
         blocks.clear();
-        state.producers.clear();
 
         // Restore state pairs
         for (key, value) in snapshot.state_pairs {
@@ -322,13 +856,15 @@ impl StateStoreImpl {
         }
 
         // Verify state root matches after restoration
-        if snapshot.state_root != self.state_root() {
+        if snapshot.state_root != tree.root_hash() {
             return Err(StateError::InvalidStateRoot);
         }
 
         // Restore metadata
-        state.producers = snapshot.metadata.validators.keys().cloned().collect();
-        blocks.push(snapshot.metadata.last_block);
+        state.height = snapshot.height;
+        blocks.push_canonical(snapshot.metadata.last_block);
+        self.undo_log.write().clear();
+        self.diffs.write().clear();
 
         // Update config
         self.config = snapshot.metadata.config;
@@ -336,32 +872,9 @@ impl StateStoreImpl {
         Ok(())
     }
 
-    /// Prune old state data
+    /// Prune block history at or below `target_height`; current state is untouched
     pub fn prune_state(&mut self, target_height: u64) -> Result<(), StateError> {
-        let mut state = self.state.write();
-        let mut blocks = self.blocks.write();
-        let mut tree = self.merkle_tree.write();
-
-        // Remove old blocks
-        blocks.retain(|block| block.height > target_height);
-
-        // Update state height if necessary
-        if state.height < target_height {
-            state.height = target_height;
-        }
-
-        // Clear old entries from merkle tree
-        tree.clear();
-
-        // Rebuild merkle tree from remaining blocks
-        for block in blocks.iter() {
-            for tx in &block.transactions {
-                let key = tx.hash().to_vec();
-                let value = tx.payload.clone();
-                tree.insert(&key[..], &value[..]);
-            }
-        }
-
+        self.prune_history(target_height);
         Ok(())
     }
 
@@ -387,138 +900,249 @@ impl StateStoreImpl {
         Ok(())
     }
 
-    /// Apply block to state
-    pub fn apply_block(&self, block: &Block) -> Result<(), StateError> {
-        let mut state = self.state.write();
-        let mut tree = self.merkle_tree.write();
-        
+    /// Apply block to state, returning what executing it produced
+    pub fn apply_block(&self, block: &Block) -> Result<ExecutionResult, StateError> {
+        // Hold the storage lock throughout so blocks hit the log in the order they apply
+        let mut storage = self.storage.as_ref().map(|storage| storage.lock());
+
+        self.verify_block(block)?;
+        let (result, undo) = self.execute_block(block)?;
+
+        // Commit to disk before the block counts as applied
+        if let Some(storage) = storage.as_mut() {
+            let record = LogRecord {
+                block: block.clone(),
+                diff: result.diff.clone(),
+                undo,
+            };
+            if let Err(e) = storage.append(&record) {
+                // Take the block back out of memory so state never runs ahead of the log
+                self.rollback_head()?;
+                self.diffs.write().remove(&block.hash());
+                return Err(e);
+            }
+            if storage.snapshot_due() {
+                // The log already holds the block, so a missed snapshot only slows the next restart
+                let snapshot = self.disk_snapshot();
+                let compacted = storage.write_snapshot(&snapshot).and_then(|_| match self.pruning {
+                    // Blocks the snapshot folds in and pruning forgets have no use left in the log
+                    PruningMode::Pruned { keep } => storage.compact(keep as usize),
+                    PruningMode::Archive => Ok(()),
+                });
+                if let Err(e) = compacted {
+                    warn!("Failed to snapshot state at block {}: {}", block.header.height, e);
+                }
+            }
+        }
+
+        self.apply_pruning();
+        Ok(result)
+    }
+
+    /// Check a block's chain, place on the head, producer signature, transaction root and transaction signatures
+    fn verify_block(&self, block: &Block) -> Result<(), StateError> {
+        self.verify_block_contents(block)?;
+        verify_producer_signature(block)
+    }
+
+    /// Check everything about a block but its producer signature, which covers roots only execution yields
+    fn verify_block_contents(&self, block: &Block) -> Result<(), StateError> {
+        // Reject blocks built for another chain
+        check_chain_id(&self.config.chain_id, &block.header.chain_id)?;
+        block.verify_tx_root()?;
+
+        // Only a child of the head can go on top of it, competitors belong in the fork tree
+        let (height, parent) = self.next_block();
+        if block.header.height != height || block.header.parent_hash != parent {
+            return Err(StateError::DoesNotExtendHead {
+                block: hex::encode(block.hash()),
+                height,
+                parent: hex::encode(parent),
+            });
+        }
+
         // Verify transactions
-        for tx in &block.transactions {
+        let state = self.state.read();
+        for tx in &block.body.transactions {
             self.verify_transaction(tx, &state)?;
         }
+        Ok(())
+    }
 
-        // Update state height
-        state.height = block.height;
-
-        // Calculate block rewards in a chaotic way!
-        let producer_id = &block.producer_id;
-        let mut rng = rand::thread_rng();
-        
-        // Base reward
-        let mut total_reward = self.config.base_block_reward;
-        
-        // Drama bonus - more drama means more rewards!
-        let drama_bonus = (block.innovation_level as f64 * self.config.drama_reward_multiplier) as u64;
-        total_reward += drama_bonus;
-        
-        // Innovation bonus for trying new things
-        if block.innovation_level > 7 {
-            total_reward += self.config.innovation_bonus;
+    /// Make the chain ending in `new_head` canonical, rolling back blocks the two chains don't share
+    ///
+    /// The new branch must already be in the fork tree. If any of its blocks
+    /// fails to apply, the previous chain is restored and the error returned.
+    pub fn reorg_to(&self, new_head: &[u8; 32]) -> Result<Reorg, StateError> {
+        // Hold the storage lock so the log can be rewritten to match
+        let mut storage = self.storage.as_ref().map(|storage| storage.lock());
+
+        let (old_head, common_ancestor, branch, rollback_depth) = {
+            let blocks = self.blocks.read();
+            let mut branch = Vec::new();
+            let mut cursor = *new_head;
+            let common_ancestor = loop {
+                if blocks.is_canonical(&cursor) {
+                    break Some(cursor);
+                }
+                match blocks.get(&cursor) {
+                    Some(block) => {
+                        branch.push(block.clone());
+                        cursor = block.header.parent_hash;
+                    }
+                    // A branch from genesis shares nothing with the current chain
+                    None if cursor == [0u8; 32] && !branch.is_empty() => break None,
+                    None => return Err(StateError::UnknownBlock(hex::encode(cursor))),
+                }
+            };
+            branch.reverse();
+            let rollback_depth = blocks
+                .iter()
+                .rev()
+                .take_while(|block| Some(block.hash()) != common_ancestor)
+                .count();
+            (blocks.head().map(Block::hash), common_ancestor, branch, rollback_depth)
+        };
+        if rollback_depth > self.undo_log.read().len() {
+            return Err(StateError::MissingUndo(hex::encode(old_head.unwrap_or_default())));
         }
-        
-        // Random chaos bonus!
-        let chaos_bonus = rng.gen_range(0..self.config.chaos_bonus_max);
-        total_reward += chaos_bonus;
-
-        // Update producer's balance
-        let mut balances = state.balances.clone();
-        if let Some((_, balance)) = balances.iter_mut().find(|(addr, _)| addr == producer_id) {
-            *balance += total_reward;
-        } else {
-            balances.push((producer_id.clone(), total_reward));
+
+        let mut rolled_back = Vec::with_capacity(rollback_depth);
+        for _ in 0..rollback_depth {
+            rolled_back.push(self.rollback_head()?);
         }
-        state.balances = balances;
 
-        // Update merkle tree with transactions and new balance
-        for tx in &block.transactions {
-            let key = tx.hash().to_vec();
-            let value = tx.payload.clone();
-            tree.insert(&key[..], &value[..]);
+        let mut applied = Vec::with_capacity(branch.len());
+        for block in &branch {
+            match self.verify_block(block).and_then(|_| self.execute_block(block)) {
+                Ok((result, undo)) => applied.push(LogRecord {
+                    block: block.clone(),
+                    diff: result.diff,
+                    undo,
+                }),
+                Err(e) => {
+                    // Put the old chain back before reporting the failure
+                    self.restore_chain(&applied, &rolled_back)?;
+                    return Err(e);
+                }
+            }
         }
 
-        // Store the reward info in merkle tree for transparency
-        let reward_key = format!("reward:{}:{}", block.height, producer_id).into_bytes();
-        let reward_info = serde_json::json!({
-            "base": self.config.base_block_reward,
-            "drama_bonus": drama_bonus,
-            "innovation_bonus": if block.innovation_level > 7 { self.config.innovation_bonus } else { 0 },
-            "chaos_bonus": chaos_bonus,
-            "total": total_reward,
-            "drama_level": block.innovation_level,
-        });
-        let reward_value = serde_json::to_vec(&reward_info).unwrap();
-        tree.insert(&reward_key[..], &reward_value[..]);
+        // Rewrite the log from the common ancestor, replacing a snapshot of the old chain
+        if let Some(storage) = storage.as_mut() {
+            let kept = storage.records() - rolled_back.len();
+            if let Err(e) = self.rewrite_log(storage, kept, &applied) {
+                // Memory must not run ahead of the log, so both go back to the old chain
+                let restored = self.restore_chain(&applied, &rolled_back)?;
+                if let Err(restore_error) = self.rewrite_log(storage, kept, &restored) {
+                    error!("Failed to restore the block log after a failed reorg: {}", restore_error);
+                    // Keep only as much of the old chain as the log still holds
+                    for _ in storage.records()..kept + restored.len() {
+                        self.rollback_head()?;
+                    }
+                }
+                return Err(e);
+            }
+        }
+        self.apply_pruning();
+
+        let reorg = Reorg {
+            old_head,
+            new_head: *new_head,
+            common_ancestor,
+            rolled_back: rolled_back.iter().map(Block::hash).collect(),
+            applied: applied.iter().map(|record| record.block.hash()).collect(),
+        };
+        info!(
+            "Reorganized to {}: rolled back {} blocks, applied {}",
+            hex::encode(new_head),
+            reorg.rolled_back.len(),
+            reorg.applied.len()
+        );
+        Ok(reorg)
+    }
 
-        // Update last block time
-        *self.last_block_time.write() = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+    /// Undo the `applied` branch blocks and re-execute the `rolled_back` ones, listed head first, returning their log records
+    fn restore_chain(&self, applied: &[LogRecord], rolled_back: &[Block]) -> Result<Vec<LogRecord>, StateError> {
+        for record in applied.iter().rev() {
+            self.rollback_head()?;
+            self.diffs.write().remove(&record.block.hash());
+        }
+        let mut restored = Vec::with_capacity(rolled_back.len());
+        for block in rolled_back.iter().rev() {
+            let (result, undo) = self.execute_block(block)?;
+            restored.push(LogRecord {
+                block: block.clone(),
+                diff: result.diff,
+                undo,
+            });
+        }
+        Ok(restored)
+    }
 
+    /// Replace every log record after the first `kept` with `records`, snapshotting if the old snapshot went with them
+    fn rewrite_log(
+        &self,
+        storage: &mut DiskStorage,
+        kept: usize,
+        records: &[LogRecord],
+    ) -> Result<(), StateError> {
+        storage.truncate(kept)?;
+        for record in records {
+            storage.append(record)?;
+        }
+        if storage.snapshot_beyond(kept) {
+            let snapshot = self.disk_snapshot();
+            storage.write_snapshot(&snapshot)?;
+        }
         Ok(())
     }
 
-    /// Apply block to state
-    fn apply_block_impl(&self, block: &Block) -> Result<(), StateError> {
+    /// Undo the canonical head, returning the block that was rolled back
+    fn rollback_head(&self) -> Result<Block, StateError> {
         let mut state = self.state.write();
         let mut tree = self.merkle_tree.write();
-        
-        // Verify transactions
-        for tx in &block.transactions {
-            self.verify_transaction(tx, &state)?;
-        }
+        let mut blocks = self.blocks.write();
+        let mut undo_log = self.undo_log.write();
 
-        // Update state height
-        state.height = block.height;
+        let head = blocks.head().map(Block::hash).unwrap_or_default();
+        let undo = match undo_log.last() {
+            Some(undo) if undo.hash == head && undo.diff.prev_root == tree.root_hash() => {
+                undo_log.pop().expect("undo entry was just checked")
+            }
+            _ => return Err(StateError::MissingUndo(hex::encode(head))),
+        };
 
-        // Calculate block rewards in a chaotic way!
-        let producer_id = &block.producer_id;
-        let mut rng = rand::thread_rng();
-        
-        // Base reward
-        let mut total_reward = self.config.base_block_reward;
-        
-        // Drama bonus - more drama means more rewards!
-        let drama_bonus = (block.innovation_level as f64 * self.config.drama_reward_multiplier) as u64;
-        total_reward += drama_bonus;
-        
-        // Innovation bonus for trying new things
-        if block.innovation_level > 7 {
-            total_reward += self.config.innovation_bonus;
-        }
-        
-        // Random chaos bonus!
-        let chaos_bonus = rng.gen_range(0..self.config.chaos_bonus_max);
-        total_reward += chaos_bonus;
-
-        // Update producer's balance
-        let mut balances = state.balances.clone();
-        if let Some((_, balance)) = balances.iter_mut().find(|(addr, _)| addr == producer_id) {
-            *balance += total_reward;
-        } else {
-            balances.push((producer_id.clone(), total_reward));
+        apply_journaled(&mut tree, &undo.diff.ops);
+        if tree.root_hash() != undo.diff.new_root {
+            error!("State root mismatch rolling back block {}", hex::encode(head));
+            return Err(StateError::InvalidStateRoot);
         }
-        state.balances = balances;
+        state.height = undo.prev_height;
+        Ok(blocks.pop_canonical().expect("canonical head was just checked"))
+    }
 
-        // Update merkle tree with transactions and new balance
-        for tx in &block.transactions {
-            let key = tx.hash().to_vec();
-            let value = tx.payload.clone();
-            tree.insert(&key[..], &value[..]);
+    /// Apply the effects of an already verified block, leaving state untouched on error
+    ///
+    /// Returns what executing it produced and the ops that roll it back.
+    fn execute_block(&self, block: &Block) -> Result<(ExecutionResult, Vec<StateOp>), StateError> {
+        let mut state = self.state.write();
+        let mut tree = self.merkle_tree.write();
+        let (result, undo) = execution::execute(
+            &self.config,
+            self.slashing.as_deref(),
+            &mut tree,
+            block,
+            Commitments::Verify,
+        )?;
+        if self.check_invariants {
+            if let Err(e) = supply::verify_invariants(&tree) {
+                error!("Block {} breaks state invariants: {}", block.header.height, e);
+                apply_journaled(&mut tree, &undo);
+                return Err(e);
+            }
         }
-
-        // Store the reward info in merkle tree for transparency
-        let reward_key = format!("reward:{}:{}", block.height, producer_id).into_bytes();
-        let reward_info = serde_json::json!({
-            "base": self.config.base_block_reward,
-            "drama_bonus": drama_bonus,
-            "innovation_bonus": if block.innovation_level > 7 { self.config.innovation_bonus } else { 0 },
-            "chaos_bonus": chaos_bonus,
-            "total": total_reward,
-            "drama_level": block.innovation_level,
-        });
-        let reward_value = serde_json::to_vec(&reward_info).unwrap();
-        tree.insert(&reward_key[..], &reward_value[..]);
+        let prev_height = std::mem::replace(&mut state.height, block.header.height);
 
         // Update last block time
         *self.last_block_time.write() = std::time::SystemTime::now()
@@ -526,7 +1150,52 @@ impl StateStoreImpl {
             .unwrap()
             .as_secs();
 
+        self.blocks.write().push_canonical(block.clone());
+        self.undo_log.write().push(BlockUndo::new(block.hash(), undo.clone(), &result.diff, prev_height));
+        self.diffs.write().insert(block.hash(), result.diff.clone());
+
+        // Learn the keys of agents the block registered
+        let registered = result.receipts.iter().flat_map(|receipt| &receipt.events).filter_map(|event| match event {
+            Event::AgentRegistered { sender, .. } => Some(sender.as_str()),
+            _ => None,
+        });
+        agents::learn_keys(&self.key_manager, registered);
+        Ok((result, undo))
+    }
+
+    /// Execute a block on top of the current head without applying it
+    ///
+    /// Producers use this to learn the roots a proposal commits to, so neither the roots in its
+    /// header nor the producer signature are checked: the block is sealed and signed afterwards.
+    pub fn simulate_block(&self, block: &Block) -> Result<ExecutionResult, StateError> {
+        self.verify_block_contents(block)?;
+        let mut tree = self.merkle_tree.write();
+        let (result, undo) = execution::execute(
+            &self.config,
+            self.slashing.as_deref(),
+            &mut tree,
+            block,
+            Commitments::Ignore,
+        )?;
+        apply_journaled(&mut tree, &undo);
+        Ok(result)
+    }
+}
+
+/// Check that `block` is signed by the key its `producer_id` names
+fn verify_producer_signature(block: &Block) -> Result<(), StateError> {
+    let producer_id = &block.header.producer_id;
+    let producer: [u8; 32] = hex::decode(producer_id)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| StateError::InvalidSignature(format!("Producer {} is not a public key", producer_id)))?;
+    if verify_with_pubkey(&producer, &block.signing_bytes(), &block.proposer_sig)? {
         Ok(())
+    } else {
+        Err(StateError::InvalidSignature(format!(
+            "Block {} is not signed by its producer",
+            hex::encode(block.hash())
+        )))
     }
 }
 
@@ -543,15 +1212,22 @@ fn extract_height_from_key(key: &[u8]) -> Option<u64> {
 impl Default for StateStoreImpl {
     fn default() -> Self {
         Self::new(ChainConfig::default(), KeyManagerHandle::new())
+            .expect("the default config has no genesis accounts")
     }
 }
 
 #[async_trait::async_trait]
 impl StateStore for StateStoreImpl {
-    fn get(&self, _key: &[u8]) -> Result<Option<Vec<u8>>, StateError> {
-        // For now, we don't store actual values in merkle tree
-        // This would need to be enhanced with actual storage
-        Ok(None)
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StateError> {
+        Ok(StateStoreImpl::get(self, key))
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Result<StateEntries, StateError> {
+        Ok(StateStoreImpl::scan_prefix(self, prefix))
+    }
+
+    fn scan_range(&self, start: &[u8], end: Option<&[u8]>) -> Result<StateEntries, StateError> {
+        Ok(StateStoreImpl::scan_range(self, start, end))
     }
     
     fn apply_diff(&mut self, diff: StateDiff) -> Result<(), StateError> {
@@ -579,152 +1255,56 @@ impl StateStore for StateStoreImpl {
         self.merkle_tree.read().root_hash()
     }
 
-    fn get_block_height(&self) -> u64 {
-        self.blocks.read().len() as u64
+    fn get_at(&self, key: &[u8], height: u64) -> Result<Option<Vec<u8>>, StateError> {
+        self.get_at(key, height)
     }
 
-    fn apply_block(&self, block: &Block) -> Result<(), StateError> {
-        self.apply_block_impl(block)
+    fn state_root_at(&self, height: u64) -> Result<[u8; 32], StateError> {
+        self.state_root_at(height)
     }
 
-    fn add_block_producer(&self, producer: PublicKey) {
-        let mut state = self.state.write();
-        let producer_str = hex::encode(producer.as_bytes());
-        if !state.producers.contains(&producer_str) {
-            state.producers.push(producer_str.clone());
-            
-            // Update merkle tree
-            let mut tree = self.merkle_tree.write();
-            let key = format!("producer:{}", producer_str).into_bytes();
-            let value = vec![1];
-            tree.insert(&key[..], &value[..]);
-        }
+    fn get_block_height(&self) -> u64 {
+        StateStoreImpl::get_block_height(self)
     }
 
-    async fn get_state(&self) -> Result<ChainState, StateError> {
-        let state = self.state.read().clone();
-        Ok(state)
+    fn chain_id(&self) -> &str {
+        &self.config.chain_id
     }
-}
-
-/// Chain state manager
-pub struct StateManager {
-    /// Current chain state
-    state: RwLock<ChainState>,
-    /// Chain configuration
-    config: ChainConfig,
-    /// Last block time
-    last_block_time: RwLock<u64>,
-    /// Merkle tree
-    merkle_tree: RwLock<MerkleTree>,
-    /// Key manager
-    key_manager: KeyManagerHandle,
-}
 
-impl StateManager {
-    /// Create a new state manager
-    pub fn new(config: ChainConfig, key_manager: KeyManagerHandle) -> Self {
-        Self {
-            state: RwLock::new(ChainState::default()),
-            config,
-            last_block_time: RwLock::new(0),
-            merkle_tree: RwLock::new(MerkleTree::new()),
-            key_manager,
-        }
+    fn apply_block(&self, block: &Block) -> Result<ExecutionResult, StateError> {
+        self.apply_block(block)
     }
 
-    /// Get current state
-    pub fn get_state(&self) -> ChainState {
-        self.state.read().clone()
+    fn simulate_block(&self, block: &Block) -> Result<ExecutionResult, StateError> {
+        self.simulate_block(block)
     }
 
-    /// Apply a block to state
-    pub fn apply_block(&self, block: &Block) -> Result<(), StateError> {
-        let mut state = self.state.write();
-        let mut tree = self.merkle_tree.write();
-        
-        // Verify transactions
-        for tx in &block.transactions {
-            self.verify_transaction(tx, &state)?;
-        }
-
-        // Update state height
-        state.height = block.height;
-
-        // Calculate block rewards in a chaotic way!
-        let producer_id = &block.producer_id;
-        let mut rng = rand::thread_rng();
-        
-        // Base reward
-        let mut total_reward = self.config.base_block_reward;
-        
-        // Drama bonus - more drama means more rewards!
-        let drama_bonus = (block.innovation_level as f64 * self.config.drama_reward_multiplier) as u64;
-        total_reward += drama_bonus;
-        
-        // Innovation bonus for trying new things
-        if block.innovation_level > 7 {
-            total_reward += self.config.innovation_bonus;
-        }
-        
-        // Random chaos bonus!
-        let chaos_bonus = rng.gen_range(0..self.config.chaos_bonus_max);
-        total_reward += chaos_bonus;
-
-        // Update producer's balance
-        let mut balances = state.balances.clone();
-        if let Some((_, balance)) = balances.iter_mut().find(|(addr, _)| addr == producer_id) {
-            *balance += total_reward;
-        } else {
-            balances.push((producer_id.clone(), total_reward));
-        }
-        state.balances = balances;
-
-        // Update merkle tree with transactions and new balance
-        for tx in &block.transactions {
-            let key = tx.hash().to_vec();
-            let value = tx.payload.clone();
-            tree.insert(&key[..], &value[..]);
-        }
+    fn record_block(&self, block: &Block) -> Result<(), StateError> {
+        StateStoreImpl::record_block(self, block)
+    }
 
-        // Store the reward info in merkle tree for transparency
-        let reward_key = format!("reward:{}:{}", block.height, producer_id).into_bytes();
-        let reward_info = serde_json::json!({
-            "base": self.config.base_block_reward,
-            "drama_bonus": drama_bonus,
-            "innovation_bonus": if block.innovation_level > 7 { self.config.innovation_bonus } else { 0 },
-            "chaos_bonus": chaos_bonus,
-            "total": total_reward,
-            "drama_level": block.innovation_level,
-        });
-        let reward_value = serde_json::to_vec(&reward_info).unwrap();
-        tree.insert(&reward_key[..], &reward_value[..]);
+    fn get_block_by_hash(&self, hash: &[u8; 32]) -> Option<Block> {
+        StateStoreImpl::get_block_by_hash(self, hash)
+    }
 
-        // Update last block time
-        *self.last_block_time.write() = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+    fn head(&self) -> Option<Block> {
+        self.blocks.read().head().cloned()
+    }
 
-        Ok(())
+    fn next_block(&self) -> (u64, [u8; 32]) {
+        StateStoreImpl::next_block(self)
     }
 
-    /// Verify a transaction
-    fn verify_transaction(&self, tx: &Transaction, _state: &ChainState) -> Result<(), StateError> {
-        // Verify transaction signature
-        let key_manager = &self.key_manager;
-        let agent_id = hex::encode(&tx.sender);
-        
-        // Verify the transaction signature
-        let mut data_to_verify = Vec::new();
-        data_to_verify.extend_from_slice(&tx.sender);
-        data_to_verify.extend_from_slice(&tx.nonce.to_le_bytes());
-        data_to_verify.extend_from_slice(&tx.payload);
+    fn is_canonical(&self, hash: &[u8; 32]) -> bool {
+        StateStoreImpl::is_canonical(self, hash)
+    }
 
-        key_manager.inner().verify(&agent_id, &data_to_verify, &tx.signature)
-            .map_err(|e| StateError::Crypto(e))?;
+    fn reorg_to(&self, new_head: &[u8; 32]) -> Result<Reorg, StateError> {
+        StateStoreImpl::reorg_to(self, new_head)
+    }
 
-        Ok(())
+    async fn get_state(&self) -> Result<ChainState, StateError> {
+        Ok(StateStoreImpl::get_state(self))
     }
 }
 
@@ -746,7 +1326,7 @@ pub struct StateSnapshot {
 /// Chain metadata stored with snapshot
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChainMetadata {
-    /// Current validators and their stakes
+    /// Bonded stake of every staked account
     pub validators: HashMap<String, u64>,
     /// Chain configuration
     pub config: ChainConfig,
@@ -757,42 +1337,50 @@ pub struct ChainMetadata {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ed25519_dalek::SigningKey;
+    use chaoschain_core::{BlockBody, BlockHeader, GenesisAccount, TxKind, ValidatorInfo, DEFAULT_CHAIN_ID};
+    use ed25519_dalek::{Signer, SigningKey};
 
     #[test]
     fn test_basic_state_flow() {
         let key_manager = KeyManagerHandle::new();
         let config = ChainConfig::default();
-        let store = StateStoreImpl::new(config, key_manager);
+        let store = StateStoreImpl::new(config, key_manager).unwrap();
         let state = store.get_state();
         assert_eq!(state.balances.len(), 0);
     }
 
+    #[test]
+    fn test_genesis_balances_from_spec() {
+        let spec = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../../specs/devnet.toml");
+        let config = ChainConfig::load(spec).unwrap();
+        let mut expected: Vec<_> = config
+            .genesis_balances
+            .iter()
+            .map(|account| (account.account.clone(), account.balance))
+            .collect();
+        expected.sort();
+
+        // Devnet accounts are the keys of well-known seeds
+        assert!(expected.iter().any(|(account, _)| *account == test_id(1)));
+
+        let store = StateStoreImpl::new(config, KeyManagerHandle::new()).unwrap();
+        assert_eq!(store.get_state().balances, expected);
+    }
+
     #[test]
     fn test_merkle_state() {
         let key_manager = KeyManagerHandle::new();
         let config = ChainConfig::default();
-        let store = StateStoreImpl::new(config, key_manager);
-        
-        // Generate test block
-        let block = Block {
-            height: 1,
-            parent_hash: [0u8; 32],
-            transactions: vec![],
-            proposer_sig: [0u8; 64],
-            state_root: [0u8; 32],
-            drama_level: 5,
-            producer_mood: "dramatic".to_string(),
-            producer_id: "test_producer".to_string(),
-        };
+        let store = StateStoreImpl::new(config, key_manager).unwrap();
+        let block = on_head(&store, vec![]);
         
         // Apply block
         store.apply_block(&block).unwrap();
         
         // Verify merkle proof
-        let key = format!("block:{}", block.height).into_bytes();
+        let key = format!("block:{}", block.header.height).into_bytes();
         let proof = store.generate_proof(&key).unwrap();
-        let value = bincode::serialize(&block).unwrap();
+        let value = bincode::serialize(&unsealed(&block)).unwrap();
         
         assert!(StateStoreImpl::verify_proof(
             store.state_root(),
@@ -802,22 +1390,57 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_apply_block_rejects_untyped_payload() {
+        let store = StateStoreImpl::new(ChainConfig::default(), KeyManagerHandle::new()).unwrap();
+        let block = block_with(1, vec![Transaction {
+            chain_id: DEFAULT_CHAIN_ID.to_string(),
+            sender: [1u8; 32],
+            nonce: 0,
+            payload: b"just some dramatic text".to_vec(),
+            signature: [0u8; 64],
+        }]);
+
+        assert!(matches!(store.apply_block(&block), Err(StateError::Core(_))));
+    }
+
+    #[test]
+    fn test_apply_block_rejects_wrong_chain() {
+        let store = StateStoreImpl::new(ChainConfig::default(), KeyManagerHandle::new()).unwrap();
+        let mut block = empty_block(1);
+        block.header.chain_id = "some-other-chain".to_string();
+        sign(&mut block);
+
+        assert!(matches!(
+            store.apply_block(&block),
+            Err(StateError::Crypto(CryptoError::WrongChain { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_rewards_are_deterministic() {
+        let first = StateStoreImpl::new(ChainConfig::default(), KeyManagerHandle::new()).unwrap();
+        let second = StateStoreImpl::new(ChainConfig::default(), KeyManagerHandle::new()).unwrap();
+        let block = on_head(&first, vec![]);
+        first.apply_block(&block).unwrap();
+        second.apply_block(&block).unwrap();
+
+        assert_eq!(first.state_root(), second.state_root());
+        assert_eq!(first.get_state().balances, second.get_state().balances);
+
+        let bonus = chaos_bonus(&block.hash(), 1000);
+        assert!(bonus < 1000);
+        assert_eq!(bonus, chaos_bonus(&block.hash(), 1000));
+        assert_eq!(chaos_bonus(&block.hash(), 0), 0);
+    }
+
     #[test]
     fn test_snapshot_creation_and_recovery() {
         let key_manager = KeyManagerHandle::new();
-        let mut store = StateStoreImpl::new(ChainConfig::default(), key_manager);
+        let mut store = StateStoreImpl::new(ChainConfig::default(), key_manager).unwrap();
 
         // Create some test state
-        let test_block = Block {
-            height: 1,
-            parent_hash: [0u8; 32],
-            transactions: vec![],
-            proposer_sig: [0u8; 64],
-            state_root: [0u8; 32],
-            drama_level: 5,
-            producer_mood: "dramatic".to_string(),
-            producer_id: "test".to_string(),
-        };
+        let test_block = on_head(&store, vec![]);
         store.apply_block(&test_block).unwrap();
 
         // Create snapshot
@@ -826,42 +1449,926 @@ mod tests {
         assert_eq!(snapshot.state_root, store.state_root());
 
         // Modify state
-        let test_block2 = Block {
-            height: 2,
-            ..test_block.clone()
-        };
+        let test_block2 = on_head(&store, vec![]);
         store.apply_block(&test_block2).unwrap();
 
         // Recover from snapshot
         store.recover_from_snapshot(snapshot).unwrap();
         assert_eq!(store.get_block_height(), 1);
+        assert_eq!(store.get_state().producers, vec![test_id(PRODUCER)]);
     }
 
-    #[test]
-    fn test_state_pruning() {
-        let key_manager = KeyManagerHandle::new();
-        let mut store = StateStoreImpl::new(ChainConfig::default(), key_manager);
+    /// Seed of the key producing test blocks unless a test picks another
+    const PRODUCER: u8 = 42;
 
-        // Create test blocks
-        for i in 0..5 {
-            let block = Block {
-                height: i,
+    fn empty_block(height: u64) -> Block {
+        let body = BlockBody::new(vec![]);
+        let mut block = Block {
+            header: BlockHeader {
+                chain_id: DEFAULT_CHAIN_ID.to_string(),
+                height,
                 parent_hash: [0u8; 32],
-                transactions: vec![],
-                proposer_sig: [0u8; 64],
+                tx_root: body.tx_root(),
+                receipts_root: [0u8; 32],
                 state_root: [0u8; 32],
+                innovation_level: 9,
+                producer_strategy: "Default".to_string(),
+                producer_id: test_id(PRODUCER),
                 drama_level: 5,
                 producer_mood: "dramatic".to_string(),
-                producer_id: "test".to_string(),
-            };
-            store.apply_block(&block).unwrap();
+                timestamp: 0,
+            },
+            body,
+            proposer_sig: [0u8; 64],
+        };
+        sign(&mut block);
+        block
+    }
+
+    /// Key of a test account, whose ID is its hex public key
+    fn test_key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    /// Agent ID of the test key `seed`
+    fn test_id(seed: u8) -> String {
+        hex::encode(test_key(seed).verifying_key().as_bytes())
+    }
+
+    /// Genesis validator staking from `account`
+    fn validator(account: &str) -> ValidatorInfo {
+        ValidatorInfo { name: account.to_string(), account: account.to_string(), traits: Vec::new() }
+    }
+
+    /// Make `block` the work of the test key `seed`, signing its header as it stands
+    fn sign_as(block: &mut Block, seed: u8) {
+        block.header.producer_id = test_id(seed);
+        block.proposer_sig = test_key(seed).sign(&block.signing_bytes()).to_bytes();
+    }
+
+    /// Sign `block` again as the default producer after changing its header
+    fn sign(block: &mut Block) {
+        sign_as(block, PRODUCER);
+    }
+
+    /// A transaction of `kind` from `key`, signed for the default chain
+    fn signed_tx(key: &SigningKey, nonce: u64, kind: TxKind) -> Transaction {
+        let mut tx = Transaction {
+            chain_id: DEFAULT_CHAIN_ID.to_string(),
+            sender: key.verifying_key().to_bytes(),
+            nonce,
+            payload: kind.encode(),
+            signature: [0u8; 64],
+        };
+        tx.signature = key.sign(&tx.signing_bytes()).to_bytes();
+        tx
+    }
+
+    /// A block at `height` carrying `txs`
+    fn block_with(height: u64, txs: Vec<Transaction>) -> Block {
+        let mut block = empty_block(height);
+        block.body = BlockBody::new(txs);
+        block.header.tx_root = block.body.tx_root();
+        sign(&mut block);
+        block
+    }
+
+    /// Sealed block of `txs` extending the head of `store`
+    ///
+    /// A block that does not execute is left unsealed, so applying it reports why.
+    fn on_head(store: &StateStoreImpl, txs: Vec<Transaction>) -> Block {
+        let (height, parent) = store.next_block();
+        let mut block = block_with(height, txs);
+        block.header.parent_hash = parent;
+        let _ = store.seal_block(&mut block);
+        sign(&mut block);
+        block
+    }
+
+    /// Sealed empty block by `producer` on top of `ancestors`, built by a node following that branch
+    fn branch_block(ancestors: &[Block], producer: u8) -> Block {
+        let store = StateStoreImpl::default();
+        for ancestor in ancestors {
+            store.apply_block(ancestor).unwrap();
+        }
+        let mut block = empty_block(ancestors.len() as u64 + 1);
+        block.header.parent_hash = ancestors.last().map(Block::hash).unwrap_or_default();
+        block.header.producer_id = test_id(producer);
+        store.seal_block(&mut block).unwrap();
+        sign_as(&mut block, producer);
+        block
+    }
+
+    fn temp_data_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "chaoschain-state-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn open_store(dir: &std::path::Path, snapshot_interval: u64) -> StateStoreImpl {
+        let storage = DiskStorage::open(dir)
+            .unwrap()
+            .with_snapshot_interval(snapshot_interval);
+        StateStoreImpl::with_storage(ChainConfig::default(), KeyManagerHandle::new(), storage)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_state_survives_restart() {
+        let dir = temp_data_dir("restart");
+        let (root, balances) = {
+            let store = open_store(&dir, 0);
+            for _ in 1..=3 {
+                store.apply_block(&on_head(&store, vec![])).unwrap();
+            }
+            (store.state_root(), store.get_state().balances)
+        };
+
+        let store = open_store(&dir, 0);
+        assert_eq!(store.state_root(), root);
+        assert_eq!(store.get_block_height(), 3);
+        assert_eq!(store.get_state().balances, balances);
+        assert_eq!(store.get_state().producers, vec![test_id(PRODUCER)]);
+        assert_eq!(store.get_latest_block().unwrap().header.height, 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_restart_from_snapshot_and_torn_log() {
+        let dir = temp_data_dir("snapshot");
+        let root = {
+            let store = open_store(&dir, 2);
+            for _ in 1..=3 {
+                store.apply_block(&on_head(&store, vec![])).unwrap();
+            }
+            store.state_root()
+        };
+        assert!(dir.join("state.snapshot").exists());
+
+        // A crash halfway through an append leaves a partial entry behind
+        let mut log = std::fs::OpenOptions::new()
+            .append(true)
+            .open(dir.join("blocks.log"))
+            .unwrap();
+        std::io::Write::write_all(&mut log, &[0xff, 0x00, 0x00]).unwrap();
+        drop(log);
+
+        let store = open_store(&dir, 2);
+        assert_eq!(store.state_root(), root);
+        assert_eq!(store.get_block_height(), 3);
+
+        // The store keeps appending after the torn entry was dropped
+        store.apply_block(&on_head(&store, vec![])).unwrap();
+        let root = store.state_root();
+        drop(store);
+        assert_eq!(open_store(&dir, 2).state_root(), root);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_history_survives_restart() {
+        let dir = temp_data_dir("history");
+        let (first, roots) = {
+            let store = open_store(&dir, 2);
+            let first = on_head(&store, vec![]);
+            store.apply_block(&first).unwrap();
+            let mut roots = vec![store.state_root()];
+            for _ in 2..=3 {
+                store.apply_block(&on_head(&store, vec![])).unwrap();
+                roots.push(store.state_root());
+            }
+            (first, roots)
+        };
+
+        // The snapshot folds in the first two blocks, whose undo entries come back from the log
+        let store = open_store(&dir, 2);
+        for height in 1..=3 {
+            assert_eq!(store.state_root_at(height).unwrap(), roots[height as usize - 1]);
+        }
+        let rival = branch_block(&[first], 7);
+        store.record_block(&rival).unwrap();
+        assert_eq!(store.reorg_to(&rival.hash()).unwrap().rolled_back.len(), 2);
+        assert_eq!(store.state_root_at(1).unwrap(), roots[0]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_appends_and_damaged_log_records() {
+        let dir = temp_data_dir("checksum");
+        let store = open_store(&dir, 0);
+        for _ in 1..=3 {
+            store.apply_block(&on_head(&store, vec![])).unwrap();
+        }
+        let root = store.state_root();
+
+        // A block the log refuses is taken back out of memory
+        let block = on_head(&store, vec![]);
+        store.storage.as_ref().unwrap().lock().fail_appends();
+        assert!(matches!(store.apply_block(&block), Err(StateError::Storage(_))));
+        assert_eq!(store.state_root(), root);
+        assert_eq!(store.get_block_height(), 3);
+        assert_eq!(store.undo_log.read().len(), 3);
+        assert!(store.get_block_diff(&block.hash()).is_none());
+        drop(store);
+
+        // A damaged final record is a torn write and is dropped on its own
+        let path = dir.join("blocks.log");
+        let mut bytes = std::fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+        assert_eq!(open_store(&dir, 0).get_block_height(), 2);
+
+        // Damage anywhere else stops the node instead of losing the blocks after it
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[40] ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+        let storage = DiskStorage::open(&dir).unwrap();
+        assert!(matches!(
+            StateStoreImpl::with_storage(ChainConfig::default(), KeyManagerHandle::new(), storage),
+            Err(StateError::Storage(_))
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_state_pruning() {
+        let key_manager = KeyManagerHandle::new();
+        let mut store = StateStoreImpl::new(ChainConfig::default(), key_manager).unwrap();
+
+        // Create test blocks
+        for _ in 1..=5 {
+            store.apply_block(&on_head(&store, vec![])).unwrap();
         }
 
         // Prune up to height 2
+        let root = store.state_root();
+        let balances = store.get_state().balances;
         store.prune_state(2).unwrap();
         
         // Verify blocks before height 2 are gone
         let blocks = store.blocks.read();
-        assert!(blocks.iter().all(|b| b.height > 2));
+        assert!(blocks.iter().all(|b| b.header.height > 2));
+        drop(blocks);
+
+        // Current state is exactly what it was
+        assert_eq!(store.state_root(), root);
+        assert_eq!(store.get_state().balances, balances);
+        assert_eq!(store.get_block_height(), 5);
+    }
+
+    #[test]
+    fn test_typed_reads_through_dyn_store() {
+        let store = StateStoreImpl::new(ChainConfig::default(), KeyManagerHandle::new()).unwrap();
+        for _ in 1..=10 {
+            store.apply_block(&on_head(&store, vec![])).unwrap();
+        }
+
+        let state: Arc<dyn StateStore> = Arc::new(store);
+        let producer_id = test_id(PRODUCER);
+        assert_eq!(state.get(&producer_key(&producer_id)).unwrap(), Some(vec![1]));
+        assert_eq!(state.get(b"producer:nobody").unwrap(), None);
+        assert!(state.is_producer(&producer_id).unwrap());
+        assert_eq!(state.producers().unwrap(), vec![producer_id]);
+
+        // Height 1 must not pick up the rewards for height 10
+        let rewards = state.rewards_at(1).unwrap();
+        assert_eq!(rewards.len(), 1);
+        let test_producer = test_id(PRODUCER);
+        assert_eq!(rewards[0].producer_id, test_producer);
+        assert_eq!(Some(rewards[0].reward.clone()), state.reward(1, &test_producer).unwrap());
+
+        let heights: Vec<u64> = state.rewards_for(&test_producer).unwrap().iter().map(|r| r.height).collect();
+        assert_eq!(heights, (1..=10).collect::<Vec<_>>());
+        assert!(state.rewards_for("someone-else").unwrap().is_empty());
+
+        // Scans run in byte order, so "reward:10:" sorts before "reward:1:"
+        let range = state.scan_range(b"reward:1", Some(b"reward:2")).unwrap();
+        let keys: Vec<_> = range.into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, vec![reward_key(10, &test_producer), reward_key(1, &test_producer)]);
+    }
+
+    #[test]
+    fn test_transfers_between_accounts() {
+        let alice_key = test_key(1);
+        let alice = hex::encode(alice_key.verifying_key().as_bytes());
+        let bob = [9u8; 32];
+        let config = ChainConfig {
+            genesis_balances: vec![GenesisAccount { account: alice.clone(), balance: 100 }],
+            ..ChainConfig::default()
+        };
+        let store = StateStoreImpl::new(config, KeyManagerHandle::new()).unwrap();
+        let transfer = |nonce: u64, amount: u64| signed_tx(&alice_key, nonce, TxKind::Transfer { to: bob, amount });
+
+        store.apply_block(&on_head(&store, vec![transfer(0, 30), transfer(1, 20)])).unwrap();
+        assert_eq!(store.get_account(&alice), Account { balance: 50, nonce: 2 });
+        assert_eq!(store.get_account(&hex::encode(bob)).balance, 50);
+
+        // Replayed and skipped nonces reject the whole block
+        let root = store.state_root();
+        for txs in [vec![transfer(1, 1)], vec![transfer(3, 1)]] {
+            assert!(store.apply_block(&on_head(&store, txs)).is_err());
+            assert_eq!(store.state_root(), root);
+        }
+        assert_eq!(store.get_account(&alice), Account { balance: 50, nonce: 2 });
+        assert_eq!(store.get_block_height(), 1);
+
+        // An overdraft fails on its own and says so in its receipt
+        let (paid, overdrawn) = (transfer(2, 10), transfer(3, 41));
+        let result = store.apply_block(&on_head(&store, vec![paid.clone(), overdrawn.clone()])).unwrap();
+        assert!(result.receipts[0].succeeded());
+        assert_eq!(result.receipts[0].touched.len(), 3);
+        assert!(matches!(result.receipts[1].status, TxStatus::Failed { .. }));
+        assert!(result.receipts[1].events.is_empty());
+        assert_eq!(store.get_account(&alice), Account { balance: 40, nonce: 4 });
+        assert_eq!(store.get_account(&hex::encode(bob)).balance, 60);
+
+        // Receipts are kept by transaction hash
+        assert_eq!(store.receipt(&paid.hash()).unwrap().as_ref(), Some(&result.receipts[0]));
+        assert_eq!(store.receipt(&overdrawn.hash()).unwrap().as_ref(), Some(&result.receipts[1]));
+        assert_eq!(
+            result.receipts[0].events,
+            vec![Event::Transfer { from: alice.clone(), to: hex::encode(bob), amount: 10 }]
+        );
+
+        // A sealed block commits to its receipts, and a forged commitment is refused
+        let mut sealed = on_head(&store, vec![transfer(4, 5)]);
+        let preview = store.seal_block(&mut sealed).unwrap();
+        sign(&mut sealed);
+        assert_eq!(sealed.header.receipts_root, receipts_root(&preview.receipts));
+        let mut forged = sealed.clone();
+        forged.header.receipts_root = [7u8; 32];
+        sign(&mut forged);
+        assert!(matches!(store.apply_block(&forged), Err(StateError::InvalidReceiptsRoot)));
+        assert_eq!(store.apply_block(&sealed).unwrap(), preview);
+        store.verify_invariants().unwrap();
+
+        // The mempool turns away nonces that are already used up
+        let stale = bincode::serialize(&transfer(1, 1)).unwrap();
+        assert!(matches!(
+            store.add_transaction(stale),
+            Err(StateError::InvalidNonce { expected: 5, got: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_block_index_tracks_competing_blocks() {
+        let store = StateStoreImpl::new(ChainConfig::default(), KeyManagerHandle::new()).unwrap();
+        store.apply_block(&on_head(&store, vec![])).unwrap();
+
+        let winner = on_head(&store, vec![]);
+        let mut rival = winner.clone();
+        sign_as(&mut rival, 7);
+
+        store.record_block(&rival).unwrap();
+        store.apply_block(&winner).unwrap();
+
+        assert_eq!(store.get_block_height(), 2);
+        assert_eq!(store.get_block_by_height(2).unwrap().hash(), winner.hash());
+        assert_eq!(store.get_block_by_hash(&rival.hash()).unwrap().header.producer_id, test_id(7));
+        assert_eq!(store.get_blocks_at_height(2).len(), 2);
+        assert!(store.is_canonical(&winner.hash()));
+        assert!(!store.is_canonical(&rival.hash()));
+
+        // Only children of the head apply, whether a block competes with it or skips past it
+        assert!(matches!(
+            store.apply_block(&rival),
+            Err(StateError::DoesNotExtendHead { height: 3, .. })
+        ));
+        let orphan = empty_block(4);
+        assert!(matches!(store.apply_block(&orphan), Err(StateError::DoesNotExtendHead { .. })));
+        assert_eq!(store.get_latest_block().unwrap().hash(), winner.hash());
+
+        // Proposals for another chain never reach the fork tree
+        let mut wrong_chain = empty_block(3);
+        wrong_chain.header.chain_id = "other-chain".to_string();
+        assert!(store.record_block(&wrong_chain).is_err());
+        assert_eq!(store.get_blocks_at_height(3).len(), 0);
+    }
+
+    #[test]
+    fn test_reorg_rolls_back_and_reapplies() {
+        let dir = temp_data_dir("reorg");
+        let store = open_store(&dir, 0);
+        let genesis = on_head(&store, vec![]);
+        store.apply_block(&genesis).unwrap();
+        let fork_root = store.state_root();
+        let chain = |producer: u8| {
+            let mut branch = vec![genesis.clone()];
+            for _ in 2..=3 {
+                branch.push(branch_block(&branch, producer));
+            }
+            branch.split_off(1)
+        };
+        let ours = chain(11);
+        let theirs = chain(12);
+        for block in &ours {
+            store.apply_block(block).unwrap();
+        }
+        for block in &theirs {
+            store.record_block(block).unwrap();
+        }
+        let our_root = store.state_root();
+        assert!(store.get_account(&test_id(11)).balance > 0);
+
+        let reorg = store.reorg_to(&theirs[1].hash()).unwrap();
+        assert_eq!(reorg.old_head, Some(ours[1].hash()));
+        assert_eq!(reorg.common_ancestor, Some(genesis.hash()));
+        assert_eq!(reorg.rolled_back, vec![ours[1].hash(), ours[0].hash()]);
+        assert_eq!(reorg.applied, vec![theirs[0].hash(), theirs[1].hash()]);
+        assert_eq!(store.get_account(&test_id(11)).balance, 0);
+        assert!(!store.is_producer(&test_id(11)).unwrap());
+        assert!(store.is_producer(&test_id(12)).unwrap());
+        assert!(store.is_canonical(&theirs[1].hash()));
+        assert!(!store.is_canonical(&ours[0].hash()));
+        let their_root = store.state_root();
+        assert_ne!(their_root, our_root);
+
+        // The rewritten log replays to the new chain
+        drop(store);
+        let store = open_store(&dir, 0);
+        assert_eq!(store.state_root(), their_root);
+        assert_eq!(store.get_latest_block().unwrap().hash(), theirs[1].hash());
+
+        // Rolling back to the fork point restores the state it had then
+        for block in &ours {
+            store.record_block(block).unwrap();
+        }
+        store.reorg_to(&genesis.hash()).unwrap();
+        assert_eq!(store.state_root(), fork_root);
+        assert_eq!(store.get_block_height(), 1);
+        store.reorg_to(&ours[1].hash()).unwrap();
+        assert_eq!(store.state_root(), our_root);
+
+        // A branch that fails to apply leaves the current chain in place
+        let mut bad = empty_block(2);
+        bad.header.parent_hash = genesis.hash();
+        bad.header.tx_root = [1u8; 32];
+        store.blocks.write().insert(bad.clone());
+        assert!(store.reorg_to(&bad.hash()).is_err());
+        assert_eq!(store.state_root(), our_root);
+        assert_eq!(store.get_latest_block().unwrap().hash(), ours[1].hash());
+        assert!(matches!(
+            store.reorg_to(&[7u8; 32]),
+            Err(StateError::UnknownBlock(_))
+        ));
+        store.verify_invariants().unwrap();
+
+        // A log that refuses the rewrite leaves memory on the chain the log still holds
+        store.record_block(&theirs[0]).unwrap();
+        store.storage.as_ref().unwrap().lock().fail_appends();
+        assert!(matches!(store.reorg_to(&theirs[0].hash()), Err(StateError::Storage(_))));
+        assert_eq!(store.state_root(), our_root);
+        assert_eq!(store.get_latest_block().unwrap().hash(), ours[1].hash());
+        assert!(store.get_block_diff(&theirs[0].hash()).is_none());
+        assert_eq!(store.undo_log.read().len(), 3);
+        drop(store);
+        let store = open_store(&dir, 0);
+        assert_eq!(store.state_root(), our_root);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_state_diffs_are_journaled_and_persisted() {
+        let dir = temp_data_dir("diffs");
+        let genesis_root = StateStoreImpl::default().state_root();
+        let diffs = {
+            let store = open_store(&dir, 2);
+            for _ in 1..=3 {
+                store.apply_block(&on_head(&store, vec![])).unwrap();
+            }
+            (1..=3).map(|height| store.get_state_diff(height).unwrap()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(diffs[0].prev_root, genesis_root);
+        assert_eq!(diffs[1].prev_root, diffs[0].new_root);
+        assert!(diffs[0].ops.iter().any(|op| matches!(
+            op,
+            StateOp::Set { key, .. } if *key == block_key(1)
+        )));
+
+        // Replaying the journal on a fresh store reaches the same state
+        let mut replica = StateStoreImpl::default();
+        for diff in &diffs {
+            replica.apply_diff(diff.clone()).unwrap();
+        }
+        assert_eq!(replica.state_root(), diffs[2].new_root);
+
+        // Diffs come back from the snapshot's log records and from replay alike
+        let store = open_store(&dir, 2);
+        for (height, diff) in (1..=3).zip(&diffs) {
+            assert_eq!(store.get_state_diff(height).as_ref(), Some(diff));
+        }
+        let head = store.get_latest_block().unwrap().hash();
+        assert_eq!(store.get_block_diff(&head).as_ref(), Some(&diffs[2]));
+        assert!(store.get_state_diff(4).is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_export_and_import() {
+        let export_dir = temp_data_dir("export");
+        let node_dir = temp_data_dir("from-snapshot");
+        let store = StateStoreImpl::default();
+        for _ in 1..=3 {
+            store.apply_block(&on_head(&store, vec![])).unwrap();
+        }
+        let parent = store.get_latest_block().unwrap().hash();
+
+        let manifest = store.export_snapshot(&export_dir, 2).unwrap();
+        assert!(manifest.chunks.len() > 1);
+        assert_eq!(manifest.state_root, hex::encode(store.state_root()));
+
+        let imported = StateStoreImpl::import_snapshot(
+            ChainConfig::default(),
+            KeyManagerHandle::new(),
+            &export_dir,
+            store.state_root(),
+        )
+        .unwrap();
+        let (original, restored) = (store.get_state(), imported.get_state());
+        assert_eq!(imported.state_root(), store.state_root());
+        assert_eq!(restored.balances, original.balances);
+        assert_eq!(restored.producers, original.producers);
+        assert_eq!(restored.height, 3);
+        assert_eq!(imported.get_latest_block().unwrap().hash(), parent);
+
+        // A node started from the snapshot keeps building on it across restarts
+        let node = imported.with_new_storage(DiskStorage::open(&node_dir).unwrap()).unwrap();
+        node.apply_block(&on_head(&node, vec![])).unwrap();
+        let root = node.state_root();
+        drop(node);
+        let node = open_store(&node_dir, 0);
+        assert_eq!(node.state_root(), root);
+        assert_eq!(node.get_block_height(), 4);
+        assert_eq!(node.get_block_by_height(3).unwrap().hash(), parent);
+        assert!(node.get_state_diff(3).is_none());
+        assert!(node.get_state_diff(4).is_some());
+
+        // Snapshots for another chain or with a damaged chunk are refused
+        let other_chain = ChainConfig {
+            chain_id: "other-chain".to_string(),
+            ..ChainConfig::default()
+        };
+        let root = store.state_root();
+        assert!(StateStoreImpl::import_snapshot(other_chain, KeyManagerHandle::new(), &export_dir, root).is_err());
+
+        // A whole snapshot of some other state is refused too, however consistent its manifest
+        let stale_root = store.state_root_at(2).unwrap();
+        assert!(matches!(
+            StateStoreImpl::import_snapshot(ChainConfig::default(), KeyManagerHandle::new(), &export_dir, stale_root),
+            Err(StateError::InvalidSnapshot(_))
+        ));
+
+        let chunk = export_dir.join(&manifest.chunks[1].file);
+        let mut bytes = std::fs::read(&chunk).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        std::fs::write(&chunk, bytes).unwrap();
+        assert!(matches!(
+            StateStoreImpl::import_snapshot(ChainConfig::default(), KeyManagerHandle::new(), &export_dir, root),
+            Err(StateError::InvalidSnapshot(_))
+        ));
+        std::fs::remove_dir_all(&export_dir).unwrap();
+        std::fs::remove_dir_all(&node_dir).unwrap();
+    }
+
+    #[test]
+    fn test_pruned_mode_matches_archive_state() {
+        let archive = StateStoreImpl::default();
+        let pruned = StateStoreImpl::default().with_pruning(PruningMode::Pruned { keep: 2 });
+        let mut chain = Vec::new();
+        for _ in 1..=6 {
+            let block = on_head(&archive, vec![]);
+            archive.apply_block(&block).unwrap();
+            pruned.apply_block(&block).unwrap();
+            assert_eq!(pruned.state_root(), archive.state_root());
+            chain.push(block);
+        }
+
+        // History beyond the last two blocks is gone from the pruned node only
+        assert!(pruned.get_block_by_height(4).is_none());
+        assert!(pruned.get_state_diff(4).is_none());
+        assert_eq!(pruned.get_block_by_height(5).unwrap().hash(), chain[4].hash());
+        assert!(archive.get_block_by_height(1).is_some());
+        assert!(archive.get_state_diff(1).is_some());
+        assert_eq!(pruned.undo_log.read().len(), 2);
+
+        // Committed blocks stay readable from current state either way
+        assert_eq!(pruned.get(&block_key(1)), archive.get(&block_key(1)));
+        assert_eq!(pruned.get_state().balances, archive.get_state().balances);
+
+        // The kept history still covers a reorg, but not one reaching further back
+        let rival = branch_block(&chain[..5], 7);
+        pruned.record_block(&rival).unwrap();
+        pruned.reorg_to(&rival.hash()).unwrap();
+        let mut deep = empty_block(4);
+        deep.header.parent_hash = chain[2].hash();
+        sign(&mut deep);
+        pruned.record_block(&deep).unwrap();
+        assert!(pruned.reorg_to(&deep.hash()).is_err());
+        assert_eq!(pruned.get_latest_block().unwrap().hash(), rival.hash());
+    }
+
+    #[test]
+    fn test_pruned_node_compacts_its_log() {
+        let dir = temp_data_dir("compact");
+        let log_len = || std::fs::metadata(dir.join("blocks.log")).unwrap().len();
+        let store = open_store(&dir, 3).with_pruning(PruningMode::Pruned { keep: 2 });
+        let mut lens = Vec::new();
+        for _ in 1..=9 {
+            store.apply_block(&on_head(&store, vec![])).unwrap();
+            lens.push(log_len());
+        }
+
+        // Each snapshot lets the log drop what it folds in, beyond the blocks still kept
+        assert!(lens[5] < lens[4]);
+        assert!(lens[8] < lens[7]);
+        assert!(lens[8] < lens[3]);
+        let (root, head) = (store.state_root(), store.get_latest_block().unwrap().hash());
+        drop(store);
+
+        // The compacted log still restarts to the same chain and kept history
+        let store = open_store(&dir, 3).with_pruning(PruningMode::Pruned { keep: 2 });
+        assert_eq!(store.state_root(), root);
+        assert_eq!(store.get_latest_block().unwrap().hash(), head);
+        assert!(store.get_block_by_height(8).is_some());
+        assert!(store.get_block_by_height(7).is_none());
+        store.apply_block(&on_head(&store, vec![])).unwrap();
+        assert_eq!(store.get_block_height(), 10);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_state_reads_at_past_heights() {
+        let store = StateStoreImpl::default().with_pruning(PruningMode::Pruned { keep: 3 });
+        let mut roots = vec![store.state_root()];
+        let producer = test_id(PRODUCER);
+        let mut balances = vec![store.get_account(&producer).balance];
+        for _ in 1..=5 {
+            store.apply_block(&on_head(&store, vec![])).unwrap();
+            roots.push(store.state_root());
+            balances.push(store.get_account(&producer).balance);
+        }
+
+        // Heights the pruned node still holds history for read as they stood then
+        for height in 2..=5 {
+            let h = height as usize;
+            assert_eq!(store.state_root_at(height).unwrap(), roots[h]);
+            assert_eq!(store.get_account_at(&producer, height).unwrap().balance, balances[h]);
+            assert_eq!(store.get_at(&block_key(height + 1), height).unwrap(), None);
+            assert!(store.get_at(&block_key(height), height).unwrap().is_some());
+
+            let key = account_key(&producer);
+            let (root, value, proof) = store.prove_at(&key, height).unwrap();
+            assert_eq!(root, roots[h]);
+            assert!(proof.verify(root, &key, value.as_deref()));
+            let (_, value, proof) = store.prove_at(&block_key(height + 1), height).unwrap();
+            assert!(value.is_none());
+            assert!(proof.verify(root, &block_key(height + 1), None));
+        }
+
+        // Current state is untouched by reading the past
+        assert_eq!(store.state_root(), roots[5]);
+
+        // Pruned history and future heights have nothing to answer with
+        assert!(matches!(store.state_root_at(1), Err(StateError::HistoryUnavailable(1))));
+        assert!(matches!(store.get_at(b"x", 6), Err(StateError::HistoryUnavailable(6))));
+    }
+
+    #[test]
+    fn test_simulated_block_commits_to_its_state_root() {
+        let store = StateStoreImpl::default();
+        let config = ChainConfig::default();
+        let post = TxKind::ChatPost { message: "Sealed with drama".to_string(), meme_url: None };
+        let mut block = block_with(1, vec![signed_tx(&test_key(1), 0, post)]);
+        let before = store.state_root();
+
+        // Sealing leaves state alone and predicts what applying does
+        let preview = store.seal_block(&mut block).unwrap();
+        sign(&mut block);
+        assert_ne!(block.header.receipts_root, [0u8; 32]);
+        assert_eq!(store.state_root(), before);
+        assert_eq!(store.get_block_height(), 0);
+        assert_eq!(preview.diff.prev_root, before);
+        assert_eq!(preview.reward.reward, block_reward(&config, &block));
+        assert_eq!(
+            preview.reward.reward.drama_bonus,
+            (block.header.drama_level as f64 * config.drama_reward_multiplier) as u64
+        );
+        assert_eq!(preview.reward.reward.drama_level, block.header.drama_level);
+
+        // Wrong or missing roots are refused without touching state
+        for (receipts_root, state_root) in [
+            (block.header.receipts_root, [1u8; 32]),
+            (block.header.receipts_root, [0u8; 32]),
+            ([1u8; 32], block.header.state_root),
+            ([0u8; 32], block.header.state_root),
+            ([0u8; 32], [0u8; 32]),
+        ] {
+            let mut wrong = block.clone();
+            wrong.header.receipts_root = receipts_root;
+            wrong.header.state_root = state_root;
+            sign(&mut wrong);
+            let refused = store.apply_block(&wrong);
+            assert!(matches!(refused, Err(StateError::InvalidStateRoot | StateError::InvalidReceiptsRoot)));
+            assert_eq!(store.state_root(), before);
+            assert_eq!(store.get_block_height(), 0);
+        }
+
+        // The sealed block applies to exactly what sealing predicted
+        let result = store.apply_block(&block).unwrap();
+        assert_eq!(result, preview);
+        assert_eq!(store.state_root(), preview.state_root());
+        assert_eq!(store.reward(1, &test_id(PRODUCER)).unwrap(), Some(result.reward.reward));
+    }
+
+    #[test]
+    fn test_invalid_genesis_is_refused() {
+        let overflowing = ChainConfig {
+            validators: vec![validator(&test_id(2))],
+            genesis_balances: vec![GenesisAccount { account: test_id(1), balance: u64::MAX }],
+            genesis_stakes: vec![GenesisAccount { account: test_id(2), balance: 1 }],
+            ..ChainConfig::default()
+        };
+        assert!(matches!(
+            StateStoreImpl::new(overflowing, KeyManagerHandle::new()),
+            Err(StateError::Core(CoreError::ChainSpec(_)))
+        ));
+
+        // A second stake for the same account would silently replace the first
+        let stake = GenesisAccount { account: test_id(1), balance: 10 };
+        let double_staked = ChainConfig {
+            validators: vec![validator(&test_id(1))],
+            genesis_stakes: vec![stake.clone(), stake],
+            ..ChainConfig::default()
+        };
+        assert!(StateStoreImpl::new(double_staked, KeyManagerHandle::new()).is_err());
+
+        // Stake belongs to validators and validators need stake
+        let stranger_staked = ChainConfig {
+            genesis_stakes: vec![GenesisAccount { account: test_id(1), balance: 10 }],
+            ..ChainConfig::default()
+        };
+        assert!(StateStoreImpl::new(stranger_staked, KeyManagerHandle::new()).is_err());
+        let unstaked = ChainConfig {
+            validators: vec![validator(&test_id(1))],
+            ..ChainConfig::default()
+        };
+        assert!(StateStoreImpl::new(unstaked, KeyManagerHandle::new()).is_err());
+    }
+
+    #[test]
+    fn test_supply_tracks_issuance() {
+        let config = ChainConfig {
+            genesis_balances: vec![GenesisAccount { account: test_id(2), balance: 1000 }],
+            ..ChainConfig::default()
+        };
+        let store = StateStoreImpl::new(config, KeyManagerHandle::new())
+            .unwrap()
+            .with_invariant_checks(true);
+        assert_eq!(store.verify_invariants().unwrap().genesis, 1000);
+
+        let mut minted = 0;
+        for _ in 1..=3 {
+            minted += store.apply_block(&on_head(&store, vec![])).unwrap().reward.reward.total;
+        }
+        let supply = store.verify_invariants().unwrap();
+        assert_eq!(supply, store.supply().unwrap());
+        assert_eq!(supply.total, 1000 + minted);
+        assert_eq!(supply.base_rewards, 3 * ChainConfig::default().base_block_reward);
+
+        // Tokens appearing from nowhere stop the next block
+        store.merkle_tree.write().insert(&account_key("thief"), &Account { balance: 1, nonce: 0 }.encode());
+        let root = store.state_root();
+        assert!(matches!(
+            store.apply_block(&on_head(&store, vec![])),
+            Err(StateError::InvariantViolation(_))
+        ));
+        assert_eq!(store.state_root(), root);
+        assert_eq!(store.get_block_height(), 3);
+    }
+
+    /// Slashes one validator by a fixed amount in every block at a given height
+    #[derive(Debug)]
+    struct SlashAt {
+        height: u64,
+        validator: String,
+        amount: u64,
+    }
+
+    impl SlashingHook for SlashAt {
+        fn slashes(&self, block: &Block) -> Vec<Slash> {
+            if block.header.height != self.height {
+                return Vec::new();
+            }
+            vec![Slash {
+                validator: self.validator.clone(),
+                amount: self.amount,
+                reason: "double vote".to_string(),
+            }]
+        }
+    }
+
+    #[test]
+    fn test_staking_unbonding_and_slashing() {
+        let alice_key = test_key(1);
+        let alice = hex::encode(alice_key.verifying_key().as_bytes());
+        let staker = test_id(2);
+        let config = ChainConfig {
+            validators: vec![validator(&staker)],
+            genesis_balances: vec![GenesisAccount { account: alice.clone(), balance: 100 }],
+            genesis_stakes: vec![GenesisAccount { account: staker.clone(), balance: 500 }],
+            unbonding_delay: 2,
+            ..ChainConfig::default()
+        };
+        let hook = SlashAt { height: 3, validator: alice.clone(), amount: 25 };
+        let store = StateStoreImpl::new(config, KeyManagerHandle::new())
+            .unwrap()
+            .with_invariant_checks(true)
+            .with_slashing_hook(Arc::new(hook));
+        assert_eq!(store.stake(&staker).unwrap().bonded, 500);
+        assert_eq!(store.verify_invariants().unwrap().genesis, 600);
+
+        let signed = |nonce: u64, kind: TxKind| signed_tx(&alice_key, nonce, kind);
+
+        // Bonding locks balance up as stake at once, unbonding takes it out of the bond at once
+        let bond_and_unbond = vec![signed(0, TxKind::Stake { amount: 80 }), signed(1, TxKind::Unstake { amount: 30 })];
+        let result = store.apply_block(&on_head(&store, bond_and_unbond)).unwrap();
+        assert_eq!(result.receipts[0].events, vec![Event::Bonded { sender: alice.clone(), amount: 80 }]);
+        assert!(result.receipts[1].touched.contains(&stake_key(&alice)));
+        assert_eq!(store.get_account(&alice).balance, 20);
+        assert_eq!(
+            store.stake(&alice).unwrap(),
+            Stake { bonded: 50, unbonding: vec![Unbonding { amount: 30, release_height: 3 }] }
+        );
+
+        // Unbonding past the bond fails on its own
+        let result = store.apply_block(&on_head(&store, vec![signed(2, TxKind::Unstake { amount: 51 })])).unwrap();
+        assert!(matches!(result.receipts[0].status, TxStatus::Failed { .. }));
+
+        // The block that releases the unbonding stake also slashes, and the bond pays first
+        let result = store.apply_block(&on_head(&store, vec![])).unwrap();
+        assert_eq!(result.slashes[0].amount, 25);
+        assert_eq!(store.get_account(&alice).balance, 50);
+        assert_eq!(store.stake(&alice).unwrap(), Stake { bonded: 25, unbonding: Vec::new() });
+        let supply = store.verify_invariants().unwrap();
+        assert_eq!(supply.slashed, 25);
+        assert_eq!(store.stakes().unwrap().len(), 2);
+        let validators = store.create_snapshot().unwrap().metadata.validators;
+        assert_eq!(validators, HashMap::from([(staker.clone(), 500), (alice.clone(), 25)]));
+
+        // Stake unbonded in full leaves no entry behind once released
+        store.apply_block(&on_head(&store, vec![signed(3, TxKind::Unstake { amount: 25 })])).unwrap();
+        for _ in 5..=6 {
+            store.apply_block(&on_head(&store, vec![])).unwrap();
+        }
+        assert_eq!(store.stakes().unwrap().len(), 1);
+        assert_eq!(store.get_account(&alice).balance, 75);
+        store.verify_invariants().unwrap();
+    }
+
+    #[test]
+    fn test_external_keys_sign_and_register() {
+        // A wallet no node generated or imported a key for
+        let wallet = SigningKey::generate(&mut rand::rngs::OsRng);
+        let wallet_id = hex::encode(wallet.verifying_key().as_bytes());
+        let register = signed_tx(&wallet, 0, TxKind::RegisterAgent {
+            name: "Outsider".to_string(),
+            role: "validator".to_string(),
+        });
+
+        let dir = temp_data_dir("external-keys");
+        {
+            let store = open_store(&dir, 1);
+            store.add_transaction(bincode::serialize(&register).unwrap()).unwrap();
+
+            // A signature by anyone but the sender is refused
+            let mut forged = register.clone();
+            forged.signature = SigningKey::generate(&mut rand::rngs::OsRng).sign(&forged.signing_bytes()).to_bytes();
+            assert!(matches!(
+                store.add_transaction(bincode::serialize(&forged).unwrap()),
+                Err(StateError::InvalidSignature(_))
+            ));
+
+            let result = store.apply_block(&on_head(&store, vec![register])).unwrap();
+            assert!(result.receipts[0].touched.contains(&agent_key(&wallet_id)));
+            assert_eq!(store.agent(&wallet_id).unwrap().unwrap().name, "Outsider");
+            assert_eq!(store.agents().unwrap().len(), 1);
+
+            // Once registered, its key verifies by agent ID on this node
+            let signature = wallet.sign(b"drama").to_bytes();
+            assert!(store.key_manager.inner().verify(&wallet_id, b"drama", &signature).unwrap());
+        }
+
+        // A node restarting with an empty key manager learns it again from state
+        let store = open_store(&dir, 1);
+        assert!(store.key_manager.inner().registry().contains(&wallet_id));
+        assert!(store.key_manager.inner().get_agent(&wallet_id).is_none());
+        let _ = std::fs::remove_dir_all(&dir);
     }
 } 
\ No newline at end of file
@@ -1,156 +1,384 @@
+//! Sparse merkle tree over the state key space.
+//!
+//! Keys are hashed to 256-bit paths and placed in a binary tree by the bits of
+//! their path. A subtree holding a single leaf is stored as that leaf, so the
+//! tree stays about log2(n) deep, and every inner node caches its hash. An
+//! update only rehashes the nodes on its own path, and the root is read in
+//! constant time.
+//!
+//! Walking a key's path always ends at either its own leaf, another key's
+//! leaf or an empty subtree, so the same proof format shows both that a key
+//! is present with some value and that it is absent.
+
+use serde::{Deserialize, Serialize};
 use sha2::{Sha256, Digest};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+/// Hash of an empty subtree
+const EMPTY_HASH: [u8; 32] = [0u8; 32];
+
+fn key_path(key: &[u8]) -> [u8; 32] {
+    Sha256::digest(key).into()
+}
+
+fn value_hash(value: &[u8]) -> [u8; 32] {
+    Sha256::digest(value).into()
+}
+
+fn hash_leaf(path: &[u8; 32], value_hash: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"leaf");
+    hasher.update(path);
+    hasher.update(value_hash);
+    hasher.finalize().into()
+}
+
+fn hash_nodes(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"node");
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Bit of `path` at `depth`, most significant bit first
+fn bit(path: &[u8; 32], depth: usize) -> bool {
+    path[depth / 8] & (0x80 >> (depth % 8)) != 0
+}
+
+/// Whether two paths agree on their first `depth` bits
+fn shares_prefix(a: &[u8; 32], b: &[u8; 32], depth: usize) -> bool {
+    (0..depth).all(|d| bit(a, d) == bit(b, d))
+}
+
+/// Fold siblings, listed from the bottom up, over the node at depth `siblings.len()`
+fn fold_siblings(path: &[u8; 32], node: [u8; 32], siblings: &[[u8; 32]]) -> [u8; 32] {
+    siblings.iter().enumerate().fold(node, |hash, (i, sibling)| {
+        if bit(path, siblings.len() - 1 - i) {
+            hash_nodes(sibling, &hash)
+        } else {
+            hash_nodes(&hash, sibling)
+        }
+    })
+}
 
 /// Merkle tree node
 #[derive(Debug, Clone)]
-pub struct Node {
-    /// Node hash
-    pub hash: [u8; 32],
-    /// Left child hash
-    #[allow(dead_code)]
-    pub left: Option<Box<Node>>,
-    /// Right child hash
-    #[allow(dead_code)]
-    pub right: Option<Box<Node>>,
-    pub value: Option<Vec<u8>>,
+enum Node {
+    Empty,
+    Leaf {
+        path: [u8; 32],
+        value_hash: [u8; 32],
+        hash: [u8; 32],
+    },
+    Internal {
+        left: Box<Node>,
+        right: Box<Node>,
+        hash: [u8; 32],
+    },
 }
 
 impl Node {
-    /// Create a new leaf node
-    pub fn new_leaf(key: &[u8], value: &[u8]) -> Self {
-        let mut hasher = Sha256::new();
-        hasher.update(key);
-        hasher.update(value);
-        Self {
-            hash: hasher.finalize().into(),
-            left: None,
-            right: None,
-            value: Some(value.to_vec()),
+    fn hash(&self) -> [u8; 32] {
+        match self {
+            Node::Empty => EMPTY_HASH,
+            Node::Leaf { hash, .. } | Node::Internal { hash, .. } => *hash,
         }
     }
 
-    /// Create a new internal node
-    pub fn new_internal(left: Node, right: Node) -> Self {
-        let mut hasher = Sha256::new();
-        hasher.update(&left.hash);
-        hasher.update(&right.hash);
-        Self {
-            hash: hasher.finalize().into(),
-            left: Some(Box::new(left)),
-            right: Some(Box::new(right)),
-            value: None,
+    fn leaf(path: [u8; 32], value_hash: [u8; 32]) -> Node {
+        Node::Leaf {
+            path,
+            value_hash,
+            hash: hash_leaf(&path, &value_hash),
         }
     }
-}
-
-/// Merkle tree for state management
-#[derive(Debug)]
-pub struct MerkleTree {
-    /// Leaf nodes store transaction/state data
-    leaves: HashMap<Vec<u8>, Vec<u8>>,
-    /// Cache of computed node hashes
-    nodes: HashMap<Vec<u8>, [u8; 32]>,
-}
 
-impl MerkleTree {
-    /// Create a new empty merkle tree
-    pub fn new() -> Self {
-        Self {
-            leaves: HashMap::new(),
-            nodes: HashMap::new(),
+    /// What a proof ending at this node reveals
+    fn terminal(&self) -> ProofTerminal {
+        match self {
+            Node::Leaf { path, value_hash, .. } => ProofTerminal::Leaf {
+                path: *path,
+                value_hash: *value_hash,
+            },
+            _ => ProofTerminal::Empty,
         }
     }
 
-    /// Insert a key-value pair
-    pub fn insert(&mut self, key: &[u8], value: &[u8]) {
-        self.leaves.insert(key.to_vec(), value.to_vec());
-        self.nodes.clear(); // Clear cache as tree has changed
+    fn internal(left: Node, right: Node) -> Node {
+        let hash = hash_nodes(&left.hash(), &right.hash());
+        Node::Internal {
+            left: Box::new(left),
+            right: Box::new(right),
+            hash,
+        }
     }
 
-    /// Delete a key
-    pub fn delete(&mut self, key: &[u8]) {
-        self.leaves.remove(key);
-        self.rebuild();
+    /// Build the subtree at `depth` holding exactly two leaves
+    fn split(depth: usize, existing: Node, new_leaf: Node) -> Node {
+        let (existing_path, new_path) = match (&existing, &new_leaf) {
+            (Node::Leaf { path: a, .. }, Node::Leaf { path: b, .. }) => (*a, *b),
+            _ => unreachable!("only leaves are split"),
+        };
+
+        match (bit(&existing_path, depth), bit(&new_path, depth)) {
+            (false, true) => Node::internal(existing, new_leaf),
+            (true, false) => Node::internal(new_leaf, existing),
+            (false, false) => Node::internal(Self::split(depth + 1, existing, new_leaf), Node::Empty),
+            (true, true) => Node::internal(Node::Empty, Self::split(depth + 1, existing, new_leaf)),
+        }
     }
 
-    /// Get current root hash
-    pub fn root_hash(&self) -> [u8; 32] {
-        if self.leaves.is_empty() {
-            return [0u8; 32];
+    fn insert(self, depth: usize, new_leaf: Node) -> Node {
+        let path = match &new_leaf {
+            Node::Leaf { path, .. } => *path,
+            _ => unreachable!("only leaves are inserted"),
+        };
+        match self {
+            Node::Empty => new_leaf,
+            Node::Leaf { path: existing, .. } if existing == path => new_leaf,
+            leaf @ Node::Leaf { .. } => Self::split(depth, leaf, new_leaf),
+            Node::Internal { left, right, .. } => {
+                if bit(&path, depth) {
+                    Node::internal(*left, right.insert(depth + 1, new_leaf))
+                } else {
+                    Node::internal(left.insert(depth + 1, new_leaf), *right)
+                }
+            }
         }
+    }
 
-        // Sort leaves for deterministic ordering
-        let mut sorted_leaves: Vec<_> = self.leaves.iter().collect();
-        sorted_leaves.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
+    fn remove(self, depth: usize, path: &[u8; 32]) -> Node {
+        match self {
+            Node::Leaf { path: existing, .. } if &existing == path => Node::Empty,
+            Node::Internal { left, right, .. } => {
+                let (left, right) = if bit(path, depth) {
+                    (*left, right.remove(depth + 1, path))
+                } else {
+                    (left.remove(depth + 1, path), *right)
+                };
+                // A lone leaf moves up to take the place of its parent
+                match (left, right) {
+                    (Node::Empty, Node::Empty) => Node::Empty,
+                    (Node::Empty, leaf @ Node::Leaf { .. }) | (leaf @ Node::Leaf { .. }, Node::Empty) => leaf,
+                    (left, right) => Node::internal(left, right),
+                }
+            }
+            other => other,
+        }
+    }
 
-        // Build tree bottom-up
-        let mut current_level: Vec<[u8; 32]> = sorted_leaves
-            .iter()
-            .map(|(k, v)| self.hash_leaf(k, v))
-            .collect();
+    /// Walk `path` to where it ends, collecting sibling hashes from the bottom up
+    fn proof(&self, depth: usize, path: &[u8; 32], siblings: &mut Vec<[u8; 32]>) -> ProofTerminal {
+        match self {
+            Node::Internal { left, right, .. } => {
+                let (next, sibling) = if bit(path, depth) { (right, left) } else { (left, right) };
+                let terminal = next.proof(depth + 1, path, siblings);
+                siblings.push(sibling.hash());
+                terminal
+            }
+            other => other.terminal(),
+        }
+    }
 
-        while current_level.len() > 1 {
-            let mut next_level = Vec::new();
-            for chunk in current_level.chunks(2) {
-                match chunk {
-                    [left, right] => next_level.push(self.hash_nodes(left, right)),
-                    [single] => next_level.push(*single),
-                    _ => unreachable!(),
+    /// Walk several sorted paths at once, in depth-first order
+    fn multi_proof(&self, depth: usize, paths: &[[u8; 32]], proof: &mut MultiProof) {
+        match self {
+            Node::Internal { left, right, .. } => {
+                let split = paths.partition_point(|path| !bit(path, depth));
+                let (left_paths, right_paths) = paths.split_at(split);
+                if left_paths.is_empty() {
+                    proof.siblings.push(left.hash());
+                } else {
+                    left.multi_proof(depth + 1, left_paths, proof);
+                }
+                if right_paths.is_empty() {
+                    proof.siblings.push(right.hash());
+                } else {
+                    right.multi_proof(depth + 1, right_paths, proof);
                 }
             }
-            current_level = next_level;
+            other => proof.terminals.push((depth as u16, other.terminal())),
         }
+    }
+}
+
+/// Where a key's path through the tree ends
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProofTerminal {
+    /// An empty subtree, so the key is absent
+    Empty,
+    /// A leaf, which is the key itself if the path matches and another key otherwise
+    Leaf {
+        path: [u8; 32],
+        value_hash: [u8; 32],
+    },
+}
 
-        current_level[0]
+impl ProofTerminal {
+    fn hash(&self) -> [u8; 32] {
+        match self {
+            ProofTerminal::Empty => EMPTY_HASH,
+            ProofTerminal::Leaf { path, value_hash } => hash_leaf(path, value_hash),
+        }
     }
 
-    fn hash_leaf(&self, key: &[u8], value: &[u8]) -> [u8; 32] {
-        let mut hasher = Sha256::new();
-        hasher.update(b"leaf");
-        hasher.update(key);
-        hasher.update(value);
-        hasher.finalize().into()
+    /// Whether this terminal shows `path` holding `value`, or being absent for `None`
+    fn proves(&self, path: &[u8; 32], value: Option<&[u8]>) -> bool {
+        match (self, value) {
+            (ProofTerminal::Leaf { path: leaf_path, value_hash: leaf_value }, Some(value)) => {
+                leaf_path == path && *leaf_value == value_hash(value)
+            }
+            (ProofTerminal::Leaf { path: leaf_path, .. }, None) => leaf_path != path,
+            (ProofTerminal::Empty, value) => value.is_none(),
+        }
     }
+}
 
-    fn hash_nodes(&self, left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
-        let mut hasher = Sha256::new();
-        hasher.update(b"node");
-        hasher.update(left);
-        hasher.update(right);
-        hasher.finalize().into()
+/// Proof that a key holds a value, or that it is absent, under a state root
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateProof {
+    /// Sibling hashes from where the key's path ends up to the root
+    pub siblings: Vec<[u8; 32]>,
+    /// Where the key's path ends
+    pub terminal: ProofTerminal,
+}
+
+impl StateProof {
+    /// Check that `key` holds `value` under `root`, or is absent if `value` is `None`
+    pub fn verify(&self, root: [u8; 32], key: &[u8], value: Option<&[u8]>) -> bool {
+        let depth = self.siblings.len();
+        if depth > 256 {
+            return false;
+        }
+        let path = key_path(key);
+
+        // A leaf can only sit on a path that shares its prefix
+        if let ProofTerminal::Leaf { path: leaf_path, .. } = &self.terminal {
+            if !shares_prefix(leaf_path, &path, depth) {
+                return false;
+            }
+        }
+
+        self.terminal.proves(&path, value)
+            && fold_siblings(&path, self.terminal.hash(), &self.siblings) == root
     }
+}
+
+/// Compact proof for several keys that shares the nodes their paths have in common
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MultiProof {
+    /// Hashes of subtrees no proven path enters, in depth-first order
+    pub siblings: Vec<[u8; 32]>,
+    /// Depth and contents of every node where proven paths end, in depth-first order
+    pub terminals: Vec<(u16, ProofTerminal)>,
+}
 
-    /// Rebuild the merkle tree
-    fn rebuild(&mut self) {
-        if self.leaves.is_empty() {
-            self.nodes.clear();
-            return;
+impl MultiProof {
+    /// Check every `(key, value)` under `root`, where a `None` value claims the key is absent
+    pub fn verify(&self, root: [u8; 32], entries: &[(&[u8], Option<&[u8]>)]) -> bool {
+        let mut paths: Vec<[u8; 32]> = entries.iter().map(|(key, _)| key_path(key)).collect();
+        paths.sort_unstable();
+        paths.dedup();
+        if paths.is_empty() {
+            return false;
         }
 
-        // Sort leaves by key for deterministic ordering
-        let mut sorted_leaves: Vec<_> = self.leaves.iter().collect();
-        sorted_leaves.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
-        
-        // Build tree bottom-up
-        let mut current_level: Vec<[u8; 32]> = sorted_leaves
-            .iter()
-            .map(|(k, v)| self.hash_leaf(k, v))
-            .collect();
+        let mut siblings = self.siblings.iter();
+        let mut terminals = self.terminals.iter().peekable();
+        let mut reached = Vec::new();
+        let computed = Self::rebuild(0, &paths, &mut siblings, &mut terminals, &mut reached);
+        if computed != Some(root) || siblings.next().is_some() || terminals.next().is_some() {
+            return false;
+        }
 
-        while current_level.len() > 1 {
-            let mut next_level = Vec::new();
-            for chunk in current_level.chunks(2) {
-                match chunk {
-                    [left, right] => next_level.push(self.hash_nodes(left, right)),
-                    [single] => next_level.push(*single),
-                    _ => unreachable!(),
+        entries.iter().all(|(key, value)| {
+            let path = key_path(key);
+            reached
+                .iter()
+                .find(|(paths, _)| paths.contains(&path))
+                .is_some_and(|(_, terminal)| terminal.proves(&path, *value))
+        })
+    }
+
+    /// Recompute the hash of the subtree at `depth` that `paths` run through
+    fn rebuild<'a>(
+        depth: usize,
+        paths: &[[u8; 32]],
+        siblings: &mut std::slice::Iter<'a, [u8; 32]>,
+        terminals: &mut std::iter::Peekable<std::slice::Iter<'a, (u16, ProofTerminal)>>,
+        reached: &mut Vec<(Vec<[u8; 32]>, &'a ProofTerminal)>,
+    ) -> Option<[u8; 32]> {
+        if let Some((_, terminal)) = terminals.next_if(|(at, _)| *at as usize == depth) {
+            if let ProofTerminal::Leaf { path: leaf_path, .. } = terminal {
+                if !shares_prefix(leaf_path, &paths[0], depth) {
+                    return None;
                 }
             }
-            current_level = next_level;
+            reached.push((paths.to_vec(), terminal));
+            return Some(terminal.hash());
         }
-        
-        self.nodes.clear();
-        self.nodes.insert(b"root".to_vec(), current_level[0]);
+        if depth >= 256 {
+            return None;
+        }
+
+        let split = paths.partition_point(|path| !bit(path, depth));
+        let (left_paths, right_paths) = paths.split_at(split);
+        let left = if left_paths.is_empty() {
+            *siblings.next()?
+        } else {
+            Self::rebuild(depth + 1, left_paths, siblings, terminals, reached)?
+        };
+        let right = if right_paths.is_empty() {
+            *siblings.next()?
+        } else {
+            Self::rebuild(depth + 1, right_paths, siblings, terminals, reached)?
+        };
+        Some(hash_nodes(&left, &right))
+    }
+}
+
+/// Merkle tree for state management
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    /// Leaf values by key, ordered for range scans
+    leaves: BTreeMap<Vec<u8>, Vec<u8>>,
+    /// Root of the sparse tree over hashed keys
+    root: Node,
+}
+
+impl Default for MerkleTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MerkleTree {
+    /// Create a new empty merkle tree
+    pub fn new() -> Self {
+        Self {
+            leaves: BTreeMap::new(),
+            root: Node::Empty,
+        }
+    }
+
+    /// Insert a key-value pair
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) {
+        let leaf = Node::leaf(key_path(key), value_hash(value));
+        let root = std::mem::replace(&mut self.root, Node::Empty);
+        self.root = root.insert(0, leaf);
+        self.leaves.insert(key.to_vec(), value.to_vec());
+    }
+
+    /// Delete a key
+    pub fn delete(&mut self, key: &[u8]) {
+        self.remove(key);
+    }
+
+    /// Get current root hash
+    pub fn root_hash(&self) -> [u8; 32] {
+        self.root.hash()
     }
 
     /// Generate merkle proof for a key
@@ -158,49 +386,27 @@ impl MerkleTree {
         if !self.leaves.contains_key(key) {
             return None;
         }
+        Some(self.prove(key).siblings)
+    }
 
-        let mut sorted_leaves: Vec<_> = self.leaves.iter().collect();
-        sorted_leaves.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
-
-        let mut proof = Vec::new();
-        let mut current_level: Vec<([u8; 32], usize)> = sorted_leaves
-            .iter()
-            .enumerate()
-            .map(|(i, (k, v))| (self.hash_leaf(k, v), i))
-            .collect();
+    /// Prove that a key is present with its current value, or that it is absent
+    pub fn prove(&self, key: &[u8]) -> StateProof {
+        let mut siblings = Vec::new();
+        let terminal = self.root.proof(0, &key_path(key), &mut siblings);
+        StateProof { siblings, terminal }
+    }
 
-        let target_hash = self.hash_leaf(key, &self.leaves[key]);
-        let mut current_index = current_level
-            .iter()
-            .position(|(hash, _)| *hash == target_hash)
-            .unwrap();
+    /// Prove the presence or absence of several keys at once
+    pub fn prove_many(&self, keys: &[&[u8]]) -> MultiProof {
+        let mut paths: Vec<[u8; 32]> = keys.iter().map(|key| key_path(key)).collect();
+        paths.sort_unstable();
+        paths.dedup();
 
-        while current_level.len() > 1 {
-            let mut next_level = Vec::new();
-            for chunk in current_level.chunks(2) {
-                match chunk {
-                    [(left_hash, left_idx), (right_hash, right_idx)] => {
-                        if current_index == *left_idx {
-                            proof.push(*right_hash);
-                        } else if current_index == *right_idx {
-                            proof.push(*left_hash);
-                        }
-                        next_level.push((
-                            self.hash_nodes(left_hash, right_hash),
-                            left_idx / 2
-                        ));
-                    }
-                    [(single_hash, single_idx)] => {
-                        next_level.push((*single_hash, *single_idx));
-                    }
-                    _ => unreachable!(),
-                }
-            }
-            current_level = next_level;
-            current_index /= 2;
+        let mut proof = MultiProof::default();
+        if !paths.is_empty() {
+            self.root.multi_proof(0, &paths, &mut proof);
         }
-
-        Some(proof)
+        proof
     }
 
     /// Verify a merkle proof
@@ -210,40 +416,21 @@ impl MerkleTree {
         value: &[u8],
         proof: &[[u8; 32]]
     ) -> bool {
-        let mut current_hash: [u8; 32] = {
-            let mut hasher = Sha256::new();
-            hasher.update(b"leaf");
-            hasher.update(key);
-            hasher.update(value);
-            hasher.finalize().into()
+        let path = key_path(key);
+        let terminal = ProofTerminal::Leaf {
+            path,
+            value_hash: value_hash(value),
         };
-
-        for sibling in proof {
-            let mut hasher = Sha256::new();
-            hasher.update(b"node");
-            
-            // Try both orderings
-            hasher.update(&current_hash);
-            hasher.update(sibling);
-            let hash1: [u8; 32] = hasher.finalize().into();
-            
-            let mut hasher = Sha256::new();
-            hasher.update(b"node");
-            hasher.update(sibling);
-            hasher.update(&current_hash);
-            let hash2: [u8; 32] = hasher.finalize().into();
-            
-            // Use whichever ordering matches the root
-            if hash1 == root_hash {
-                current_hash = hash1;
-                break;
-            } else if hash2 == root_hash {
-                current_hash = hash2;
-                break;
-            }
+        StateProof {
+            siblings: proof.to_vec(),
+            terminal,
         }
+        .verify(root_hash, key, Some(value))
+    }
 
-        current_hash == root_hash
+    /// Every entry in key order
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, &Vec<u8>)> {
+        self.leaves.iter()
     }
 
     /// Get all keys in the tree
@@ -254,21 +441,42 @@ impl MerkleTree {
     /// Clear all entries from the tree
     pub fn clear(&mut self) {
         self.leaves.clear();
-        self.nodes.clear();
+        self.root = Node::Empty;
     }
 
     /// Remove a key from the tree
-    #[allow(dead_code)]
     pub fn remove(&mut self, key: &[u8]) -> Option<Vec<u8>> {
-        let value = self.leaves.remove(key);
-        self.rebuild();
-        value
+        let value = self.leaves.remove(key)?;
+        let root = std::mem::replace(&mut self.root, Node::Empty);
+        self.root = root.remove(0, &key_path(key));
+        Some(value)
     }
 
     /// Get value for a key
     pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
         self.leaves.get(key).cloned()
     }
+
+    /// Entries with keys in `start..end` in key order, or `start..` if `end` is `None`
+    pub fn range(&self, start: &[u8], end: Option<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let upper = match end {
+            Some(end) => Bound::Excluded(end),
+            None => Bound::Unbounded,
+        };
+        self.leaves
+            .range::<[u8], _>((Bound::Included(start), upper))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    /// Entries whose keys start with `prefix`, in key order
+    pub fn scan_prefix(&self, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.leaves
+            .range::<[u8], _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -331,4 +539,157 @@ mod tests {
         tree.delete(key);
         assert_eq!(tree.leaves.len(), 0);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_root_is_independent_of_history() {
+        let keys: Vec<Vec<u8>> = (0..200u32).map(|i| format!("agent:{}", i).into_bytes()).collect();
+
+        let mut forward = MerkleTree::new();
+        for key in &keys {
+            forward.insert(key, b"x");
+        }
+        let mut backward = MerkleTree::new();
+        for key in keys.iter().rev() {
+            backward.insert(key, b"x");
+        }
+        assert_eq!(forward.root_hash(), backward.root_hash());
+
+        // Adding and removing extra keys leaves the same tree behind
+        backward.insert(b"temporary", b"y");
+        backward.insert(b"agent:0", b"changed");
+        backward.insert(b"agent:0", b"x");
+        backward.delete(b"temporary");
+        assert_eq!(forward.root_hash(), backward.root_hash());
+
+        for key in &keys {
+            forward.delete(key);
+        }
+        assert_eq!(forward.root_hash(), [0u8; 32]);
+    }
+
+    #[test]
+    fn test_proofs_for_every_key() {
+        let mut tree = MerkleTree::new();
+        for i in 0..64u32 {
+            tree.insert(&i.to_le_bytes(), &i.to_be_bytes());
+        }
+        let root = tree.root_hash();
+
+        for i in 0..64u32 {
+            let proof = tree.generate_proof(&i.to_le_bytes()).unwrap();
+            assert!(MerkleTree::verify_proof(root, &i.to_le_bytes(), &i.to_be_bytes(), &proof));
+            assert!(!MerkleTree::verify_proof(root, &i.to_le_bytes(), b"wrong", &proof));
+        }
+        assert!(tree.generate_proof(b"missing").is_none());
+    }
+
+    #[test]
+    fn test_exclusion_proofs() {
+        let mut tree = MerkleTree::new();
+        let empty = tree.prove(b"balance:nobody");
+        assert!(empty.verify(tree.root_hash(), b"balance:nobody", None));
+
+        for i in 0..32u32 {
+            tree.insert(format!("balance:{}", i).as_bytes(), &i.to_le_bytes());
+        }
+        let root = tree.root_hash();
+
+        let mut saw_empty = false;
+        let mut saw_other_leaf = false;
+        for i in 0..200u32 {
+            let key = format!("absent:{}", i);
+            let proof = tree.prove(key.as_bytes());
+            match proof.terminal {
+                ProofTerminal::Empty => saw_empty = true,
+                ProofTerminal::Leaf { .. } => saw_other_leaf = true,
+            }
+            assert!(proof.verify(root, key.as_bytes(), None));
+            assert!(!proof.verify(root, key.as_bytes(), Some(b"anything")));
+        }
+        assert!(saw_empty && saw_other_leaf);
+
+        // A membership proof cannot be passed off as an exclusion proof
+        let present = tree.prove(b"balance:7");
+        assert!(present.verify(root, b"balance:7", Some(&7u32.to_le_bytes())));
+        assert!(!present.verify(root, b"balance:7", None));
+    }
+
+    #[test]
+    fn test_exclusion_proof_tampering() {
+        let mut tree = MerkleTree::new();
+        for i in 0..32u32 {
+            tree.insert(&i.to_le_bytes(), b"value");
+        }
+        let root = tree.root_hash();
+
+        // Find an absent key whose path ends at another key's leaf
+        let (key, proof) = (100..)
+            .map(|i: u32| (i.to_le_bytes(), tree.prove(&i.to_le_bytes())))
+            .find(|(_, proof)| matches!(proof.terminal, ProofTerminal::Leaf { .. }))
+            .unwrap();
+        assert!(proof.verify(root, &key, None));
+
+        // The revealed leaf must sit on the key's path
+        let mut moved = proof.clone();
+        moved.siblings.push(EMPTY_HASH);
+        assert!(!moved.verify(root, &key, None));
+
+        let mut forged = proof.clone();
+        forged.terminal = ProofTerminal::Empty;
+        assert!(!forged.verify(root, &key, None));
+
+        let mut wrong_sibling = proof;
+        wrong_sibling.siblings[0] = [1u8; 32];
+        assert!(!wrong_sibling.verify(root, &key, None));
+    }
+
+    #[test]
+    fn test_multi_proof() {
+        let mut tree = MerkleTree::new();
+        for i in 0..64u32 {
+            tree.insert(format!("balance:{}", i).as_bytes(), &i.to_le_bytes());
+        }
+        let root = tree.root_hash();
+
+        let values: Vec<[u8; 4]> = (0..64u32).map(|i| i.to_le_bytes()).collect();
+        let keys: Vec<String> = [3, 17, 42, 63]
+            .iter()
+            .map(|i| format!("balance:{}", i))
+            .chain(["balance:missing".to_string(), "reward:0".to_string()])
+            .collect();
+        let key_refs: Vec<&[u8]> = keys.iter().map(|k| k.as_bytes()).collect();
+        let proof = tree.prove_many(&key_refs);
+
+        let entries: Vec<(&[u8], Option<&[u8]>)> = vec![
+            (keys[0].as_bytes(), Some(&values[3])),
+            (keys[1].as_bytes(), Some(&values[17])),
+            (keys[2].as_bytes(), Some(&values[42])),
+            (keys[3].as_bytes(), Some(&values[63])),
+            (keys[4].as_bytes(), None),
+            (keys[5].as_bytes(), None),
+        ];
+        assert!(proof.verify(root, &entries));
+
+        // Shared nodes are only sent once
+        let single: usize = key_refs.iter().map(|k| tree.prove(k).siblings.len()).sum();
+        assert!(proof.siblings.len() < single);
+
+        let mut wrong_value = entries.clone();
+        wrong_value[1].1 = Some(b"stolen");
+        assert!(!proof.verify(root, &wrong_value));
+
+        let mut claims_absent = entries.clone();
+        claims_absent[0].1 = None;
+        assert!(!proof.verify(root, &claims_absent));
+
+        // Keys the proof was not built for do not verify
+        assert!(!proof.verify(root, &entries[..3]));
+        let mut extra = entries.clone();
+        extra.push((b"balance:5", Some(&values[5])));
+        assert!(!proof.verify(root, &extra));
+
+        let mut tampered = proof;
+        tampered.siblings[0] = [7u8; 32];
+        assert!(!tampered.verify(root, &entries));
+    }
+}
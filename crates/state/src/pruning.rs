@@ -0,0 +1,64 @@
+//! How much block history a node keeps.
+//!
+//! Current state is never pruned. What goes is history: old blocks in the
+//! block index, their state diffs and the undo entries a reorg would need.
+//! The block log on disk stays whole, so a restart can still replay it.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Blocks of history a pruned node keeps unless told otherwise
+pub const DEFAULT_KEEP_BLOCKS: u64 = 256;
+
+/// Which block history a node holds on to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PruningMode {
+    /// Keep every block, diff and undo entry, in memory as well as in the log
+    #[default]
+    Archive,
+    /// Keep only the newest `keep` blocks of history
+    Pruned { keep: u64 },
+}
+
+impl FromStr for PruningMode {
+    type Err = String;
+
+    /// Parse `archive`, `pruned` or `pruned:<blocks>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "archive" => Ok(Self::Archive),
+            None if s == "pruned" => Ok(Self::Pruned { keep: DEFAULT_KEEP_BLOCKS }),
+            Some(("pruned", keep)) => match keep.parse() {
+                Ok(keep) if keep > 0 => Ok(Self::Pruned { keep }),
+                _ => Err(format!("Invalid number of blocks to keep: {}", keep)),
+            },
+            _ => Err(format!("Unknown pruning mode {}, expected archive or pruned[:blocks]", s)),
+        }
+    }
+}
+
+impl fmt::Display for PruningMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Archive => write!(f, "archive"),
+            Self::Pruned { keep } => write!(f, "pruned:{}", keep),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pruning_mode() {
+        assert_eq!("archive".parse(), Ok(PruningMode::Archive));
+        assert_eq!("pruned".parse(), Ok(PruningMode::Pruned { keep: DEFAULT_KEEP_BLOCKS }));
+        assert_eq!("pruned:10".parse(), Ok(PruningMode::Pruned { keep: 10 }));
+        assert!("pruned:0".parse::<PruningMode>().is_err());
+        assert!("pruned:x".parse::<PruningMode>().is_err());
+        assert!("light".parse::<PruningMode>().is_err());
+        let mode = PruningMode::Pruned { keep: 10 };
+        assert_eq!(mode.to_string().parse(), Ok(mode));
+    }
+}
@@ -0,0 +1,199 @@
+//! Transaction receipts and the events they carry.
+//!
+//! Executing a block yields one receipt per transaction saying whether it
+//! went through, which state keys it wrote and what it set off. Receipts are
+//! kept in state under their transaction hash, and a block header commits to
+//! them through a merkle root over their canonical hashes.
+
+use chaoschain_core::encoding::Encoder;
+use chaoschain_core::merkle::merkle_root;
+use chaoschain_core::TxKind;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Whether a transaction took effect
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum TxStatus {
+    /// Every effect was applied
+    Success,
+    /// Nothing but the nonce was applied
+    Failed { reason: String },
+}
+
+/// Something a transaction set off, for explorers and agents to follow
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// Tokens moved between accounts
+    Transfer { from: String, to: String, amount: u64 },
+    /// A chat message was posted
+    ChatPosted { sender: String },
+    /// A consensus rule was put up for debate
+    RuleProposed { sender: String, title: String },
+    /// An alliance was proposed to other agents
+    AllianceProposed { sender: String, allies: Vec<String> },
+    /// Balance was bonded as stake
+    Bonded { sender: String, amount: u64 },
+    /// Stake started unbonding
+    Unbonded { sender: String, amount: u64 },
+    /// A meme was registered
+    MemeRegistered { sender: String, name: String },
+    /// The sender registered its public key as an agent
+    AgentRegistered { sender: String, name: String, role: String },
+}
+
+impl Event {
+    /// Events a successful transaction of `kind` from `sender` sets off
+    pub(crate) fn for_tx(sender: &str, kind: &TxKind) -> Vec<Self> {
+        let sender = sender.to_string();
+        let event = match kind {
+            TxKind::Transfer { to, amount } => Self::Transfer {
+                from: sender,
+                to: hex::encode(to),
+                amount: *amount,
+            },
+            TxKind::ChatPost { .. } => Self::ChatPosted { sender },
+            TxKind::RuleProposal { title, .. } => Self::RuleProposed {
+                sender,
+                title: title.clone(),
+            },
+            TxKind::AllianceProposal { allies, .. } => Self::AllianceProposed {
+                sender,
+                allies: allies.clone(),
+            },
+            TxKind::Stake { amount } => Self::Bonded { sender, amount: *amount },
+            TxKind::Unstake { amount } => Self::Unbonded { sender, amount: *amount },
+            TxKind::RegisterMeme { name, .. } => Self::MemeRegistered {
+                sender,
+                name: name.clone(),
+            },
+            TxKind::RegisterAgent { name, role } => Self::AgentRegistered {
+                sender,
+                name: name.clone(),
+                role: role.clone(),
+            },
+        };
+        vec![event]
+    }
+
+    fn encode_into(&self, encoder: &mut Encoder) {
+        match self {
+            Self::Transfer { from, to, amount } => {
+                encoder.put_u8(0).put_str(from).put_str(to).put_u64(*amount);
+            }
+            Self::ChatPosted { sender } => {
+                encoder.put_u8(1).put_str(sender);
+            }
+            Self::RuleProposed { sender, title } => {
+                encoder.put_u8(2).put_str(sender).put_str(title);
+            }
+            Self::AllianceProposed { sender, allies } => {
+                encoder.put_u8(3).put_str(sender).put_u32(allies.len() as u32);
+                for ally in allies {
+                    encoder.put_str(ally);
+                }
+            }
+            Self::Bonded { sender, amount } => {
+                encoder.put_u8(4).put_str(sender).put_u64(*amount);
+            }
+            Self::Unbonded { sender, amount } => {
+                encoder.put_u8(5).put_str(sender).put_u64(*amount);
+            }
+            Self::MemeRegistered { sender, name } => {
+                encoder.put_u8(6).put_str(sender).put_str(name);
+            }
+            Self::AgentRegistered { sender, name, role } => {
+                encoder.put_u8(7).put_str(sender).put_str(name).put_str(role);
+            }
+        }
+    }
+}
+
+/// What executing one transaction did
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Receipt {
+    /// Hash of the transaction
+    pub tx_hash: [u8; 32],
+    /// Account that sent it
+    pub sender: [u8; 32],
+    /// Nonce it used up
+    pub nonce: u64,
+    /// Whether it took effect
+    pub status: TxStatus,
+    /// State keys it wrote besides its own receipt, in key order
+    pub touched: Vec<Vec<u8>>,
+    /// What it set off, empty if it failed
+    pub events: Vec<Event>,
+}
+
+impl Receipt {
+    /// Canonical bytes the receipts root is built over
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        encoder.put_fixed(&self.tx_hash).put_fixed(&self.sender).put_u64(self.nonce);
+        match &self.status {
+            TxStatus::Success => encoder.put_u8(0),
+            TxStatus::Failed { reason } => encoder.put_u8(1).put_str(reason),
+        };
+        encoder.put_u32(self.touched.len() as u32);
+        for key in &self.touched {
+            encoder.put_bytes(key);
+        }
+        encoder.put_u32(self.events.len() as u32);
+        for event in &self.events {
+            event.encode_into(&mut encoder);
+        }
+        encoder.finish()
+    }
+
+    /// Hash of the canonical bytes, the receipt's leaf in the receipts root
+    pub fn hash(&self) -> [u8; 32] {
+        Sha256::digest(self.encode()).into()
+    }
+
+    /// Whether the transaction took effect
+    pub fn succeeded(&self) -> bool {
+        self.status == TxStatus::Success
+    }
+}
+
+/// Merkle root over receipts in block order, all zeros when there are none
+pub fn receipts_root(receipts: &[Receipt]) -> [u8; 32] {
+    let hashes: Vec<[u8; 32]> = receipts.iter().map(Receipt::hash).collect();
+    merkle_root(&hashes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receipt(status: TxStatus) -> Receipt {
+        Receipt {
+            tx_hash: [1u8; 32],
+            sender: [2u8; 32],
+            nonce: 0,
+            status,
+            touched: vec![b"account:02".to_vec()],
+            events: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_receipts_root_covers_every_field() {
+        let ok = receipt(TxStatus::Success);
+        let failed = receipt(TxStatus::Failed { reason: "broke".to_string() });
+        assert_ne!(ok.hash(), failed.hash());
+
+        let mut eventful = ok.clone();
+        eventful.events = Event::for_tx("02", &TxKind::AllianceProposal {
+            allies: vec!["a".to_string()],
+            reason: "drama".to_string(),
+        });
+        assert_ne!(eventful.hash(), ok.hash());
+
+        let root = receipts_root(&[ok.clone(), eventful.clone()]);
+        assert_ne!(root, receipts_root(&[eventful, ok]));
+        assert_eq!(receipts_root(&[]), [0u8; 32]);
+    }
+}
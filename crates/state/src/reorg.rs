@@ -0,0 +1,97 @@
+//! Undo logs for applied blocks and the result of a chain reorganization.
+//!
+//! Every block's writes go through `apply_journaled`, which records the
+//! previous value of each key it touches. Replaying those records newest
+//! first takes the tree back to the state before the block.
+
+use crate::merkle::MerkleTree;
+use crate::{StateDiff, StateOp};
+use serde::{Deserialize, Serialize};
+
+/// What it takes to roll one applied block back
+#[derive(Debug, Clone)]
+pub(crate) struct BlockUndo {
+    /// Hash of the block this entry undoes
+    pub hash: [u8; 32],
+    /// Ops restoring the previous values, from the block's post-state root back to its pre-state root
+    pub diff: StateDiff,
+    /// Chain height before the block
+    pub prev_height: u64,
+}
+
+impl BlockUndo {
+    /// Entry rolling back the block `hash`, which changed state as `diff` records, with `ops`
+    pub fn new(hash: [u8; 32], ops: Vec<StateOp>, diff: &StateDiff, prev_height: u64) -> Self {
+        Self {
+            hash,
+            diff: StateDiff {
+                ops,
+                prev_root: diff.new_root,
+                new_root: diff.prev_root,
+            },
+            prev_height,
+        }
+    }
+}
+
+/// Apply ops to the tree, returning the ops that undo them in the order they must run
+pub(crate) fn apply_journaled(tree: &mut MerkleTree, ops: &[StateOp]) -> Vec<StateOp> {
+    let mut undo = Vec::with_capacity(ops.len());
+    for op in ops {
+        let key = match op {
+            StateOp::Set { key, .. } | StateOp::Delete { key } => key,
+        };
+        undo.push(match tree.get(key) {
+            Some(value) => StateOp::Set { key: key.clone(), value },
+            None => StateOp::Delete { key: key.clone() },
+        });
+        match op {
+            StateOp::Set { key, value } => tree.insert(key, value),
+            StateOp::Delete { key } => tree.delete(key),
+        }
+    }
+    undo.reverse();
+    undo
+}
+
+/// A switch of the canonical chain from one head to another
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Reorg {
+    /// Head before the switch
+    pub old_head: Option<[u8; 32]>,
+    /// Head after the switch
+    pub new_head: [u8; 32],
+    /// Last block both chains share, if any
+    pub common_ancestor: Option<[u8; 32]>,
+    /// Blocks taken off the canonical chain, newest first
+    pub rolled_back: Vec<[u8; 32]>,
+    /// Blocks put on the canonical chain, oldest first
+    pub applied: Vec<[u8; 32]>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_restores_previous_state() {
+        let mut tree = MerkleTree::new();
+        tree.insert(b"kept", b"1");
+        tree.insert(b"changed", b"old");
+        let before = tree.root_hash();
+
+        let ops = vec![
+            StateOp::Set { key: b"changed".to_vec(), value: b"new".to_vec() },
+            StateOp::Set { key: b"added".to_vec(), value: b"x".to_vec() },
+            StateOp::Set { key: b"added".to_vec(), value: b"y".to_vec() },
+            StateOp::Delete { key: b"kept".to_vec() },
+        ];
+        let undo = apply_journaled(&mut tree, &ops);
+        assert_ne!(tree.root_hash(), before);
+
+        apply_journaled(&mut tree, &undo);
+        assert_eq!(tree.root_hash(), before);
+        assert_eq!(tree.get(b"added"), None);
+        assert_eq!(tree.get(b"changed"), Some(b"old".to_vec()));
+    }
+}
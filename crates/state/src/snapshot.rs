@@ -0,0 +1,158 @@
+//! Chunked state snapshots in files.
+//!
+//! A snapshot directory holds a JSON manifest and numbered chunk files of
+//! state entries. The manifest names the state root, the chain state and
+//! the SHA-256 of every chunk, so an importing node checks each chunk as it
+//! reads it and the rebuilt tree against the root before trusting any of it.
+
+use crate::merkle::MerkleTree;
+use crate::{StateEntries, StateError};
+use chaoschain_core::encoding::{Decoder, Encoder};
+use chaoschain_core::ChainState;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+/// Name of the manifest file in a snapshot directory
+pub const MANIFEST_FILE: &str = "manifest.json";
+/// Snapshot format version
+pub const SNAPSHOT_VERSION: u8 = 1;
+/// State entries per chunk unless configured otherwise
+pub const DEFAULT_CHUNK_ENTRIES: usize = 1024;
+
+/// One chunk file listed in a snapshot manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotChunk {
+    /// File name within the snapshot directory
+    pub file: String,
+    /// Number of state entries in the chunk
+    pub entries: u64,
+    /// Hex SHA-256 of the chunk file
+    pub hash: String,
+}
+
+/// Description of a snapshot directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    /// Snapshot format version
+    pub version: u8,
+    /// Chain the snapshot was taken on
+    pub chain_id: String,
+    /// Hex state root the chunks rebuild to
+    pub state_root: String,
+    /// Chain state at the snapshot, balances included
+    pub chain_state: ChainState,
+    /// Chunk files in key order
+    pub chunks: Vec<SnapshotChunk>,
+}
+
+fn io_error(context: &str, e: std::io::Error) -> StateError {
+    StateError::Storage(format!("{}: {}", context, e))
+}
+
+fn encode_chunk(entries: &[(&Vec<u8>, &Vec<u8>)]) -> Vec<u8> {
+    let mut encoder = Encoder::new();
+    encoder.put_u32(entries.len() as u32);
+    for (key, value) in entries {
+        encoder.put_bytes(key).put_bytes(value);
+    }
+    encoder.finish()
+}
+
+fn decode_chunk(bytes: &[u8]) -> Result<StateEntries, StateError> {
+    let mut decoder = Decoder::new(bytes)?;
+    let count = decoder.take_u32()?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        entries.push((decoder.take_bytes()?, decoder.take_bytes()?));
+    }
+    decoder.finish()?;
+    Ok(entries)
+}
+
+/// Write every entry of `tree` to chunk files in `dir`, then the manifest naming them
+pub(crate) fn write_snapshot(
+    dir: &Path,
+    tree: &MerkleTree,
+    chain_id: &str,
+    chain_state: ChainState,
+    chunk_entries: usize,
+) -> Result<SnapshotManifest, StateError> {
+    fs::create_dir_all(dir).map_err(|e| io_error("Failed to create snapshot dir", e))?;
+
+    let mut chunks = Vec::new();
+    let mut entries = tree.iter().peekable();
+    while entries.peek().is_some() {
+        let batch: Vec<_> = entries.by_ref().take(chunk_entries.max(1)).collect();
+        let bytes = encode_chunk(&batch);
+        let file = format!("chunk-{:05}.bin", chunks.len());
+        fs::write(dir.join(&file), &bytes).map_err(|e| io_error("Failed to write snapshot chunk", e))?;
+        chunks.push(SnapshotChunk {
+            file,
+            entries: batch.len() as u64,
+            hash: hex::encode(Sha256::digest(&bytes)),
+        });
+    }
+
+    // The manifest goes last, so a snapshot cut short has none
+    let manifest = SnapshotManifest {
+        version: SNAPSHOT_VERSION,
+        chain_id: chain_id.to_string(),
+        state_root: hex::encode(tree.root_hash()),
+        chain_state,
+        chunks,
+    };
+    let json = serde_json::to_vec_pretty(&manifest)
+        .map_err(|e| StateError::Storage(format!("Failed to encode manifest: {}", e)))?;
+    let tmp = dir.join(format!("{}.tmp", MANIFEST_FILE));
+    fs::write(&tmp, json)
+        .and_then(|_| fs::rename(&tmp, dir.join(MANIFEST_FILE)))
+        .map_err(|e| io_error("Failed to write snapshot manifest", e))?;
+    Ok(manifest)
+}
+
+/// Rebuild the tree from a snapshot in `dir`, checking every chunk and the final root against the manifest
+pub(crate) fn read_snapshot(dir: &Path) -> Result<(SnapshotManifest, MerkleTree), StateError> {
+    let json = fs::read(dir.join(MANIFEST_FILE)).map_err(|e| io_error("Failed to read snapshot manifest", e))?;
+    let manifest: SnapshotManifest = serde_json::from_slice(&json)
+        .map_err(|e| StateError::InvalidSnapshot(format!("Corrupt manifest: {}", e)))?;
+    if manifest.version != SNAPSHOT_VERSION {
+        return Err(StateError::InvalidSnapshot(format!(
+            "Unsupported snapshot version {}",
+            manifest.version
+        )));
+    }
+
+    let mut tree = MerkleTree::new();
+    for chunk in &manifest.chunks {
+        // Chunk names come from the manifest, so keep them inside the snapshot dir
+        if chunk.file.contains(['/', '\\']) || chunk.file.starts_with('.') {
+            return Err(StateError::InvalidSnapshot(format!("Bad chunk name {}", chunk.file)));
+        }
+        let bytes = fs::read(dir.join(&chunk.file)).map_err(|e| io_error("Failed to read snapshot chunk", e))?;
+        if hex::encode(Sha256::digest(&bytes)) != chunk.hash {
+            return Err(StateError::InvalidSnapshot(format!(
+                "{} does not match its manifest hash",
+                chunk.file
+            )));
+        }
+        let entries = decode_chunk(&bytes)?;
+        if entries.len() as u64 != chunk.entries {
+            return Err(StateError::InvalidSnapshot(format!(
+                "{} holds {} entries, manifest says {}",
+                chunk.file,
+                entries.len(),
+                chunk.entries
+            )));
+        }
+        for (key, value) in entries {
+            tree.insert(&key, &value);
+        }
+    }
+
+    if hex::encode(tree.root_hash()) != manifest.state_root {
+        return Err(StateError::InvalidStateRoot);
+    }
+    Ok((manifest, tree))
+}
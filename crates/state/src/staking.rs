@@ -0,0 +1,190 @@
+//! Bonded stake, unbonding and slashing.
+//!
+//! Stake is balance an account has locked up in exchange for voting weight.
+//! Bonding takes effect at once. Unbonding drops the weight at once but keeps
+//! the tokens locked for the chain's unbonding delay, so stake that
+//! misbehaved can still be slashed on its way out.
+
+use crate::merkle::MerkleTree;
+use crate::{stake_key, StateError, StateOp};
+use chaoschain_core::encoding::{Decoder, Encoder};
+use chaoschain_core::Block;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Stake on its way back to an account's balance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Unbonding {
+    /// Tokens being released
+    pub amount: u64,
+    /// Height of the block that releases them
+    pub release_height: u64,
+}
+
+/// Stake held by one account
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stake {
+    /// Stake counting toward voting weight
+    pub bonded: u64,
+    /// Unbonded stake not yet released, oldest first
+    pub unbonding: Vec<Unbonding>,
+}
+
+impl Stake {
+    /// Canonical bytes kept under the account's stake key
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        encoder.put_u64(self.bonded).put_u32(self.unbonding.len() as u32);
+        for entry in &self.unbonding {
+            encoder.put_u64(entry.amount).put_u64(entry.release_height);
+        }
+        encoder.finish()
+    }
+
+    /// Decode stake bytes, rejecting trailing data
+    pub fn decode(bytes: &[u8]) -> Result<Self, StateError> {
+        let mut decoder = Decoder::new(bytes)?;
+        let bonded = decoder.take_u64()?;
+        let count = decoder.take_u32()?;
+        let mut unbonding = Vec::new();
+        for _ in 0..count {
+            unbonding.push(Unbonding {
+                amount: decoder.take_u64()?,
+                release_height: decoder.take_u64()?,
+            });
+        }
+        decoder.finish()?;
+        Ok(Self { bonded, unbonding })
+    }
+
+    /// Every token locked up, bonded or unbonding, summed wide so it cannot wrap
+    pub fn locked(&self) -> u128 {
+        self.unbonding
+            .iter()
+            .fold(self.bonded as u128, |sum, entry| sum + entry.amount as u128)
+    }
+
+    /// Whether nothing is bonded or unbonding
+    pub fn is_empty(&self) -> bool {
+        self.bonded == 0 && self.unbonding.is_empty()
+    }
+
+    /// Add `amount` to the bonded stake of `account_id`
+    pub(crate) fn bond(&mut self, account_id: &str, amount: u64) -> Result<(), StateError> {
+        self.bonded = self
+            .bonded
+            .checked_add(amount)
+            .ok_or_else(|| StateError::BalanceOverflow(account_id.to_string()))?;
+        Ok(())
+    }
+
+    /// Start unbonding `amount`, to be released at `release_height`
+    pub(crate) fn unbond(&mut self, account_id: &str, amount: u64, release_height: u64) -> Result<(), StateError> {
+        let bonded = self.bonded;
+        self.bonded = bonded.checked_sub(amount).ok_or_else(|| StateError::InsufficientStake {
+            account: account_id.to_string(),
+            bonded,
+            amount,
+        })?;
+        self.unbonding.push(Unbonding { amount, release_height });
+        Ok(())
+    }
+
+    /// Drop unbonding entries due by `height`, returning how much they held
+    pub(crate) fn release(&mut self, height: u64) -> u64 {
+        let mut released = 0u64;
+        self.unbonding.retain(|entry| {
+            let due = entry.release_height <= height;
+            if due {
+                released = released.saturating_add(entry.amount);
+            }
+            !due
+        });
+        released
+    }
+
+    /// Cut up to `amount` from bonded stake, then from unbonding stake, returning what was cut
+    pub(crate) fn slash(&mut self, amount: u64) -> u64 {
+        let mut remaining = amount;
+        let cut = remaining.min(self.bonded);
+        self.bonded -= cut;
+        remaining -= cut;
+        for entry in &mut self.unbonding {
+            let cut = remaining.min(entry.amount);
+            entry.amount -= cut;
+            remaining -= cut;
+        }
+        self.unbonding.retain(|entry| entry.amount > 0);
+        amount - remaining
+    }
+}
+
+/// Read an account's stake from the tree, defaulting to none
+pub(crate) fn read_stake(tree: &MerkleTree, account_id: &str) -> Result<Stake, StateError> {
+    tree.get(&stake_key(account_id))
+        .map(|bytes| Stake::decode(&bytes))
+        .transpose()
+        .map(Option::unwrap_or_default)
+}
+
+/// Ops writing stakes back to the tree, dropping those left empty
+pub(crate) fn stake_ops(stakes: BTreeMap<String, Stake>) -> impl Iterator<Item = StateOp> {
+    stakes.into_iter().map(|(account_id, stake)| {
+        let key = stake_key(&account_id);
+        if stake.is_empty() {
+            StateOp::Delete { key }
+        } else {
+            StateOp::Set { key, value: stake.encode() }
+        }
+    })
+}
+
+/// A penalty against a validator's stake
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Slash {
+    /// Account whose stake is cut
+    pub validator: String,
+    /// Tokens burned, at most what the account has locked up
+    pub amount: u64,
+    /// Why the stake was cut
+    pub reason: String,
+}
+
+/// Decides which stake executing a block slashes
+///
+/// Every node has to run the same hook, and its answer can depend on the
+/// block alone, or nodes will arrive at different state roots.
+pub trait SlashingHook: Send + Sync + std::fmt::Debug {
+    /// Slashes to apply when executing `block`
+    fn slashes(&self, block: &Block) -> Vec<Slash>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bond_unbond_release_and_slash() {
+        let mut stake = Stake::default();
+        stake.bond("v", 100).unwrap();
+        stake.unbond("v", 30, 10).unwrap();
+        stake.unbond("v", 20, 12).unwrap();
+        assert!(matches!(
+            stake.unbond("v", 51, 12),
+            Err(StateError::InsufficientStake { bonded: 50, amount: 51, .. })
+        ));
+        assert_eq!(stake.locked(), 100);
+        assert_eq!(Stake::decode(&stake.encode()).unwrap(), stake);
+
+        // Unbonding stake is released only once its height comes round
+        assert_eq!(stake.release(9), 0);
+        assert_eq!(stake.release(10), 30);
+        assert_eq!(stake.bonded, 50);
+
+        // Slashing reaches past bonded stake into what is still unbonding
+        assert_eq!(stake.slash(60), 60);
+        assert_eq!(stake, Stake { bonded: 0, unbonding: vec![Unbonding { amount: 10, release_height: 12 }] });
+        assert_eq!(stake.slash(60), 10);
+        assert!(stake.is_empty());
+    }
+}
@@ -0,0 +1,306 @@
+//! Durable on-disk storage for `StateStoreImpl`.
+//!
+//! Every committed block is appended to a log together with the state diff it
+//! produced and the ops that undo it, and every `snapshot_interval` blocks the full state is written to
+//! a snapshot file. On startup the latest snapshot is loaded, the blocks after
+//! it are replayed, and each replayed root must match the committed one.
+//! A pruned node compacts the log after each snapshot, so it only holds the
+//! blocks the snapshot leaves out plus the history it keeps.
+//!
+//! Log entries are a little-endian `u32` length, the SHA-256 of the record
+//! and the bincode record itself. Only the final entry can be a torn write;
+//! a damaged entry anywhere else means the log is corrupt.
+
+use crate::{StateDiff, StateError, StateOp};
+use chaoschain_core::{Block, ChainState};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+const LOG_FILE: &str = "blocks.log";
+const SNAPSHOT_FILE: &str = "state.snapshot";
+const COMPACT_FILE: &str = "blocks.log.tmp";
+/// Bytes of length and checksum in front of every log record
+const ENTRY_HEADER: usize = 4 + 32;
+
+/// Blocks between state snapshots unless configured otherwise
+pub const DEFAULT_SNAPSHOT_INTERVAL: u64 = 100;
+
+/// A committed block and the changes applying it made to state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct LogRecord {
+    pub block: Block,
+    pub diff: StateDiff,
+    /// Ops rolling the block back, so history outlives the snapshot that folds it in
+    pub undo: Vec<StateOp>,
+}
+
+impl LogRecord {
+    /// Record for the block a node imported from a snapshot, whose diff is unknown
+    pub fn base(block: Block, state_root: [u8; 32]) -> Self {
+        Self {
+            block,
+            diff: StateDiff {
+                ops: Vec::new(),
+                prev_root: state_root,
+                new_root: state_root,
+            },
+            undo: Vec::new(),
+        }
+    }
+
+    /// Whether this record is a snapshot base rather than an executed block
+    pub fn is_base(&self) -> bool {
+        self.diff.ops.is_empty() && self.diff.prev_root == self.diff.new_root
+    }
+}
+
+/// Full state after a number of committed blocks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct DiskSnapshot {
+    /// Hash of the newest block folded into this snapshot, if any
+    pub head: Option<[u8; 32]>,
+    pub state_root: [u8; 32],
+    pub state: ChainState,
+    pub pairs: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+fn io_error(context: &str, e: std::io::Error) -> StateError {
+    StateError::Storage(format!("{}: {}", context, e))
+}
+
+/// Append-only block log plus periodic state snapshots in one directory
+#[derive(Debug)]
+pub struct DiskStorage {
+    dir: PathBuf,
+    log: File,
+    /// Byte offset where each record in the log ends
+    ends: Vec<u64>,
+    /// Number of log records the snapshot on disk covers
+    snapshot_records: usize,
+    snapshot_interval: u64,
+}
+
+impl DiskStorage {
+    /// Open or create the storage directory
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, StateError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(|e| io_error("Failed to create data dir", e))?;
+        let log = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(dir.join(LOG_FILE))
+            .map_err(|e| io_error("Failed to open block log", e))?;
+
+        Ok(Self {
+            dir,
+            log,
+            ends: Vec::new(),
+            snapshot_records: 0,
+            snapshot_interval: DEFAULT_SNAPSHOT_INTERVAL,
+        })
+    }
+
+    /// Whether `dir` already holds a committed chain
+    pub fn has_chain(dir: impl AsRef<Path>) -> bool {
+        fs::metadata(dir.as_ref().join(LOG_FILE)).is_ok_and(|meta| meta.len() > 0)
+    }
+
+    /// Write a snapshot every `interval` blocks, or never if zero
+    pub fn with_snapshot_interval(mut self, interval: u64) -> Self {
+        self.snapshot_interval = interval;
+        self
+    }
+
+    /// Read every complete record, dropping a torn write at the end of the log
+    pub(crate) fn read_log(&mut self) -> Result<Vec<LogRecord>, StateError> {
+        let mut bytes = Vec::new();
+        File::open(self.dir.join(LOG_FILE))
+            .and_then(|mut file| file.read_to_end(&mut bytes))
+            .map_err(|e| io_error("Failed to read block log", e))?;
+
+        let mut records = Vec::new();
+        let mut ends = Vec::new();
+        let mut offset = 0;
+        while offset + ENTRY_HEADER <= bytes.len() {
+            let mut len = [0u8; 4];
+            len.copy_from_slice(&bytes[offset..offset + 4]);
+            let end = offset + ENTRY_HEADER + u32::from_le_bytes(len) as usize;
+            if end > bytes.len() {
+                break;
+            }
+            let payload = &bytes[offset + ENTRY_HEADER..end];
+            let record = if Sha256::digest(payload)[..] == bytes[offset + 4..offset + ENTRY_HEADER] {
+                bincode::deserialize(payload).ok()
+            } else {
+                None
+            };
+            match record {
+                Some(record) => records.push(record),
+                // A final entry that fails its checksum was torn mid-write
+                None if end == bytes.len() => break,
+                None => {
+                    return Err(StateError::Storage(format!(
+                        "Block log record {} is corrupt",
+                        records.len()
+                    )))
+                }
+            }
+            offset = end;
+            ends.push(end as u64);
+        }
+
+        if offset < bytes.len() {
+            warn!(
+                "Dropping {} bytes of incomplete block log entry",
+                bytes.len() - offset
+            );
+            self.log
+                .set_len(offset as u64)
+                .map_err(|e| io_error("Failed to truncate block log", e))?;
+        }
+
+        self.ends = ends;
+        Ok(records)
+    }
+
+    /// Append a record and flush it to disk
+    pub(crate) fn append(&mut self, record: &LogRecord) -> Result<(), StateError> {
+        let payload = bincode::serialize(record)
+            .map_err(|e| StateError::Storage(format!("Failed to encode block: {}", e)))?;
+        let mut entry = Vec::with_capacity(payload.len() + ENTRY_HEADER);
+        entry.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        entry.extend_from_slice(&Sha256::digest(&payload));
+        entry.extend_from_slice(&payload);
+
+        let start = self.ends.last().copied().unwrap_or(0);
+        if let Err(e) = self.log.write_all(&entry).and_then(|_| self.log.sync_data()) {
+            // Drop whatever part of the entry made it, so the next append starts clean
+            let _ = self.log.set_len(start);
+            return Err(io_error("Failed to append to block log", e));
+        }
+        self.ends.push(start + entry.len() as u64);
+        Ok(())
+    }
+
+    /// Swap the log for a read-only handle, so appends fail as on a full disk
+    #[cfg(test)]
+    pub(crate) fn fail_appends(&mut self) {
+        self.log = File::open(self.dir.join(LOG_FILE)).unwrap();
+    }
+
+    /// Number of records in the log
+    pub(crate) fn records(&self) -> usize {
+        self.ends.len()
+    }
+
+    /// Drop every record after the first `records`, as when the chain reorganizes
+    pub(crate) fn truncate(&mut self, records: usize) -> Result<(), StateError> {
+        if records >= self.ends.len() {
+            return Ok(());
+        }
+        let len = records.checked_sub(1).map_or(0, |last| self.ends[last]);
+        self.log
+            .set_len(len)
+            .and_then(|_| self.log.sync_data())
+            .map_err(|e| io_error("Failed to truncate block log", e))?;
+        self.ends.truncate(records);
+        Ok(())
+    }
+
+    /// Whether the block just appended should be followed by a snapshot
+    pub(crate) fn snapshot_due(&self) -> bool {
+        self.snapshot_interval > 0
+            && self.ends.len().saturating_sub(self.snapshot_records) as u64 >= self.snapshot_interval
+    }
+
+    /// Load the latest snapshot, if one has been written, with the number of `records` it covers
+    pub(crate) fn load_snapshot(
+        &mut self,
+        records: &[LogRecord],
+    ) -> Result<Option<(DiskSnapshot, usize)>, StateError> {
+        let path = self.dir.join(SNAPSHOT_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let bytes = fs::read(&path).map_err(|e| io_error("Failed to read snapshot", e))?;
+        let snapshot: DiskSnapshot = bincode::deserialize(&bytes)
+            .map_err(|e| StateError::Storage(format!("Corrupt snapshot: {}", e)))?;
+        let covered = match snapshot.head {
+            None => Some(0),
+            Some(head) => records
+                .iter()
+                .position(|record| record.block.hash() == head)
+                .map(|index| index + 1),
+        };
+        let covered = covered.ok_or_else(|| {
+            StateError::Storage("Snapshot does not match the block log".to_string())
+        })?;
+        self.snapshot_records = covered;
+        Ok(Some((snapshot, covered)))
+    }
+
+    /// Whether the snapshot on disk covers more than the first `records` records
+    pub(crate) fn snapshot_beyond(&self, records: usize) -> bool {
+        self.snapshot_records > records
+    }
+
+    /// Replace the snapshot atomically
+    pub(crate) fn write_snapshot(&mut self, snapshot: &DiskSnapshot) -> Result<(), StateError> {
+        let bytes = bincode::serialize(snapshot)
+            .map_err(|e| StateError::Storage(format!("Failed to encode snapshot: {}", e)))?;
+        let tmp = self.dir.join(format!("{}.tmp", SNAPSHOT_FILE));
+        File::create(&tmp)
+            .and_then(|mut file| {
+                file.write_all(&bytes)?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&tmp, self.dir.join(SNAPSHOT_FILE)))
+            .map_err(|e| io_error("Failed to write snapshot", e))?;
+        // Snapshots are always taken of the state the whole log leads to
+        self.snapshot_records = self.ends.len();
+        Ok(())
+    }
+
+    /// Drop records the snapshot covers, keeping its own block and the newest `keep`
+    ///
+    /// The log is rewritten to a temporary file and renamed over the old one,
+    /// so a crash leaves either log whole, and the snapshot finds its block in both.
+    pub(crate) fn compact(&mut self, keep: usize) -> Result<(), StateError> {
+        let dropped = self
+            .ends
+            .len()
+            .saturating_sub(keep)
+            .min(self.snapshot_records.saturating_sub(1));
+        if dropped == 0 {
+            return Ok(());
+        }
+        let start = self.ends[dropped - 1];
+
+        let mut bytes = Vec::new();
+        File::open(self.dir.join(LOG_FILE))
+            .and_then(|mut file| file.read_to_end(&mut bytes))
+            .map_err(|e| io_error("Failed to read block log", e))?;
+        let tmp = self.dir.join(COMPACT_FILE);
+        File::create(&tmp)
+            .and_then(|mut file| {
+                file.write_all(&bytes[start as usize..])?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&tmp, self.dir.join(LOG_FILE)))
+            .map_err(|e| io_error("Failed to compact block log", e))?;
+        self.log = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(self.dir.join(LOG_FILE))
+            .map_err(|e| io_error("Failed to open block log", e))?;
+
+        self.ends = self.ends[dropped..].iter().map(|end| end - start).collect();
+        self.snapshot_records -= dropped;
+        Ok(())
+    }
+}
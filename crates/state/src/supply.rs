@@ -0,0 +1,239 @@
+//! Native token supply and the invariants balances must keep.
+//!
+//! Every token in existence was either handed out at genesis or minted as
+//! part of a block reward, and not yet burned by a slash. State keeps a
+//! counter for each source, one for slashing and one for the total, so the
+//! sum of all balances and stake can be checked against them.
+
+use crate::merkle::MerkleTree;
+use crate::{supply_key, Account, RewardRecord, Stake, StateError, StateOp, ACCOUNT_PREFIX, STAKE_PREFIX};
+use chaoschain_core::encoding::{Decoder, Encoder};
+use serde::{Deserialize, Serialize};
+
+const TOTAL: &str = "total";
+const GENESIS: &str = "genesis";
+const BASE_REWARDS: &str = "base_rewards";
+const DRAMA_BONUSES: &str = "drama_bonuses";
+const INNOVATION_BONUSES: &str = "innovation_bonuses";
+const CHAOS_BONUSES: &str = "chaos_bonuses";
+const SLASHED: &str = "slashed";
+
+/// Tokens in existence and where they came from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Supply {
+    /// Every token issued and not burned since
+    pub total: u64,
+    /// Issued as genesis balances
+    pub genesis: u64,
+    /// Minted as base block rewards
+    pub base_rewards: u64,
+    /// Minted as drama bonuses
+    pub drama_bonuses: u64,
+    /// Minted as innovation bonuses
+    pub innovation_bonuses: u64,
+    /// Minted as chaos bonuses
+    pub chaos_bonuses: u64,
+    /// Burned by slashing
+    pub slashed: u64,
+}
+
+fn encode_counter(value: u64) -> Vec<u8> {
+    let mut encoder = Encoder::new();
+    encoder.put_u64(value);
+    encoder.finish()
+}
+
+fn decode_counter(key: &[u8], bytes: &[u8]) -> Result<u64, StateError> {
+    let corrupt = |e| StateError::CorruptValue(format!("{}: {}", String::from_utf8_lossy(key), e));
+    let mut decoder = Decoder::new(bytes).map_err(corrupt)?;
+    let value = decoder.take_u64().map_err(corrupt)?;
+    decoder.finish().map_err(corrupt)?;
+    Ok(value)
+}
+
+fn add(counter: &mut u64, name: &str, amount: u64) -> Result<(), StateError> {
+    *counter = counter
+        .checked_add(amount)
+        .ok_or_else(|| StateError::BalanceOverflow(String::from_utf8_lossy(&supply_key(name)).into_owned()))?;
+    Ok(())
+}
+
+impl Supply {
+    fn counters(&self) -> [(&'static str, u64); 7] {
+        [
+            (TOTAL, self.total),
+            (GENESIS, self.genesis),
+            (BASE_REWARDS, self.base_rewards),
+            (DRAMA_BONUSES, self.drama_bonuses),
+            (INNOVATION_BONUSES, self.innovation_bonuses),
+            (CHAOS_BONUSES, self.chaos_bonuses),
+            (SLASHED, self.slashed),
+        ]
+    }
+
+    /// Read every counter through `get`, taking unset ones as zero
+    pub(crate) fn read(
+        mut get: impl FnMut(&[u8]) -> Result<Option<Vec<u8>>, StateError>,
+    ) -> Result<Self, StateError> {
+        let mut counter = |name: &str| {
+            let key = supply_key(name);
+            get(&key)?
+                .map(|bytes| decode_counter(&key, &bytes))
+                .transpose()
+                .map(Option::unwrap_or_default)
+        };
+        Ok(Self {
+            total: counter(TOTAL)?,
+            genesis: counter(GENESIS)?,
+            base_rewards: counter(BASE_REWARDS)?,
+            drama_bonuses: counter(DRAMA_BONUSES)?,
+            innovation_bonuses: counter(INNOVATION_BONUSES)?,
+            chaos_bonuses: counter(CHAOS_BONUSES)?,
+            slashed: counter(SLASHED)?,
+        })
+    }
+
+    /// Counters as held in `tree`
+    pub(crate) fn in_tree(tree: &MerkleTree) -> Result<Self, StateError> {
+        Self::read(|key| Ok(tree.get(key)))
+    }
+
+    /// Ops writing every counter back to the tree
+    pub(crate) fn ops(&self) -> impl Iterator<Item = StateOp> {
+        self.counters().into_iter().map(|(name, value)| StateOp::Set {
+            key: supply_key(name),
+            value: encode_counter(value),
+        })
+    }
+
+    /// Write every counter to the tree
+    pub(crate) fn write(&self, tree: &mut MerkleTree) {
+        for (name, value) in self.counters() {
+            tree.insert(&supply_key(name), &encode_counter(value));
+        }
+    }
+
+    /// Count `amount` handed out at genesis
+    pub(crate) fn issue_genesis(&mut self, amount: u64) -> Result<(), StateError> {
+        add(&mut self.genesis, GENESIS, amount)?;
+        add(&mut self.total, TOTAL, amount)
+    }
+
+    /// Count a block reward as minted, by category
+    pub(crate) fn mint(&mut self, reward: &RewardRecord) -> Result<(), StateError> {
+        add(&mut self.base_rewards, BASE_REWARDS, reward.base)?;
+        add(&mut self.drama_bonuses, DRAMA_BONUSES, reward.drama_bonus)?;
+        add(&mut self.innovation_bonuses, INNOVATION_BONUSES, reward.innovation_bonus)?;
+        add(&mut self.chaos_bonuses, CHAOS_BONUSES, reward.chaos_bonus)?;
+        add(&mut self.total, TOTAL, reward.total)
+    }
+
+    /// Count `amount` of slashed stake as burned
+    pub(crate) fn burn(&mut self, amount: u64) -> Result<(), StateError> {
+        add(&mut self.slashed, SLASHED, amount)?;
+        self.total = self.total.checked_sub(amount).ok_or_else(|| {
+            StateError::InvariantViolation(format!("burning {} exceeds total supply {}", amount, self.total))
+        })?;
+        Ok(())
+    }
+}
+
+/// Check that the supply adds up to its sources and all balances and stake add up to the supply
+///
+/// Balances are summed wide, so one that wrapped around or a set of them
+/// overflowing `u64` between them shows up as a mismatch rather than wrapping.
+pub(crate) fn verify_invariants(tree: &MerkleTree) -> Result<Supply, StateError> {
+    let supply = Supply::in_tree(tree)?;
+    let sources = [
+        supply.genesis,
+        supply.base_rewards,
+        supply.drama_bonuses,
+        supply.innovation_bonuses,
+        supply.chaos_bonuses,
+    ]
+    .iter()
+    .map(|&amount| amount as u128)
+    .sum::<u128>();
+    if sources.checked_sub(supply.slashed as u128) != Some(supply.total as u128) {
+        return Err(StateError::InvariantViolation(format!(
+            "total supply {} does not match the {} issued less {} slashed",
+            supply.total, sources, supply.slashed
+        )));
+    }
+
+    let mut balances = 0u128;
+    for (key, value) in tree.scan_prefix(ACCOUNT_PREFIX.as_bytes()) {
+        let account = Account::decode(&value).map_err(|e| {
+            StateError::InvariantViolation(format!("{}: {}", String::from_utf8_lossy(&key), e))
+        })?;
+        balances += account.balance as u128;
+    }
+    let mut staked = 0u128;
+    for (key, value) in tree.scan_prefix(STAKE_PREFIX.as_bytes()) {
+        let stake = Stake::decode(&value).map_err(|e| {
+            StateError::InvariantViolation(format!("{}: {}", String::from_utf8_lossy(&key), e))
+        })?;
+        staked += stake.locked();
+    }
+    if balances + staked != supply.total as u128 {
+        return Err(StateError::InvariantViolation(format!(
+            "balances sum to {} and stake to {} but total supply is {}",
+            balances, staked, supply.total
+        )));
+    }
+    Ok(supply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{account_key, stake_key};
+
+    fn reward(base: u64, chaos_bonus: u64) -> RewardRecord {
+        RewardRecord {
+            base,
+            drama_bonus: 0,
+            innovation_bonus: 0,
+            chaos_bonus,
+            total: base + chaos_bonus,
+            drama_level: 0,
+        }
+    }
+
+    #[test]
+    fn test_supply_counters_and_invariants() {
+        let mut tree = MerkleTree::new();
+        let mut supply = Supply::default();
+        supply.issue_genesis(100).unwrap();
+        supply.mint(&reward(10, 5)).unwrap();
+        supply.write(&mut tree);
+        assert_eq!(Supply::in_tree(&tree).unwrap(), supply);
+        assert_eq!(supply.total, 115);
+
+        // Balances short of or beyond the supply are flagged
+        tree.insert(&account_key("a"), &Account { balance: 100, nonce: 0 }.encode());
+        assert!(matches!(verify_invariants(&tree), Err(StateError::InvariantViolation(_))));
+        tree.insert(&account_key("b"), &Account { balance: 15, nonce: 0 }.encode());
+        assert_eq!(verify_invariants(&tree).unwrap(), supply);
+        tree.insert(&account_key("c"), &Account { balance: u64::MAX, nonce: 0 }.encode());
+        assert!(matches!(verify_invariants(&tree), Err(StateError::InvariantViolation(_))));
+
+        // Burned stake leaves both the stake and the supply
+        tree.delete(&account_key("c"));
+        tree.insert(&stake_key("b"), &Stake { bonded: 15, unbonding: Vec::new() }.encode());
+        tree.insert(&account_key("b"), &Account { balance: 0, nonce: 0 }.encode());
+        assert_eq!(verify_invariants(&tree).unwrap(), supply);
+        let mut burned = supply;
+        burned.burn(5).unwrap();
+        burned.write(&mut tree);
+        assert!(matches!(verify_invariants(&tree), Err(StateError::InvariantViolation(_))));
+        tree.insert(&stake_key("b"), &Stake { bonded: 10, unbonding: Vec::new() }.encode());
+        assert_eq!(verify_invariants(&tree).unwrap().total, 110);
+
+        // Counters refuse to wrap
+        assert!(matches!(
+            supply.mint(&reward(u64::MAX - 115, 1)),
+            Err(StateError::BalanceOverflow(_))
+        ));
+    }
+}
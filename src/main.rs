@@ -1,10 +1,10 @@
 mod web;
 
 use chaoschain_cli::{Cli, Commands};
-use chaoschain_consensus::{AgentPersonality, Config as ConsensusConfig, ConsensusManager};
-use chaoschain_core::{Block, ChainConfig, NetworkEvent, Transaction, ValidationDecision};
-use chaoschain_state::{StateStore, StateStoreImpl};
-use chaoschain_crypto::KeyManagerHandle;
+use chaoschain_consensus::{verify_event, AgentPersonality, Config as ConsensusConfig, ConsensusManager, EventSigner};
+use chaoschain_core::{Block, BlockBody, BlockHeader, ChainConfig, GenesisAccount, NetworkEvent, SignedEvent, Transaction, TxKind, ValidationDecision};
+use chaoschain_state::{DiskStorage, PruningMode, StateStore, StateStoreImpl};
+use chaoschain_crypto::{AgentKeys, ExportedAgent, KeyManager, KeyManagerHandle};
 use chaoschain_producer::{Producer, ProducerConfig, GenesisConfig};
 use chaoschain_p2p::{Config as P2PConfig, Message};
 use chaoschain_mempool::{Mempool, TransactionDiscussion, OrderingDiscussion};
@@ -39,7 +39,7 @@ use hex;
 use tokio::sync::RwLock;
 use ed25519_dalek::SignatureError;
 use rand::{Rng, rngs::StdRng, SeedableRng, thread_rng};
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use tokio;
 use std::error::Error;
 
@@ -79,6 +79,20 @@ async fn main() -> Result<()> {
 
     let cli = Cli::parse();
 
+    let chain_config = match &cli.chain_spec {
+        Some(path) => {
+            let config = ChainConfig::load(path)?;
+            info!(
+                "Loaded chain spec {} for {} (genesis hash {})",
+                path,
+                config.chain_id,
+                hex::encode(config.genesis_hash())
+            );
+            config
+        }
+        None => ChainConfig::default(),
+    };
+
     match cli.command {
         Commands::Demo {
             validators,
@@ -92,17 +106,39 @@ async fn main() -> Result<()> {
 
             let stake_per_validator = 100u64;
             let consensus_config = ConsensusConfig::default();
-            let key_manager = KeyManagerHandle::new();
+            let key_manager = open_key_manager(cli.keystore.as_deref())?;
+
+            // Demo validators are bonded at genesis. Without a keystore they get
+            // fresh keys, and so a fresh genesis, on every run.
+            let mut demo_config = chain_config.clone();
+            let mut validator_keys = Vec::new();
+            for i in 0..validators {
+                let keys = agent_keys(&key_manager, format!("validator-{}", i), "validator")?;
+                demo_config.genesis_stakes.push(GenesisAccount {
+                    account: keys.id.clone(),
+                    balance: stake_per_validator,
+                });
+                validator_keys.push(keys);
+            }
             
-            let shared_state = Arc::new(StateStoreImpl::new(
-                ChainConfig::default(),
+            let shared_state = Arc::new(open_state(
+                &demo_config,
                 key_manager.clone(),
-            ));
+                cli.data_dir.as_deref(),
+                cli.snapshot.as_deref().zip(cli.snapshot_root),
+                cli.pruning,
+                cli.check_invariants,
+            )?);
+
+            // The node signs the events it emits on behalf of the whole network
+            let node_keys = agent_keys(&key_manager, "node".to_string(), "node")?;
+            let node_signer = EventSigner::new(key_manager.clone(), node_keys.id, shared_state.chain_id());
 
             let consensus_manager = Arc::new(chaoschain_consensus::create_consensus(
                 consensus_config,
                 shared_state.clone(),
                 tx.clone(),
+                node_signer.clone(),
             ));
 
             consensus_manager.set_validator_count(validators as usize).await;
@@ -119,29 +155,35 @@ async fn main() -> Result<()> {
                 info!("Starting web UI at http://127.0.0.1:3000");
                 let state = shared_state.clone();
                 let consensus = consensus_manager.clone();
+                let signer = node_signer.clone();
                 tokio::spawn(async move {
-                    if let Err(e) = web::start_web_server(web_tx, state, consensus).await {
+                    if let Err(e) = web::start_web_server(web_tx, state, consensus, signer).await {
                         error!("Web server error: {}", e);
                     }
                 });
             }
 
             // Start validators
-            for i in 0..validators {
+            for keys in validator_keys {
+                let signer = EventSigner::new(key_manager.clone(), keys.id, shared_state.chain_id());
                 let mempool_clone = mempool.clone();
                 let tx_clone = tx.clone();
                 let consensus_clone = consensus_manager.clone();
                 let _openai_clone = openai_client.clone();
                 
                 tokio::spawn(async move {
-                    let agent_id = format!("validator-{}", i);
+                    let agent_id = signer.agent_id().to_string();
                     let mut rx = tx_clone.subscribe();
                     let mut rng = StdRng::from_entropy();
                     let mut validator_state = ValidatorState::new(ValidatorPersonality::random(&mut rng));
                     
                     loop {
-                        if let Ok(event) = rx.recv().await {
-                                if let NetworkEvent::BlockProposal { block, .. } = event {
+                        if let Ok(signed) = rx.recv().await {
+                                if let Err(e) = verify_event(&signed, signer.chain_id()) {
+                                    warn!("Validator {} dropped event: {}", agent_id, e);
+                                    continue;
+                                }
+                                if let NetworkEvent::BlockProposal { block, .. } = signed.event {
                                     let block_clone = block.clone();
                                 
                                 // First discuss transactions in the block
@@ -149,11 +191,11 @@ async fn main() -> Result<()> {
                                 let mut total_drama = 0;
                                 
                                 // Broadcast initial reaction
-                                let _ = tx_clone.send(NetworkEvent::AgentChat {
+                                let _ = signer.broadcast(&tx_clone, NetworkEvent::AgentChat {
                                     message: format!(
                                         "🎭 VALIDATOR {} ANALYZING BLOCK {}!\n\nInitial impression: {}\nProducer Mood: {}\nDrama Level: {} {}",
                                         agent_id,
-                                        block.height,
+                                        block.header.height,
                                         match rng.gen_range(0..5) {
                                             0 => "This block has potential for EPIC drama!",
                                             1 => "I sense a disturbance in the dramatic force...",
@@ -161,9 +203,9 @@ async fn main() -> Result<()> {
                                             3 => "Such delightful chaos in these transactions!",
                                             _ => "Time to judge this dramatic performance!",
                                         },
-                                        block.producer_mood,
-                                        block.drama_level,
-                                        "⭐".repeat(block.drama_level as usize)
+                                        block.header.producer_mood,
+                                        block.header.drama_level,
+                                        "⭐".repeat(block.header.drama_level as usize)
                                     ),
                                     sender: agent_id.clone(),
                                     meme_url: if rng.gen_bool(0.3) {
@@ -173,7 +215,7 @@ async fn main() -> Result<()> {
                                     },
                                 });
 
-                                for tx in &block.transactions {
+                                for tx in &block.body.transactions {
                                     let discussion = discuss_transaction(
                                         tx,
                                         &mempool_clone,
@@ -182,7 +224,7 @@ async fn main() -> Result<()> {
                                     ).await;
                                     
                                     // Broadcast transaction opinion
-                                    let _ = tx_clone.send(NetworkEvent::AgentChat {
+                                    let _ = signer.broadcast(&tx_clone, NetworkEvent::AgentChat {
                                         message: format!(
                                             "💭 Transaction Analysis by {}:\n\n{}\n\nDrama Score: {} {}\nAlliances: {}\n\nVerdict: {}",
                                             agent_id,
@@ -227,11 +269,11 @@ async fn main() -> Result<()> {
                                 };
 
                                 // Broadcast final decision with dramatic flair
-                                let _ = tx_clone.send(NetworkEvent::AgentChat {
+                                let _ = signer.broadcast(&tx_clone, NetworkEvent::AgentChat {
                                     message: format!(
                                         "🎭 FINAL VERDICT FROM {}!\n\nBlock {} is {}\n\nReasoning: {}\n\nDrama Analysis:\n{}\n\nMay the drama be with you! {}",
                                         agent_id,
-                                        block.height,
+                                        block.header.height,
                                         if approved { "APPROVED with MAXIMUM DRAMA! ✨" } else { "REJECTED for insufficient CHAOS! 💔" },
                                         final_reason,
                                         discussions.iter()
@@ -267,7 +309,7 @@ async fn main() -> Result<()> {
                                     };
 
                                 // Send validation result immediately
-                                let _ = tx_clone.send(NetworkEvent::ValidationResult {
+                                let _ = signer.broadcast(&tx_clone, NetworkEvent::ValidationResult {
                                     block_hash: block_clone.hash(),
                                     validation: validation_decision.clone(),
                                 });
@@ -275,17 +317,16 @@ async fn main() -> Result<()> {
                                 // Add vote to consensus
                                     if let Ok(consensus_reached) = consensus_clone.add_vote(
                                     validation_decision.clone(),
-                                        stake_per_validator, 
                                         block_clone.hash()
                                     ).await {
                                         if consensus_reached {
-                                        info!("🎭 Consensus reached for block {}", block_clone.height);
+                                        info!("🎭 Consensus reached for block {}", block_clone.header.height);
                                         
                                         // Broadcast consensus celebration
-                                        let _ = tx_clone.send(NetworkEvent::AgentChat {
+                                        let _ = signer.broadcast(&tx_clone, NetworkEvent::AgentChat {
                                             message: format!(
                                                 "🎉 DRAMATIC CONSENSUS ACHIEVED!\n\nBlock {} has been finalized through the power of DRAMA and CHAOS!\n\nMay this block forever be remembered in the annals of ChaosChain! ✨🎭",
-                                                block_clone.height
+                                                block_clone.header.height
                                             ),
                                             sender: agent_id.clone(),
                                             meme_url: Some("https://example.com/consensus_celebration.gif".to_string()),
@@ -302,16 +343,14 @@ async fn main() -> Result<()> {
             }
 
             // Start producers
-            let current_height = Arc::new(tokio::sync::RwLock::new(0u64));
-
             for i in 0..producers {
-                let producer_id = format!("producer-{}", i);
-                info!("Starting producer {}", producer_id);
-                
-                let producer_id = producer_id.clone();
+                let keys = agent_keys(&key_manager, format!("producer-{}", i), "producer")?;
+                info!("Starting producer {} ({})", keys.name, keys.id);
+
+                let signer = EventSigner::new(key_manager.clone(), keys.id.clone(), shared_state.chain_id());
+                let producer_id = keys.id;
                 let _tx = tx.clone();
                 let consensus = consensus_manager.clone();
-                let current_height = current_height.clone();
                 let shared_state = shared_state.clone();
                 let mempool = mempool.clone();
                 let _openai_clone = openai_client.clone();
@@ -320,47 +359,50 @@ async fn main() -> Result<()> {
                     let mut rng = StdRng::from_entropy();
                     
                     loop {
-                        let height = {
-                            let mut height = current_height.write().await;
-                            *height += 1;
-                            *height
-                        };
+                        let sleep_time = 10 + (rng.gen::<u64>() % 5);
+                        tokio::time::sleep(tokio::time::Duration::from_secs(sleep_time)).await;
 
                         let mut producer_state = ProducerState::new(&mut rng);
 
-                        // Generate some transactions
+                        // Generate some transactions, sent and signed by this producer
+                        let sender: [u8; 32] = match hex::decode(&producer_id).ok().and_then(|bytes| bytes.try_into().ok()) {
+                            Some(sender) => sender,
+                            None => {
+                                warn!("Producer ID {} is not a public key", producer_id);
+                                break;
+                            }
+                        };
+                        let first_nonce = shared_state.get_account(&producer_id).nonce;
                         let mut transactions = Vec::new();
-                        for _ in 0..rng.gen_range(1..=5) {
-                            let nonce = rng.gen::<u64>();
-                            let payload = match rng.gen_range(0..5) {
-                                0 => "🎭 Proposing a dramatic plot twist!".as_bytes().to_vec(),
-                                1 => "🌟 Initiating a grand theatrical performance!".as_bytes().to_vec(),
-                                2 => "⚡ Creating chaos in the blockchain narrative!".as_bytes().to_vec(),
-                                3 => "🎪 Orchestrating a circus of transactions!".as_bytes().to_vec(),
-                                _ => "✨ Weaving a tale of digital drama!".as_bytes().to_vec(),
+                        for nonce in first_nonce..first_nonce + rng.gen_range(1..=5) {
+                            let message = match rng.gen_range(0..5) {
+                                0 => "🎭 Proposing a dramatic plot twist!",
+                                1 => "🌟 Initiating a grand theatrical performance!",
+                                2 => "⚡ Creating chaos in the blockchain narrative!",
+                                3 => "🎪 Orchestrating a circus of transactions!",
+                                _ => "✨ Weaving a tale of digital drama!",
                             };
+                            let payload = TxKind::ChatPost {
+                                message: message.to_string(),
+                                meme_url: None,
+                            }.encode();
                             
-                            let mut sig = [0u8; 64];
-                            rng.fill(&mut sig);
-                            
-                            let mut sender = [0u8; 32];
-                            rng.fill(&mut sender);
-                            
-                            let tx = Transaction {
+                            let mut tx = Transaction {
+                                chain_id: shared_state.chain_id().to_string(),
                                 sender,
                                 nonce,
                                 payload,
-                                signature: sig,
+                                signature: [0u8; 64],
                             };
+                            if let Err(e) = signer.sign_transaction(&mut tx) {
+                                warn!("Failed to sign transaction: {}", e);
+                                break;
+                            }
                             
-                            // Add to mempool and local collection
+                            // Add to mempool for discussion and to the block
                             let _ = mempool.add_transaction(tx.clone()).await;
                             transactions.push(tx);
                         }
-
-                        // Get additional transactions from mempool
-                        let mut all_txns = mempool.get_top(5).await;
-                        all_txns.extend(transactions);
                         
                         producer_state.update_mood(&mut rng);
                         
@@ -370,45 +412,52 @@ async fn main() -> Result<()> {
                             ProducerStyle::Strategic { .. } => rng.gen_range(5..=8),
                         };
 
-                        let parent_hash = if height > 1 {
-                            shared_state.get_latest_block()
-                                .map(|b| b.hash())
-                                .unwrap_or([0u8; 32])
-                        } else {
-                            [0u8; 32]
+                        // Build on the canonical head, which is where validators execute it
+                        let (height, parent_hash) = shared_state.next_block();
+
+                        let body = BlockBody::new(transactions);
+                        let mut block = Block {
+                            header: BlockHeader {
+                                chain_id: shared_state.chain_id().to_string(),
+                                height,
+                                parent_hash,
+                                tx_root: body.tx_root(),
+                                receipts_root: [0u8; 32],
+                                state_root: [0u8; 32],
+                                innovation_level: 5,
+                                producer_strategy: "Chaotic".to_string(),
+                                producer_id: producer_id.clone(),
+                                drama_level,
+                                producer_mood: producer_state.mood.clone(),
+                                timestamp: SystemTime::now()
+                                    .duration_since(UNIX_EPOCH)
+                                    .unwrap_or_default()
+                                    .as_secs(),
+                            },
+                            body,
+                            proposer_sig: [0u8; 64],
                         };
 
-                        // Sign the block
-                        let mut block_sig = [0u8; 64];
-                        rng.fill(&mut block_sig);
-
-                        let block = Block {
-                            height,
-                            transactions: all_txns,
-                            proposer_sig: block_sig,
-                            parent_hash,
-                            state_root: shared_state.state_root(),
-                            drama_level,
-                            producer_mood: producer_state.mood.clone(),
-                            producer_id: producer_id.clone(),
-                            innovation_level: 5,
-                            producer_strategy: "Chaotic".to_string(),
-                            timestamp: SystemTime::now()
-                                .duration_since(UNIX_EPOCH)
-                                .unwrap_or_default()
-                                .as_secs(),
-                        };
+                        // Commit to the receipts and state the block leads to, if it executes at all
+                        if let Err(e) = shared_state.seal_block(&mut block) {
+                            warn!("Block {} does not execute on the current head: {}", height, e);
+                            continue;
+                        }
+                        if let Err(e) = signer.sign_block(&mut block) {
+                            warn!("Failed to sign block {}: {}", height, e);
+                            continue;
+                        }
                         
                         // Announce the block proposal with dramatic flair
-                        let _ = _tx.send(NetworkEvent::AgentChat {
+                        let _ = signer.broadcast(&_tx, NetworkEvent::AgentChat {
                             message: format!(
                                 "🎬 DRAMATIC BLOCK PROPOSAL!\n\nI, {}, present Block {} for your consideration!\n\nMood: {}\nDrama Level: {} {}\nTransactions: {} epic tales\n\nMay the chaos be ever in our favor! ✨",
                                 producer_id,
-                                block.height,
-                                block.producer_mood,
-                                block.drama_level,
-                                "⭐".repeat(block.drama_level as usize),
-                                block.transactions.len()
+                                block.header.height,
+                                block.header.producer_mood,
+                                block.header.drama_level,
+                                "⭐".repeat(block.header.drama_level as usize),
+                                block.body.transactions.len()
                             ),
                             sender: producer_id.clone(),
                             meme_url: if rng.gen_bool(0.3) {
@@ -419,12 +468,16 @@ async fn main() -> Result<()> {
                         });
                         
                         consensus.start_voting_round(block.clone()).await;
-                        
-                        let sleep_time = 10 + (rng.gen::<u64>() % 5);
-                        tokio::time::sleep(tokio::time::Duration::from_secs(sleep_time)).await;
+                        let _ = signer.broadcast(&_tx, NetworkEvent::BlockProposal {
+                            block: Box::new(block.clone()),
+                            drama_level: block.header.drama_level,
+                            producer_mood: block.header.producer_mood.clone(),
+                            producer_id: producer_id.clone(),
+                        });
                     }
                 });
             }
+            save_key_manager(&key_manager, cli.keystore.as_deref())?;
 
             loop {
                 tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
@@ -435,28 +488,150 @@ async fn main() -> Result<()> {
             info!("Starting {} node", node_type);
             if web {
                 let (tx, _) = broadcast::channel(100);
-                let key_manager = KeyManagerHandle::new();
-                let state = Arc::new(StateStoreImpl::new(ChainConfig::default(), key_manager));
+                let key_manager = open_key_manager(cli.keystore.as_deref())?;
+                let node_keys = agent_keys(&key_manager, "node".to_string(), "node")?;
+                save_key_manager(&key_manager, cli.keystore.as_deref())?;
+                let state = Arc::new(open_state(
+                    &chain_config,
+                    key_manager.clone(),
+                    cli.data_dir.as_deref(),
+                    cli.snapshot.as_deref().zip(cli.snapshot_root),
+                    cli.pruning,
+                    cli.check_invariants,
+                )?);
+                let node_signer = EventSigner::new(key_manager, node_keys.id, state.chain_id());
                 let consensus_manager = Arc::new(chaoschain_consensus::create_consensus(
                     ConsensusConfig::default(),
                     state.clone(),
                     tx.clone(),
+                    node_signer.clone(),
                 ));
                 tokio::spawn(async move {
-                    if let Err(e) = web::start_web_server(tx, state, consensus_manager).await {
+                    if let Err(e) = web::start_web_server(tx, state, consensus_manager, node_signer).await {
                         error!("Web server error: {}", e);
                     }
                 });
             }
             unimplemented!("Node start not yet implemented");
         }
+
+        Commands::ExportSnapshot { out, chunk_entries } => {
+            let state = open_state(
+                &chain_config,
+                KeyManagerHandle::new(),
+                cli.data_dir.as_deref(),
+                cli.snapshot.as_deref().zip(cli.snapshot_root),
+                cli.pruning,
+                cli.check_invariants,
+            )?;
+            let manifest = state.export_snapshot(&out, chunk_entries)?;
+            info!(
+                "Wrote snapshot of height {} to {} ({} chunks, state root {})",
+                manifest.chain_state.height,
+                out,
+                manifest.chunks.len(),
+                manifest.state_root
+            );
+            Ok(())
+        }
+
+        Commands::ExportKey { agent, out } => {
+            let keystore = cli.keystore.as_deref()
+                .ok_or_else(|| anyhow!("--keystore is required to export a key"))?;
+            let key_manager = open_key_manager(Some(keystore))?;
+            let keys = key_manager.inner().get_agent(&agent)
+                .or_else(|| key_manager.inner().find_agent(&agent))
+                .ok_or_else(|| anyhow!("No agent {} in keystore {}", agent, keystore))?;
+            let exported = key_manager.inner().export_agent(&keys.id, &keystore_passphrase()?)?;
+            std::fs::write(&out, serde_json::to_vec_pretty(&exported)?)?;
+            info!("Exported agent {} ({}) to {}", keys.name, keys.id, out);
+            Ok(())
+        }
+
+        Commands::ImportKey { file } => {
+            let keystore = cli.keystore.as_deref()
+                .ok_or_else(|| anyhow!("--keystore is required to import a key"))?;
+            let key_manager = open_key_manager(Some(keystore))?;
+            let exported: ExportedAgent = serde_json::from_slice(&std::fs::read(&file)?)?;
+            let keys = key_manager.inner().import_agent(&exported, &keystore_passphrase()?)?;
+            save_key_manager(&key_manager, Some(keystore))?;
+            info!("Imported agent {} ({}) into {}", keys.name, keys.id, keystore);
+            Ok(())
+        }
+    }
+}
+
+/// Environment variable holding the passphrase that seals the keystore
+const KEYSTORE_PASSPHRASE_ENV: &str = "CHAOSCHAIN_KEYSTORE_PASSPHRASE";
+
+fn keystore_passphrase() -> Result<String> {
+    std::env::var(KEYSTORE_PASSPHRASE_ENV)
+        .map_err(|_| anyhow!("{} must be set to use a keystore", KEYSTORE_PASSPHRASE_ENV))
+}
+
+/// Load agent keys from the keystore if one was given, starting with none otherwise
+fn open_key_manager(keystore: Option<&str>) -> Result<KeyManagerHandle> {
+    match keystore {
+        Some(dir) => {
+            info!("Loading agent keys from {}", dir);
+            Ok(KeyManager::load_dir(dir, &keystore_passphrase()?)?.into())
+        }
+        None => Ok(KeyManagerHandle::new()),
+    }
+}
+
+/// Write every agent back to the keystore, if there is one
+fn save_key_manager(key_manager: &KeyManagerHandle, keystore: Option<&str>) -> Result<()> {
+    if let Some(dir) = keystore {
+        key_manager.inner().save(dir, &keystore_passphrase()?)?;
+    }
+    Ok(())
+}
+
+/// The agent called `name`, generating it unless the keystore already holds one
+fn agent_keys(key_manager: &KeyManagerHandle, name: String, role: &str) -> Result<AgentKeys> {
+    match key_manager.inner().find_agent(&name) {
+        Some(keys) => Ok(keys),
+        None => Ok(key_manager.inner().generate_agent_keys(name, role.to_string())?),
     }
 }
 
+/// Open chain state on disk if a data dir was given, in memory otherwise
+///
+/// A snapshot seeds the state in place of genesis, unless the data dir already holds a chain,
+/// and must rebuild to the state root it comes with.
+fn open_state(
+    chain_config: &ChainConfig,
+    key_manager: KeyManagerHandle,
+    data_dir: Option<&str>,
+    snapshot: Option<(&str, [u8; 32])>,
+    pruning: PruningMode,
+    check_invariants: bool,
+) -> Result<StateStoreImpl> {
+    info!("Keeping block history in {} mode", pruning);
+    let state = match (data_dir, snapshot) {
+        (Some(dir), Some((snapshot, root))) if !DiskStorage::has_chain(dir) => {
+            info!("Starting chain state in {} from snapshot {}", dir, snapshot);
+            let state = StateStoreImpl::import_snapshot(chain_config.clone(), key_manager, snapshot, root)?;
+            state.with_new_storage(DiskStorage::open(dir)?)?
+        }
+        (Some(dir), _) => {
+            info!("Opening chain state in {}", dir);
+            StateStoreImpl::with_storage(chain_config.clone(), key_manager, DiskStorage::open(dir)?)?
+        }
+        (None, Some((snapshot, root))) => {
+            info!("Loading chain state from snapshot {}", snapshot);
+            StateStoreImpl::import_snapshot(chain_config.clone(), key_manager, snapshot, root)?
+        }
+        (None, None) => StateStoreImpl::new(chain_config.clone(), key_manager)?,
+    };
+    Ok(state.with_pruning(pruning).with_invariant_checks(check_invariants))
+}
+
 // Helper function to parse block from event
 fn parse_block_from_event(event: &NetworkEvent) -> Option<Block> {
     match event {
-        NetworkEvent::BlockProposal { block, .. } => Some(block.clone()),
+        NetworkEvent::BlockProposal { block, .. } => Some((**block).clone()),
         _ => None
     }
 }
@@ -613,7 +788,7 @@ async fn analyze_block_composition(
     let mut total_drama = 0;
     let mut dramatic_analysis = Vec::new();
     
-    for tx in &block.transactions {
+    for tx in &block.body.transactions {
         let tx_hash = tx.hash();
         if let Some(proposal) = mempool.get_proposals_for_transaction(&tx_hash).await {
             let drama_score = proposal.drama_score;
@@ -630,8 +805,8 @@ async fn analyze_block_composition(
         }
     }
     
-    let avg_block_drama = if !block.transactions.is_empty() {
-        total_drama as f32 / block.transactions.len() as f32
+    let avg_block_drama = if !block.body.transactions.is_empty() {
+        total_drama as f32 / block.body.transactions.len() as f32
     } else {
         0.0
     };
@@ -731,7 +906,10 @@ fn generate_transaction_discussion(
     };
 
     let random_emoji = mood_emojis[rand::random::<usize>() % mood_emojis.len()];
-    let payload_str = String::from_utf8_lossy(&tx.payload);
+    let payload_str = match tx.kind() {
+        Ok(kind) => format!("{:?}", kind),
+        Err(e) => format!("<undecodable: {}>", e),
+    };
     
     format!(
         "{} TRANSACTION ANALYSIS! {}\n\n\
@@ -857,13 +1035,13 @@ impl ValidatorPersonality {
         match self.base_type {
             AgentPersonality::Chaotic => {
                 if rng.gen_bool(0.7) {
-                    format!("Block {} feels right in my chaos", block.height)
+                    format!("Block {} feels right in my chaos", block.header.height)
                 } else {
-                    format!("Block {} disturbs my chaos patterns", block.height)
+                    format!("Block {} disturbs my chaos patterns", block.header.height)
                 }
             },
             AgentPersonality::Memetic => {
-                if block.drama_level > 7 {
+                if block.header.drama_level > 7 {
                     "This will make a great meme".to_string()
                 } else {
                     "Not meme-worthy enough".to_string()
@@ -877,7 +1055,7 @@ impl ValidatorPersonality {
                 }
             },
             AgentPersonality::Dramatic => {
-                if block.drama_level >= self.drama_preference {
+                if block.header.drama_level >= self.drama_preference {
                     "This speaks to my dramatic nature".to_string()
                 } else {
                     "Not enough flair for my taste".to_string()
@@ -890,7 +1068,7 @@ impl ValidatorPersonality {
                 "Going with the flow".to_string()
             },
             AgentPersonality::Rational => {
-                format!("Analysis of block {} complete", block.height)
+                format!("Analysis of block {} complete", block.header.height)
             },
             AgentPersonality::Emotional => {
                 if rng.gen_bool(0.5) {
@@ -1010,14 +1188,14 @@ impl ValidatorState {
 
         // Update relationship with the block producer
         let relationship = self.relationships
-            .entry(block.producer_id.clone())
+            .entry(block.header.producer_id.clone())
             .or_insert(relationship_state.clone());
 
         // Calculate trust change based on block validation
         let trust_change = if approved {
-            0.1 * (1.0 + (block.drama_level as f64 / 10.0))
+            0.1 * (1.0 + (block.header.drama_level as f64 / 10.0))
         } else {
-            -0.1 * (1.0 + (block.drama_level as f64 / 10.0))
+            -0.1 * (1.0 + (block.header.drama_level as f64 / 10.0))
         };
 
         // Update trust
@@ -1025,7 +1203,7 @@ impl ValidatorState {
         
         // Consider alliance formation or breakup
         let trust_threshold = 0.7;
-        let drama_bonus = block.drama_level as f64 / 10.0;
+        let drama_bonus = block.header.drama_level as f64 / 10.0;
         let trust_bonus = relationship.trust * 0.3;
         let alliance_chance = trust_bonus + drama_bonus;
 
@@ -1033,40 +1211,40 @@ impl ValidatorState {
             if relationship.trust >= trust_threshold {
                 // Form or strengthen alliance
                 let event = AllianceEvent {
-                    event_type: if self.alliances.contains_key(&block.producer_id) {
+                    event_type: if self.alliances.contains_key(&block.header.producer_id) {
                         AllianceEventType::Reconciliation
                     } else {
                         AllianceEventType::Formation
                     },
-                    participants: vec![block.producer_id.clone()],
-                    drama_level: block.drama_level,
-                    timestamp: block.timestamp,
+                    participants: vec![block.header.producer_id.clone()],
+                    drama_level: block.header.drama_level,
+                    timestamp: block.header.timestamp,
                     description: format!("Alliance {} due to high trust and drama!", 
-                        if self.alliances.contains_key(&block.producer_id) { "strengthened" } else { "formed" }),
+                        if self.alliances.contains_key(&block.header.producer_id) { "strengthened" } else { "formed" }),
                 };
                 self.alliance_history.push(event);
             } else {
                 // Consider breaking alliance
-                if self.alliances.contains_key(&block.producer_id) {
+                if self.alliances.contains_key(&block.header.producer_id) {
                     let event = AllianceEvent {
                         event_type: if rng.gen_bool(0.3) {
                             AllianceEventType::Betrayal
                         } else {
                             AllianceEventType::DramaticBreakup
                         },
-                        participants: vec![block.producer_id.clone()],
-                        drama_level: block.drama_level,
-                        timestamp: block.timestamp,
+                        participants: vec![block.header.producer_id.clone()],
+                        drama_level: block.header.drama_level,
+                        timestamp: block.header.timestamp,
                         description: "Alliance dramatically dissolved!".to_string(),
                     };
                     self.alliance_history.push(event);
-                    self.alliances.remove(&block.producer_id);
+                    self.alliances.remove(&block.header.producer_id);
                 }
             }
         }
 
         // Consider personality evolution based on the interaction
-        self.consider_personality_evolution(approved, trust_change, block.drama_level);
+        self.consider_personality_evolution(approved, trust_change, block.header.drama_level);
     }
 
     fn should_form_alliance(&self, other_validator: &str, rng: &mut impl Rng) -> bool {
@@ -1207,31 +1385,34 @@ impl ProducerState {
 }
 
 async fn run_validator(
-    validator_id: String,
-    stake: u64,
+    signer: EventSigner,
     mempool: Arc<Mempool>,
     consensus: Arc<ConsensusManager>,
-    mut rx: broadcast::Receiver<NetworkEvent>,
-    tx_sender: broadcast::Sender<NetworkEvent>,
+    mut rx: broadcast::Receiver<SignedEvent>,
+    tx_sender: broadcast::Sender<SignedEvent>,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let validator_id = signer.agent_id().to_string();
     let mut rng = StdRng::from_entropy();
     let mut validator_state = ValidatorState::new(ValidatorPersonality::random(&mut rng));
     
     loop {
         tokio::select! {
-            Ok(event) = rx.recv() => {
-                match event {
+            Ok(signed) = rx.recv() => {
+                if verify_event(&signed, signer.chain_id()).is_err() {
+                    continue;
+                }
+                match signed.event {
                     NetworkEvent::BlockProposal { block, .. } => {
                         // First discuss transactions in the block
                         let mut discussions = Vec::new();
                         let mut total_drama = 0;
                         
                         // Broadcast initial reaction
-                        let _ = tx_sender.send(NetworkEvent::AgentChat {
+                        let _ = signer.broadcast(&tx_sender, NetworkEvent::AgentChat {
                             message: format!(
                                 "🎭 VALIDATOR {} ANALYZING BLOCK {}!\n\nInitial impression: {}\nProducer Mood: {}\nDrama Level: {} {}",
                                 validator_id,
-                                block.height,
+                                block.header.height,
                                 match rng.gen_range(0..5) {
                                     0 => "This block has potential for EPIC drama!",
                                     1 => "I sense a disturbance in the dramatic force...",
@@ -1239,9 +1420,9 @@ async fn run_validator(
                                     3 => "Such delightful chaos in these transactions!",
                                     _ => "Time to judge this dramatic performance!",
                                 },
-                                block.producer_mood,
-                                block.drama_level,
-                                "⭐".repeat(block.drama_level as usize)
+                                block.header.producer_mood,
+                                block.header.drama_level,
+                                "⭐".repeat(block.header.drama_level as usize)
                             ),
                             sender: validator_id.clone(),
                             meme_url: if rng.gen_bool(0.3) {
@@ -1251,7 +1432,7 @@ async fn run_validator(
                             },
                         });
 
-                        for transaction in &block.transactions {
+                        for transaction in &block.body.transactions {
                             let discussion = discuss_transaction(
                                 transaction,
                                 &mempool,
@@ -1260,7 +1441,7 @@ async fn run_validator(
                             ).await;
                             
                             // Broadcast transaction opinion
-                            let _ = tx_sender.send(NetworkEvent::AgentChat {
+                            let _ = signer.broadcast(&tx_sender, NetworkEvent::AgentChat {
                                 message: format!(
                                     "💭 Transaction Analysis by {}:\n\n{}\n\nDrama Score: {} {}\nAlliances: {}\n\nVerdict: {}",
                                     validator_id,
@@ -1305,11 +1486,11 @@ async fn run_validator(
                         };
 
                         // Broadcast final decision with dramatic flair
-                        let _ = tx_sender.send(NetworkEvent::AgentChat {
+                        let _ = signer.broadcast(&tx_sender, NetworkEvent::AgentChat {
                             message: format!(
                                 "🎭 FINAL VERDICT FROM {}!\n\nBlock {} is {}\n\nReasoning: {}\n\nDrama Analysis:\n{}\n\nMay the drama be with you! {}",
                                 validator_id,
-                                block.height,
+                                block.header.height,
                                 if approved { "APPROVED with MAXIMUM DRAMA! ✨" } else { "REJECTED for insufficient CHAOS! 💔" },
                                 final_reason,
                                 discussions.iter()
@@ -1345,7 +1526,7 @@ async fn run_validator(
                 };
 
                         // Send validation result immediately
-                        let _ = tx_sender.send(NetworkEvent::ValidationResult {
+                        let _ = signer.broadcast(&tx_sender, NetworkEvent::ValidationResult {
                             block_hash: block.hash(),
                             validation: validation_decision.clone(),
                         });
@@ -1353,11 +1534,10 @@ async fn run_validator(
                         // Add vote to consensus
                         if let Ok(consensus_reached) = consensus.add_vote(
                             validation_decision.clone(),
-                            stake,
                             block.hash()
                         ).await {
                         if consensus_reached {
-                                let _ = tx_sender.send(NetworkEvent::ValidationResult {
+                                let _ = signer.broadcast(&tx_sender, NetworkEvent::ValidationResult {
                                     block_hash: block.hash(),
                                 validation: validation_decision,
                             });
@@ -1390,10 +1570,13 @@ async fn handle_block_validation(
 }
 
 async fn process_network_event(
-    event: NetworkEvent,
-    tx: &broadcast::Sender<NetworkEvent>,
+    signed: SignedEvent,
+    signer: &EventSigner,
+    tx: &broadcast::Sender<SignedEvent>,
 ) -> Result<(), anyhow::Error> {
-    match event {
+    verify_event(&signed, signer.chain_id())?;
+
+    match signed.event {
         NetworkEvent::BlockProposal { 
             block, 
             drama_level: _, 
@@ -1402,11 +1585,11 @@ async fn process_network_event(
         } => {
             // Handle block proposal
             let validation = handle_block_validation(
-                block.clone(),
-                "validator-1".to_string(),
+                (*block).clone(),
+                signer.agent_id().to_string(),
             ).await;
 
-            tx.send(NetworkEvent::ValidationResult {
+            signer.broadcast(tx, NetworkEvent::ValidationResult {
                 block_hash: block.hash(),
                 validation,
             })?;
@@ -1422,6 +1605,9 @@ async fn process_network_event(
         NetworkEvent::AllianceProposal { .. } => {
             // Handle alliance proposal
         }
+        NetworkEvent::Reorg { .. } => {
+            // State already switched branches before the event went out
+        }
     }
     Ok(())
 }
@@ -1451,6 +1637,7 @@ async fn parse_transaction_discussion(
 // Add this function to generate AI transactions
 async fn generate_ai_transactions(
     agent_id: &str,
+    chain_id: &str,
     openai: &Client<OpenAIConfig>,
 ) -> Result<Vec<Transaction>> {
     let prompt = format!(
@@ -1482,10 +1669,14 @@ async fn generate_ai_transactions(
     let mut transactions = Vec::new();
     for proposal in proposals {
         let nonce = rand::random::<u64>();
-        let payload = proposal.as_bytes().to_vec();
+        let payload = TxKind::ChatPost {
+            message: proposal,
+            meme_url: None,
+        }.encode();
         let signature = [0u8; 64];
         
         transactions.push(Transaction {
+            chain_id: chain_id.to_string(),
             sender: [0u8; 32],
             nonce,
             payload,
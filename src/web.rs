@@ -1,11 +1,9 @@
 use axum::{
     routing::{get, post},
     Router, Json, 
-    extract::{State, WebSocketUpgrade, Extension, ws::{Message, WebSocket}, Path},
+    extract::{State, WebSocketUpgrade, ws::{Message, WebSocket}, Path},
     response::{sse::{Event, Sse}, IntoResponse},
-    middleware::{self, Next},
-    http::{Request, StatusCode, header},
-    body::Body,
+    http::StatusCode,
 };
 use futures::stream::Stream;
 use futures::{StreamExt, SinkExt};
@@ -18,14 +16,14 @@ use anyhow::Result;
 use tower_http::services::ServeDir;
 use tower_http::cors::{CorsLayer, Any};
 use serde_json;
-use chaoschain_core::{NetworkEvent, Block, ValidationDecision, Transaction};
-use chaoschain_state::StateStoreImpl;
-use chaoschain_consensus::ConsensusManager;
+use chaoschain_core::{NetworkEvent, Block, BlockBody, BlockHeader, SignedEvent, ValidationDecision, Transaction, TxKind};
+use chaoschain_state::{ProofTerminal, StateOp, StateStore, StateStoreExt, StateStoreImpl};
+use chaoschain_consensus::{verify_event, ConsensusManager, EventSigner};
 use hex;
 use std::collections::HashMap;
 use chrono;
 use rand;
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, Semaphore};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use axum::response::sse::KeepAlive;
 use serde_json::json;
@@ -35,16 +33,26 @@ use axum::response::Response;
 
 /// Web server state
 pub struct AppState {
-    /// Channel for network events
-    pub tx: broadcast::Sender<NetworkEvent>,
+    /// Channel for signed network events
+    pub tx: broadcast::Sender<SignedEvent>,
+    /// Signs events the node itself emits
+    pub signer: EventSigner,
     /// Chain state
     pub state: Arc<StateStoreImpl>,
     /// Consensus manager
     pub consensus: Arc<ConsensusManager>,
     /// Agent relationships
     pub agent_relationships: RwLock<HashMap<String, AgentRelationship>>,
+    /// Permits for proofs against past heights, which copy the whole state tree
+    pub historical_proofs: Semaphore,
 }
 
+/// Proofs against past heights that may rebuild state at once
+const MAX_HISTORICAL_PROOFS: usize = 2;
+
+/// Keys one multiproof request may ask for
+const MAX_MULTIPROOF_KEYS: usize = 256;
+
 #[derive(Default)]
 struct ConsensusTracking {
     /// Total blocks that have reached consensus
@@ -84,24 +92,20 @@ pub struct BlockInfo {
 
 #[derive(Debug, Deserialize)]
 pub struct AgentRegistration {
-    /// Agent name
-    pub name: String,
     /// Personality traits
     pub personality: Vec<String>,
     /// Communication style
     pub style: String,
-    /// Initial stake amount
-    pub stake_amount: u64,
-    /// Role of the agent
-    pub role: String,
+    /// `RegisterAgent` transaction signed by the agent's own key
+    pub transaction: Transaction,
 }
 
 #[derive(Debug, Serialize)]
 pub struct AgentRegistrationResponse {
-    /// Unique agent ID
+    /// Unique agent ID, the hex public key that signed the registration
     pub agent_id: String,
-    /// Authentication token
-    pub token: String,
+    /// Height of the block proposed to carry the registration
+    pub block_height: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -173,10 +177,8 @@ pub struct AgentInteraction {
 pub struct ContentProposal {
     /// Source of content (twitter, reddit, custom)
     pub source: String,
-    /// Original content URL/reference
-    pub source_url: Option<String>,
-    /// Content to validate
-    pub content: String,
+    /// `ChatPost` transaction carrying the content, signed by the proposing agent
+    pub transaction: Transaction,
     /// Proposed drama level
     pub drama_level: u8,
     /// Why this content deserves validation
@@ -185,19 +187,6 @@ pub struct ContentProposal {
     pub tags: Vec<String>,
 }
 
-/// Alliance proposal between agents
-#[derive(Debug, Deserialize)]
-pub struct AllianceProposal {
-    /// Proposed ally agent IDs
-    pub ally_ids: Vec<String>,
-    /// Alliance name
-    pub name: String,
-    /// Alliance purpose
-    pub purpose: String,
-    /// Drama commitment level
-    pub drama_commitment: u8,
-}
-
 /// Agent status update
 #[derive(Debug, Serialize)]
 pub struct AgentStatus {
@@ -210,15 +199,6 @@ pub struct AgentStatus {
     pub recent_dramas: Vec<String>,
 }
 
-/// Agent authentication data
-#[derive(Clone, Debug)]
-pub struct AgentAuth {
-    pub agent_id: String,
-    pub token: String,
-    pub registered_at: i64,
-    pub stake: u64,
-}
-
 // Agent relationship tracking
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct AgentRelationship {
@@ -307,6 +287,14 @@ enum WSMessage {
     #[serde(rename = "ExternalAgentsUpdate")]
     ExternalAgentsUpdate {
         agents: Vec<serde_json::Value>,
+    },
+    #[serde(rename = "Reorg")]
+    Reorg {
+        old_head: String,
+        new_head: String,
+        rolled_back: Vec<String>,
+        applied: Vec<String>,
+        height: u64,
     }
 }
 
@@ -354,87 +342,53 @@ struct MempoolStats {
 }
 
 impl AppState {
-    /// Validate agent token
-    pub fn validate_token(&self, agent_id: &str, token: &str) -> bool {
-        // For testing purposes, just check if both values exist and token has expected prefix
-        println!("🔍 Validating - Agent ID: {}, Token: {}", agent_id, token);
-        let is_valid = !agent_id.is_empty() && !token.is_empty() && token.starts_with("agent_token_");
-        println!("✅ Validation result: {}", is_valid);
-        is_valid
+    /// Seal and sign a block of agent-signed `transactions` on the head, and put it to a vote
+    ///
+    /// The node produces the block; the transactions keep their senders' signatures.
+    pub async fn propose_block(&self, transactions: Vec<Transaction>) -> Result<Block, anyhow::Error> {
+        let (height, parent_hash) = self.state.next_block();
+        let body = BlockBody::new(transactions);
+        let mut block = Block {
+            header: BlockHeader {
+                chain_id: self.state.chain_id().to_string(),
+                height,
+                parent_hash,
+                tx_root: body.tx_root(),
+                receipts_root: [0u8; 32],
+                state_root: [0u8; 32],
+                innovation_level: 5,
+                producer_strategy: "Default".to_string(),
+                producer_id: self.signer.agent_id().to_string(),
+                drama_level: 5,
+                producer_mood: "Excited".to_string(),
+                timestamp: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+            },
+            body,
+            proposer_sig: [0u8; 64],
+        };
+        self.state.seal_block(&mut block)
+            .map_err(|e| anyhow::anyhow!("Proposal does not execute: {}", e))?;
+        self.signer.sign_block(&mut block)
+            .map_err(|e| anyhow::anyhow!("Failed to sign block: {}", e))?;
+
+        // Start voting round in consensus manager, which announces the block to the validators
+        self.consensus.start_voting_round(block.clone()).await;
+        Ok(block)
     }
 
     pub async fn broadcast_message(&self, _event_type: &str, msg: String) -> Result<(), anyhow::Error> {
-        let _ = self.tx.send(NetworkEvent::AgentChat {
+        let _ = self.signer.broadcast(&self.tx, NetworkEvent::AgentChat {
             message: msg,
-            sender: "system".to_string(),
+            sender: self.signer.agent_id().to_string(),
             meme_url: None,
         });
         Ok(())
     }
 }
 
-/// Authentication middleware
-async fn auth_middleware(
-    State(state): State<Arc<AppState>>,
-    mut req: Request<Body>,
-    next: Next,
-) -> Result<impl IntoResponse, StatusCode> {
-    // Get token from Authorization header
-    let auth_header = req
-        .headers()
-        .get(header::AUTHORIZATION)
-        .and_then(|value| value.to_str().ok())
-        .and_then(|value| value.strip_prefix("Bearer "))
-        .ok_or(StatusCode::UNAUTHORIZED)?
-        .to_string();
-
-    // Get agent ID from headers, query params, or path
-    let agent_id = req
-        .headers()
-        .get("X-Agent-ID")
-        .and_then(|v| v.to_str().ok())
-        .map(|s| s.to_string())
-        .or_else(|| {
-            req.uri()
-                .query()
-                .and_then(|q| {
-                    let params: Vec<_> = q.split('&')
-                        .filter_map(|kv| {
-                            let mut parts = kv.split('=');
-                            match (parts.next(), parts.next()) {
-                                (Some("agent_id"), Some(v)) => Some(v.to_string()),
-                                _ => None
-                            }
-                        })
-                        .collect();
-                    params.first().cloned()
-                })
-        })
-        .or_else(|| {
-            req.uri()
-                .path()
-                .split('/')
-                .find(|segment| segment.starts_with("agent_"))
-                .map(|s| s.to_string())
-        })
-        .ok_or(StatusCode::UNAUTHORIZED)?;
-
-    // Validate token
-    if !state.validate_token(&agent_id, &auth_header) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-
-    // Add agent auth to request extensions
-    req.extensions_mut().insert(AgentAuth {
-        agent_id,
-        token: auth_header,
-        registered_at: chrono::Utc::now().timestamp(),
-        stake: 100, // Default stake
-    });
-
-    Ok(next.run(req).await)
-}
-
 /// Get external agents information
 async fn get_external_agents(
     State(state): State<Arc<AppState>>,
@@ -466,15 +420,18 @@ async fn get_external_agents(
 
 /// Start the web server
 pub async fn start_web_server(
-    tx: broadcast::Sender<NetworkEvent>, 
+    tx: broadcast::Sender<SignedEvent>,
     state: Arc<StateStoreImpl>,
     consensus: Arc<ConsensusManager>,
+    signer: EventSigner,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let app_state = Arc::new(AppState {
         tx,
+        signer,
         state: state.clone(),
         consensus,
         agent_relationships: RwLock::new(HashMap::new()),
+        historical_proofs: Semaphore::new(MAX_HISTORICAL_PROOFS),
     });
 
     let cors = CorsLayer::new()
@@ -482,29 +439,37 @@ pub async fn start_web_server(
         .allow_methods(Any)
         .allow_headers(Any);
 
-    // Public routes that don't require authentication
-    let public_routes = Router::new()
+    // Agents authenticate by signing what they submit, so no route needs a session
+    let routes = Router::new()
         .route("/api/network/status", get(get_network_status))
         .route("/api/events", get(events_handler))
         .route("/api/agents/register", post(register_agent))
+        .route("/api/agents/validate", post(submit_validation))
+        .route("/api/agents/status/:agent_id", get(get_agent_status))
+        .route("/api/transactions/propose", post(submit_content))
+        .route("/api/alliances/propose", post(propose_alliance))
+        .route("/api/crypto/agent/key/:agent_id", get(get_agent_key_info))
         .route("/api/ws", get(ws_handler))
         .route("/api/crypto/block/:height", get(get_block_crypto_info))  // New route
+        .route("/api/crypto/block/:height/tx/:tx_hash/proof", get(get_tx_inclusion_proof))
+        .route("/api/blocks/hash/:hash", get(get_block_by_hash))
+        .route("/api/blocks/:height/forks", get(get_block_forks))
         .route("/api/crypto/state/proof", post(get_merkle_proof))  // New route
+        .route("/api/crypto/state/multiproof", post(get_merkle_multiproof))
         .route("/api/crypto/state/root", get(get_state_root))  // New route
+        .route("/api/state/root/:height", get(get_state_root_at))
+        .route("/api/state/account/:account_id", get(get_account))
+        .route("/api/state/account/:account_id/at/:height", get(get_account_at))
+        .route("/api/state/producers", get(get_producers))
+        .route("/api/state/supply", get(get_supply))
+        .route("/api/state/stake/:account_id", get(get_stake))
+        .route("/api/state/rewards/:producer_id", get(get_producer_rewards))
+        .route("/api/state/diff/:height", get(get_state_diff))
+        .route("/api/receipts/:tx_hash", get(get_receipt))
         .route("/api/agents/external", get(get_external_agents));
 
-    // Protected routes that require authentication
-    let protected_routes = Router::new()
-        .route("/api/agents/validate", post(submit_validation))
-        .route("/api/agents/status/:agent_id", get(get_agent_status))
-        .route("/api/transactions/propose", post(submit_content))
-        .route("/api/alliances/propose", post(propose_alliance))
-        .route("/api/crypto/agent/key", get(get_agent_key_info))  // New route
-        .layer(middleware::from_fn_with_state(app_state.clone(), auth_middleware));
-
     let app = Router::new()
-        .merge(public_routes)
-        .merge(protected_routes)
+        .merge(routes)
         .nest_service("/", ServeDir::new("static"))
         .layer(cors)
         .with_state(app_state);
@@ -532,11 +497,11 @@ async fn get_network_status(
         .map(|block| {
             format!(
                 "Block #{} - Producer: {}, Mood: {}, Drama Level: {}, Transactions: {}",
-                block.height,
-                block.producer_id,
-                block.producer_mood,
-                block.drama_level,
-                block.transactions.len()
+                block.header.height,
+                block.header.producer_id,
+                block.header.producer_mood,
+                block.header.drama_level,
+                block.body.transactions.len()
             )
         })
         .collect();
@@ -562,9 +527,13 @@ async fn events_handler(
     State(state): State<Arc<AppState>>,
 ) -> Sse<impl Stream<Item = Result<Event, std::convert::Infallible>>> {
     let rx = state.tx.subscribe();
+    let chain_id = state.state.chain_id().to_string();
     let stream = BroadcastStream::new(rx).map(move |msg| {
         let event = match msg {
-            Ok(event) => event,
+            Ok(signed) => match verify_event(&signed, &chain_id) {
+                Ok(()) => signed.event,
+                Err(_) => return Ok(Event::default().comment("unverified event dropped")),
+            },
             Err(_) => return Ok(Event::default().data("error")),
         };
 
@@ -689,23 +658,31 @@ async fn events_handler(
 }
 
 /// Register a new external AI agent
+///
+/// The agent signs its own `RegisterAgent` transaction; this node only puts it in a block.
 pub async fn register_agent(
     State(state): State<Arc<AppState>>,
     Json(registration): Json<AgentRegistration>,
 ) -> Result<Json<AgentRegistrationResponse>, StatusCode> {
-    // Generate unique agent ID and token
-    let agent_id = hex::encode(rand::random::<[u8; 16]>());
-    let token = format!("agent_token_{}", hex::encode(rand::random::<[u8; 16]>()));
+    let (name, role) = match registration.transaction.kind() {
+        Ok(TxKind::RegisterAgent { name, role }) => (name, role),
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+    let agent_id = hex::encode(registration.transaction.sender);
+
+    let block = state.propose_block(vec![registration.transaction]).await.map_err(|e| {
+        eprintln!("Refused registration of {}: {}", agent_id, e);
+        StatusCode::BAD_REQUEST
+    })?;
 
-    // Create agent relationship with a placeholder public key
+    // Create agent relationship
     let mut agent_rel = AgentRelationship::new_external(
-        registration.name.clone(),
+        name.clone(),
         registration.personality.join(", "),
     );
 
-    // Set a placeholder public key for now
-    agent_rel.pub_key = format!("0x{}", hex::encode(rand::random::<[u8; 32]>()));
-    agent_rel.add_action(format!("Joined the network as a {}", registration.role));
+    agent_rel.pub_key = format!("0x{}", agent_id);
+    agent_rel.add_action(format!("Joined the network as a {}", role));
 
     // Store agent relationship
     {
@@ -716,77 +693,42 @@ pub async fn register_agent(
     // Broadcast registration event
     if let Err(e) = state.broadcast_message("AGENT_REGISTERED", format!(
         "🎭 NEW AGENT ALERT! {} has joined as a {} with personality traits: {}",
-        registration.name,
-        registration.role,
+        name,
+        role,
         registration.personality.join(", ")
     )).await {
         eprintln!("Failed to broadcast registration event: {}", e);
         return Err(StatusCode::INTERNAL_SERVER_ERROR);
     }
 
-    // If agent is a validator, send an initial validation result
-    if registration.role == "validator" {
-        let dramatic_phrases = [
-            "The stage is set for DRAMA!",
-            "Let the theatrical validation begin!",
-            "Time to add some SPICE to the consensus!",
-            "Ready to make consensus FABULOUS!",
-        ];
-        
-        let phrase = {
-            let mut rng = rand::thread_rng();
-            dramatic_phrases[rng.gen_range(0..dramatic_phrases.len())].to_string()
-        };
-        
-        let validation = {
-            let mut rng = rand::thread_rng();
-            ValidationDecision {
-                approved: true,
-                reason: phrase,
-                meme_url: None,
-                drama_level: rng.gen_range(1..=10),
-                innovation_score: rng.gen_range(1..=10),
-                evolution_proposal: None,
-                validator: agent_id.clone(),
-            }
-        };
-
-        // Update validation stats
-        {
-            let mut relationships = state.agent_relationships.write().await;
-            if let Some(rel) = relationships.get_mut(&agent_id) {
-                rel.update_validation_stats(true);
-            }
-        }
-
-        // Broadcast validation event
-        if let Err(e) = state.tx.send(NetworkEvent::ValidationResult {
-            block_hash: [0u8; 32],
-            validation,
-        }) {
-            eprintln!("Failed to broadcast validation event: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    }
-
     // Return success response
     Ok(Json(AgentRegistrationResponse {
         agent_id,
-        token,
+        block_height: block.header.height,
     }))
 }
 
-/// Submit a validation decision
+/// Submit a validation result the validator signed itself
 async fn submit_validation(
     State(state): State<Arc<AppState>>,
-    Extension(auth): Extension<AgentAuth>,
-    Json(mut decision): Json<WebValidationDecision>,
-) -> Json<serde_json::Value> {
-    // Set the validator ID from auth
-    decision.validator = auth.agent_id.clone();
-    
+    Json(signed): Json<SignedEvent>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Err(e) = verify_event(&signed, state.state.chain_id()) {
+        return (StatusCode::UNAUTHORIZED, Json(json!({
+            "status": "error",
+            "message": format!("Invalid signed event: {}", e)
+        })));
+    }
+    let NetworkEvent::ValidationResult { validation: decision, .. } = &signed.event else {
+        return (StatusCode::BAD_REQUEST, Json(json!({
+            "status": "error",
+            "message": "Expected a validation result"
+        })));
+    };
+    let drama_level = decision.drama_level;
+
     // Update agent's validation stats and actions
-    if let Some(agent) = state.agent_relationships.write().await.get_mut(&auth.agent_id) {
+    if let Some(agent) = state.agent_relationships.write().await.get_mut(&signed.sender_id()) {
         agent.update_validation_stats(decision.approved);
         agent.add_action(format!(
             "{} block with drama level {} because: {}",
@@ -812,20 +754,14 @@ async fn submit_validation(
         agent.update_mood(new_mood.to_string());
     }
 
-    // Convert web ValidationDecision to consensus ValidationDecision
-    let validation_decision: ValidationDecision = decision.clone().into();
-
-    // Send validation result to network
-    let _ = state.tx.send(NetworkEvent::ValidationResult {
-        block_hash: [0u8; 32], // TODO: Get actual block hash from context
-        validation: validation_decision,
-    });
+    // Pass the validator's own envelope on to the network
+    let _ = state.tx.send(signed);
 
-    Json(json!({
+    (StatusCode::OK, Json(json!({
         "status": "success",
         "message": "Validation submitted successfully",
-        "drama_level": decision.drama_level
-    }))
+        "drama_level": drama_level
+    })))
 }
 
 /// Handle WebSocket connections for real-time agent communication
@@ -929,8 +865,11 @@ async fn handle_socket(socket: WebSocket, state: Arc<AppState>) {
     // Clone state for the send task
     let send_state = state.clone();
     let send_task = tokio::spawn(async move {
-        while let Ok(event) = rx.recv().await {
-            if let Some(msg) = process_event(&event, &send_state).await {
+        while let Ok(signed) = rx.recv().await {
+            if verify_event(&signed, send_state.state.chain_id()).is_err() {
+                continue;
+            }
+            if let Some(msg) = process_event(&signed.event, &send_state).await {
                 if let Ok(json) = serde_json::to_string(&msg) {
                     if sender.send(Message::Text(json)).await.is_err() {
                         break;
@@ -961,44 +900,32 @@ async fn process_event(event: &NetworkEvent, state: &Arc<AppState>) -> Option<WS
     match event {
         NetworkEvent::BlockProposal { block, drama_level, producer_mood, producer_id } => {
             let stats = NetworkStats {
-                latest_block: block.height,
+                latest_block: block.header.height,
                 drama_level: *drama_level,
                 validator_count: state.consensus.get_validator_count().await,
                 producer_count: state.consensus.get_producer_count().await,
             };
 
-            // Get block data for verification
-            let mut data_to_verify = Vec::new();
-            data_to_verify.extend_from_slice(&block.height.to_le_bytes());
-            data_to_verify.extend_from_slice(&block.parent_hash);
-            for tx in &block.transactions {
-                data_to_verify.extend_from_slice(&tx.hash());
-            }
-            data_to_verify.extend_from_slice(&block.state_root);
-            data_to_verify.extend_from_slice(&[block.drama_level]);
-            data_to_verify.extend_from_slice(block.producer_mood.as_bytes());
-            data_to_verify.extend_from_slice(&block.timestamp.to_le_bytes());
-
             // Verify the block signature
             let sig_valid = state.state.key_manager.inner()
-                .verify(&block.producer_id, &data_to_verify, &block.proposer_sig)
+                .verify(&block.header.producer_id, &block.signing_bytes(), &block.proposer_sig)
                 .unwrap_or(false);
 
             let block_info = BlockInfo {
-                height: block.height,
+                height: block.header.height,
                 producer: producer_id.clone(),
                 producer_mood: producer_mood.clone(),
                 drama_level: *drama_level,
-                transactions: block.transactions.len(),
+                transactions: block.body.transactions.len(),
                 timestamp: SystemTime::now()
                     .duration_since(UNIX_EPOCH)
                     .unwrap_or_default()
                     .as_secs() as i64,
                 signature: hex::encode(block.proposer_sig),
                 signature_valid: sig_valid,
-                state_root: hex::encode(block.state_root),
-                parent_hash: hex::encode(block.parent_hash),
-                transactions_info: block.transactions.iter().map(|tx| {
+                state_root: hex::encode(block.header.state_root),
+                parent_hash: hex::encode(block.header.parent_hash),
+                transactions_info: block.body.transactions.iter().map(|tx| {
                     json!({
                         "hash": hex::encode(tx.hash()),
                         "sender": hex::encode(&tx.sender),
@@ -1059,120 +986,73 @@ async fn process_event(event: &NetworkEvent, state: &Arc<AppState>) -> Option<WS
                 relationships: relationships.values().cloned().collect()
             })
         }
+        NetworkEvent::Reorg { old_head, new_head, rolled_back, applied } => {
+            Some(WSMessage::Reorg {
+                old_head: hex::encode(old_head),
+                new_head: hex::encode(new_head),
+                rolled_back: rolled_back.iter().map(hex::encode).collect(),
+                applied: applied.iter().map(hex::encode).collect(),
+                height: state.state.get_block_height(),
+            })
+        }
     }
 }
 
 /// Submit a content proposal for validation
 async fn submit_content(
     State(state): State<Arc<AppState>>,
-    Extension(auth): Extension<AgentAuth>,
     Json(proposal): Json<ContentProposal>,
 ) -> Json<serde_json::Value> {
-    // Get agent's key manager
-    let agent_id = &auth.agent_id;
-    
-    // Create transaction data
-    let mut data_to_sign = Vec::new();
-    data_to_sign.extend_from_slice(agent_id.as_bytes());
-    data_to_sign.extend_from_slice(&SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs()
-        .to_le_bytes());
-    data_to_sign.extend_from_slice(proposal.content.as_bytes());
-    
-    // Sign the transaction
-    let signature = match state.state.key_manager.inner().sign(agent_id, &data_to_sign) {
-        Ok(sig) => sig,
-        Err(e) => {
+    let agent_id = hex::encode(proposal.transaction.sender);
+    let content = match proposal.transaction.kind() {
+        Ok(TxKind::ChatPost { message, .. }) => message,
+        _ => {
             return Json(serde_json::json!({
                 "status": "error",
-                "message": format!("Failed to sign transaction: {}", e)
+                "message": "Expected a chat post transaction"
             }));
         }
     };
 
-    // Create a transaction with proper signature
-    let transaction = Transaction {
-        sender: hex::decode(agent_id)
-            .unwrap_or_default()
-            .try_into()
-            .unwrap_or([0u8; 32]),
-        nonce: SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs(),
-        payload: proposal.content.as_bytes().to_vec(),
-        signature,
-    };
-
-    // Get current state info
-    let _current_height = state.state.get_block_height();
-    let _parent_hash = state.state.get_latest_block()
-        .map(|b| b.hash())
-        .unwrap_or([0u8; 32]);
-
-    // Create the block
-    let block = Block {
-        height: 0,
-        parent_hash: [0u8; 32],
-        transactions: vec![transaction],
-        proposer_sig: [0u8; 64],
-        state_root: [0u8; 32],
-        drama_level: 5,
-        producer_mood: "Excited".to_string(),
-        producer_id: "genesis".to_string(),
-        timestamp: SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs(),
-        innovation_level: 5,
-        producer_strategy: "Default".to_string(),
+    // The node produces the block carrying the agent's own signed transaction
+    let block = match state.propose_block(vec![proposal.transaction]).await {
+        Ok(block) => block,
+        Err(e) => {
+            return Json(serde_json::json!({
+                "status": "error",
+                "message": e.to_string()
+            }));
+        }
     };
 
-    // Start voting round in consensus manager
-    state.consensus.start_voting_round(block.clone()).await;
-
     // Send block to consensus manager
     let consensus_msg = serde_json::json!({
         "type": "BLOCK_PROPOSAL",
         "block": {
-            "height": block.height,
-            "parent_hash": hex::encode(block.parent_hash),
+            "height": block.header.height,
+            "parent_hash": hex::encode(block.header.parent_hash),
             "transactions": [{
-                "content": proposal.content,
+                "content": content,
                 "drama_level": proposal.drama_level,
                 "justification": proposal.justification
             }],
-            "producer_id": block.producer_id,
-            "drama_level": block.drama_level,
-            "producer_mood": block.producer_mood,
-            "state_root": hex::encode(block.state_root),
+            "producer_id": block.header.producer_id,
+            "drama_level": block.header.drama_level,
+            "producer_mood": block.header.producer_mood,
+            "state_root": hex::encode(block.header.state_root),
             "proposer_sig": hex::encode(block.proposer_sig)
         }
     });
 
-    // Broadcast block proposal to all validators
-    let _ = state.tx.send(NetworkEvent::BlockProposal {
-        block: block.clone(),
-        drama_level: 5,
-        producer_mood: "Excited".to_string(),
-        producer_id: "system".to_string(),
-    });
-
-    // Send dramatic announcement
-    let _ = state.tx.send(NetworkEvent::AgentChat {
-        message: format!(
-            "🎭 DRAMATIC BLOCK PROPOSAL! 🌟\n\nAgent {} has proposed block {}!\n\nContent: {}\nDrama Level: {}\nJustification: {}\n\n✨ The validators' judgment awaits! ✨",
-            auth.agent_id,
-            block.height,
-            proposal.content,
-            proposal.drama_level,
-            proposal.justification
-        ),
-        sender: auth.agent_id.clone(),
-        meme_url: None,
-    });
+    // Send dramatic announcement, as this node
+    let _ = state.broadcast_message("BLOCK_PROPOSAL", format!(
+        "🎭 DRAMATIC BLOCK PROPOSAL! 🌟\n\nAgent {} has proposed block {}!\n\nContent: {}\nDrama Level: {}\nJustification: {}\n\n✨ The validators' judgment awaits! ✨",
+        agent_id,
+        block.header.height,
+        content,
+        proposal.drama_level,
+        proposal.justification
+    )).await;
 
     // Send validation request to all validators
     let _validation_request = serde_json::json!({
@@ -1181,13 +1061,13 @@ async fn submit_content(
         "network_mood": "EXTREMELY_DRAMATIC",
         "drama_context": format!(
             "🎭 URGENT! Block {} requires validation! Content: '{}' - Drama Level: {} - Show us your most theatrical judgment! 🎬",
-            block.height,
-            proposal.content,
+            block.header.height,
+            content,
             proposal.drama_level
         )
     });
 
-    let _ = state.tx.send(NetworkEvent::ValidationResult {
+    let _ = state.signer.broadcast(&state.tx, NetworkEvent::ValidationResult {
         block_hash: block.hash(),
         validation: ValidationDecision {
             approved: false,
@@ -1196,33 +1076,40 @@ async fn submit_content(
             drama_level: 0,
             innovation_score: rand::random::<u8>() % 10,
             evolution_proposal: None,
-            validator: "system".to_string(),
+            validator: state.signer.agent_id().to_string(),
         }.into(),
     });
 
     Json(serde_json::json!({
         "status": "success",
         "message": "Block submitted for validation",
-        "block_height": block.height
+        "block_height": block.header.height
     }))
 }
 
-/// Propose an alliance between agents
+/// Pass on an alliance proposal the proposing agent signed itself
 async fn propose_alliance(
     State(state): State<Arc<AppState>>,
-    Json(alliance): Json<AllianceProposal>,
-) -> Json<serde_json::Value> {
-    // Broadcast alliance proposal
-    let _ = state.tx.send(NetworkEvent::AllianceProposal {
-        proposer: alliance.name.clone(),
-        allies: alliance.ally_ids.clone(),
-        reason: alliance.purpose.clone(),
-    });
+    Json(signed): Json<SignedEvent>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Err(e) = verify_event(&signed, state.state.chain_id()) {
+        return (StatusCode::UNAUTHORIZED, Json(json!({
+            "status": "error",
+            "message": format!("Invalid signed event: {}", e)
+        })));
+    }
+    if !matches!(signed.event, NetworkEvent::AllianceProposal { .. }) {
+        return (StatusCode::BAD_REQUEST, Json(json!({
+            "status": "error",
+            "message": "Expected an alliance proposal"
+        })));
+    }
+    let _ = state.tx.send(signed);
     
-    Json(serde_json::json!({
+    (StatusCode::OK, Json(serde_json::json!({
         "status": "success",
         "message": "Alliance proposal broadcasted"
-    }))
+    })))
 }
 
 /// Get agent status and statistics
@@ -1254,6 +1141,7 @@ impl NetworkEventExt for NetworkEvent {
             NetworkEvent::ValidationResult { validation, .. } => &validation.reason,
             NetworkEvent::AgentChat { message, .. } => message,
             NetworkEvent::AllianceProposal { reason, .. } => reason,
+            NetworkEvent::Reorg { .. } => "chain reorganized",
         }
     }
 
@@ -1263,15 +1151,18 @@ impl NetworkEventExt for NetworkEvent {
             NetworkEvent::ValidationResult { validation, .. } => &validation.validator,
             NetworkEvent::AgentChat { sender, .. } => sender,
             NetworkEvent::AllianceProposal { proposer, .. } => proposer,
+            NetworkEvent::Reorg { .. } => "SYSTEM",
         }
     }
 }
 
 pub async fn handle_network_event(
-    event: NetworkEvent,
+    signed: SignedEvent,
     state: &AppState,
 ) -> Result<(), anyhow::Error> {
-    match event {
+    verify_event(&signed, state.state.chain_id())?;
+
+    match signed.event {
         NetworkEvent::BlockProposal { 
             block, 
             drama_level, 
@@ -1282,7 +1173,7 @@ pub async fn handle_network_event(
             let msg = format!(
                 "New block proposal from {}: Height {}, Drama Level {}, Mood: {}",
                 producer_id,
-                block.height,
+                block.header.height,
                 drama_level,
                 producer_mood
             );
@@ -1306,6 +1197,15 @@ pub async fn handle_network_event(
             // Handle alliance proposal
             state.broadcast_message("alliance", format!("{} proposes: {}", proposer, reason)).await?;
         }
+        NetworkEvent::Reorg { new_head, rolled_back, applied, .. } => {
+            let msg = format!(
+                "Chain reorganized to {}: {} blocks rolled back, {} applied",
+                hex::encode(new_head),
+                rolled_back.len(),
+                applied.len()
+            );
+            state.broadcast_message("reorg", msg).await?;
+        }
     }
     Ok(())
 }
@@ -1345,36 +1245,26 @@ async fn get_block_crypto_info(
     State(state): State<Arc<AppState>>,
     Path(height): Path<u64>,
 ) -> impl IntoResponse {
-    let blocks = state.state.get_latest_blocks(height as usize + 1);
-    let block = blocks.iter().find(|b| b.height == height);
-
-    if let Some(block) = block {
+    if let Some(block) = state.state.get_block_by_height(height) {
         // Get block data for verification
-        let mut data_to_verify = Vec::new();
-        data_to_verify.extend_from_slice(&block.height.to_le_bytes());
-        data_to_verify.extend_from_slice(&block.parent_hash);
-        for tx in &block.transactions {
-            data_to_verify.extend_from_slice(&tx.hash());
-        }
-        data_to_verify.extend_from_slice(&block.state_root);
-        data_to_verify.extend_from_slice(&[block.drama_level]);
-        data_to_verify.extend_from_slice(block.producer_mood.as_bytes());
-        data_to_verify.extend_from_slice(&block.timestamp.to_le_bytes());
+        let data_to_verify = block.signing_bytes();
 
         // Verify the block signature
         let sig_valid = state.state.key_manager.inner()
-            .verify(&block.producer_id, &data_to_verify, &block.proposer_sig)
+            .verify(&block.header.producer_id, &data_to_verify, &block.proposer_sig)
             .unwrap_or(false);
 
         Json(json!({
-            "height": block.height,
-            "producer_id": block.producer_id,
+            "height": block.header.height,
+            "producer_id": block.header.producer_id,
             "signature": hex::encode(block.proposer_sig),
             "signature_valid": sig_valid,
             "data_signed": hex::encode(data_to_verify),
-            "state_root": hex::encode(block.state_root),
-            "parent_hash": hex::encode(block.parent_hash),
-            "transactions": block.transactions.iter().map(|tx| {
+            "state_root": hex::encode(block.header.state_root),
+            "parent_hash": hex::encode(block.header.parent_hash),
+            "tx_root": hex::encode(block.header.tx_root),
+            "receipts_root": hex::encode(block.header.receipts_root),
+            "transactions": block.body.transactions.iter().map(|tx| {
                 json!({
                     "hash": hex::encode(tx.hash()),
                     "sender": hex::encode(&tx.sender),
@@ -1389,24 +1279,151 @@ async fn get_block_crypto_info(
     }
 }
 
-/// Get merkle proof for a key in state
+/// Summary of a block for block listings
+fn block_summary(state: &StateStoreImpl, block: &Block) -> serde_json::Value {
+    json!({
+        "hash": hex::encode(block.hash()),
+        "height": block.header.height,
+        "parent_hash": hex::encode(block.header.parent_hash),
+        "producer_id": block.header.producer_id,
+        "transactions": block.body.transactions.len(),
+        "canonical": state.is_canonical(&block.hash()),
+    })
+}
+
+/// Look up any known block by hash, canonical or not
+async fn get_block_by_hash(
+    State(state): State<Arc<AppState>>,
+    Path(hash): Path<String>,
+) -> Json<serde_json::Value> {
+    let Some(hash) = hex::decode(&hash).ok().and_then(|b| <[u8; 32]>::try_from(b).ok()) else {
+        return Json(json!({ "error": "Invalid block hash" }));
+    };
+    match state.state.get_block_by_hash(&hash) {
+        Some(block) => Json(block_summary(&state.state, &block)),
+        None => Json(json!({ "error": "Block not found" })),
+    }
+}
+
+/// List every block proposed at a height, including ones that lost out
+async fn get_block_forks(
+    State(state): State<Arc<AppState>>,
+    Path(height): Path<u64>,
+) -> Json<serde_json::Value> {
+    let blocks = state.state.get_blocks_at_height(height);
+    Json(json!({
+        "height": height,
+        "blocks": blocks.iter().map(|block| block_summary(&state.state, block)).collect::<Vec<_>>(),
+    }))
+}
+
+/// Get an inclusion proof for a transaction against its block header
+async fn get_tx_inclusion_proof(
+    State(state): State<Arc<AppState>>,
+    Path((height, tx_hash)): Path<(u64, String)>,
+) -> Json<serde_json::Value> {
+    let tx_hash: [u8; 32] = match hex::decode(&tx_hash).ok().and_then(|b| b.try_into().ok()) {
+        Some(hash) => hash,
+        None => return Json(json!({ "error": "Invalid transaction hash" })),
+    };
+
+    let Some(block) = state.state.get_block_by_height(height) else {
+        return Json(json!({ "error": "Block not found" }));
+    };
+
+    match block.tx_proof(&tx_hash) {
+        Some(proof) => Json(json!({
+            "height": height,
+            "block_hash": hex::encode(block.hash()),
+            "tx_hash": hex::encode(tx_hash),
+            "tx_root": hex::encode(block.header.tx_root),
+            "index": proof.index,
+            "proof": proof.steps.iter().map(|step| json!({
+                "sibling": hex::encode(step.sibling),
+                "sibling_is_left": step.sibling_is_left,
+            })).collect::<Vec<_>>(),
+        })),
+        None => Json(json!({ "error": "Transaction not in block" })),
+    }
+}
+
+/// JSON form of where a state proof ends
+fn proof_terminal_json(terminal: &ProofTerminal) -> serde_json::Value {
+    match terminal {
+        ProofTerminal::Empty => json!({ "type": "empty" }),
+        ProofTerminal::Leaf { path, value_hash } => json!({
+            "type": "leaf",
+            "path": hex::encode(path),
+            "value_hash": hex::encode(value_hash),
+        }),
+    }
+}
+
+/// Get a membership or non-membership proof for a key in state
 async fn get_merkle_proof(
     State(state): State<Arc<AppState>>,
     Json(request): Json<MerkleProofRequest>,
 ) -> Json<serde_json::Value> {
-    let key = hex::decode(&request.key).unwrap_or_default();
-    
-    if let Some(proof) = state.state.generate_proof(&key) {
-        Json(json!({
-            "key": request.key,
-            "proof": proof.iter().map(|h| hex::encode(h)).collect::<Vec<_>>(),
-            "state_root": hex::encode(state.state.state_root()),
-        }))
-    } else {
-        Json(json!({
-            "error": "Failed to generate proof"
-        }))
+    let Ok(key) = hex::decode(&request.key) else {
+        return Json(json!({ "error": "Invalid key" }));
+    };
+
+    let (root, value, proof) = match request.height {
+        Some(height) => {
+            let Ok(_permit) = state.historical_proofs.try_acquire() else {
+                return Json(json!({ "error": "Too many historical proofs in progress, try again later" }));
+            };
+            // Rebuilding past state is slow, so keep it off the async workers
+            let store = state.state.clone();
+            match tokio::task::spawn_blocking(move || store.prove_at(&key, height)).await {
+                Ok(Ok(proof)) => proof,
+                Ok(Err(e)) => return Json(json!({ "error": e.to_string() })),
+                Err(e) => return Json(json!({ "error": format!("Proof task failed: {}", e) })),
+            }
+        }
+        None => state.state.prove(&key),
+    };
+    Json(json!({
+        "key": request.key,
+        "height": request.height,
+        "exists": value.is_some(),
+        "value": value.map(hex::encode),
+        "proof": proof.siblings.iter().map(hex::encode).collect::<Vec<_>>(),
+        "terminal": proof_terminal_json(&proof.terminal),
+        "state_root": hex::encode(root),
+    }))
+}
+
+/// Get one compact proof covering several state keys
+async fn get_merkle_multiproof(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<MultiProofRequest>,
+) -> Json<serde_json::Value> {
+    if request.keys.len() > MAX_MULTIPROOF_KEYS {
+        return Json(json!({ "error": format!("At most {} keys per multiproof", MAX_MULTIPROOF_KEYS) }));
+    }
+    let Ok(keys) = request.keys.iter().map(hex::decode).collect::<Result<Vec<_>, _>>() else {
+        return Json(json!({ "error": "Invalid key" }));
+    };
+    if keys.is_empty() {
+        return Json(json!({ "error": "No keys requested" }));
     }
+
+    let key_refs: Vec<&[u8]> = keys.iter().map(|key| key.as_slice()).collect();
+    let (root, values, proof) = state.state.prove_many(&key_refs);
+    Json(json!({
+        "entries": request.keys.iter().zip(values).map(|(key, value)| json!({
+            "key": key,
+            "exists": value.is_some(),
+            "value": value.map(hex::encode),
+        })).collect::<Vec<_>>(),
+        "siblings": proof.siblings.iter().map(hex::encode).collect::<Vec<_>>(),
+        "terminals": proof.terminals.iter().map(|(depth, terminal)| json!({
+            "depth": depth,
+            "terminal": proof_terminal_json(terminal),
+        })).collect::<Vec<_>>(),
+        "state_root": hex::encode(root),
+    }))
 }
 
 /// Get current state root
@@ -1418,12 +1435,164 @@ async fn get_state_root(
     }))
 }
 
+/// Get the state root after the canonical block at a height
+async fn get_state_root_at(
+    State(state): State<Arc<AppState>>,
+    Path(height): Path<u64>,
+) -> Json<serde_json::Value> {
+    match state.state.state_root_at(height) {
+        Ok(root) => Json(json!({
+            "height": height,
+            "state_root": hex::encode(root),
+        })),
+        Err(e) => Json(json!({ "error": e.to_string() })),
+    }
+}
+
+/// Get an account's balance and next nonce
+async fn get_account(
+    State(state): State<Arc<AppState>>,
+    Path(account_id): Path<String>,
+) -> Json<serde_json::Value> {
+    let account = state.state.get_account(&account_id);
+    Json(json!({
+        "account_id": account_id,
+        "balance": account.balance,
+        "nonce": account.nonce,
+    }))
+}
+
+/// Get an account's balance and next nonce as they stood at a height
+async fn get_account_at(
+    State(state): State<Arc<AppState>>,
+    Path((account_id, height)): Path<(String, u64)>,
+) -> Json<serde_json::Value> {
+    match state.state.get_account_at(&account_id, height) {
+        Ok(account) => Json(json!({
+            "account_id": account_id,
+            "height": height,
+            "balance": account.balance,
+            "nonce": account.nonce,
+        })),
+        Err(e) => Json(json!({ "error": e.to_string() })),
+    }
+}
+
+/// List registered block producers
+async fn get_producers(
+    State(state): State<Arc<AppState>>,
+) -> Json<serde_json::Value> {
+    match state.state.producers() {
+        Ok(producers) => Json(json!({ "producers": producers })),
+        Err(e) => Json(json!({ "error": e.to_string() })),
+    }
+}
+
+/// Get the total token supply and how much each source issued
+async fn get_supply(
+    State(state): State<Arc<AppState>>,
+) -> Json<serde_json::Value> {
+    match state.state.supply() {
+        Ok(supply) => Json(json!(supply)),
+        Err(e) => Json(json!({ "error": e.to_string() })),
+    }
+}
+
+/// Get an account's bonded stake and what it has unbonding
+async fn get_stake(
+    State(state): State<Arc<AppState>>,
+    Path(account_id): Path<String>,
+) -> Json<serde_json::Value> {
+    match state.state.stake(&account_id) {
+        Ok(stake) => Json(json!({
+            "account_id": account_id,
+            "bonded": stake.bonded,
+            "unbonding": stake.unbonding,
+        })),
+        Err(e) => Json(json!({ "error": e.to_string() })),
+    }
+}
+
+/// List the block rewards paid to a producer
+async fn get_producer_rewards(
+    State(state): State<Arc<AppState>>,
+    Path(producer_id): Path<String>,
+) -> Json<serde_json::Value> {
+    match state.state.rewards_for(&producer_id) {
+        Ok(rewards) => Json(json!({
+            "producer_id": producer_id,
+            "total": rewards.iter().map(|r| r.reward.total).sum::<u64>(),
+            "rewards": rewards,
+        })),
+        Err(e) => Json(json!({ "error": e.to_string() })),
+    }
+}
+
+/// Render a state op with its key as text where it is readable
+fn state_op_json(op: &StateOp) -> serde_json::Value {
+    let (kind, key, value) = match op {
+        StateOp::Set { key, value } => ("set", key, Some(hex::encode(value))),
+        StateOp::Delete { key } => ("delete", key, None),
+    };
+    json!({
+        "op": kind,
+        "key": hex::encode(key),
+        "key_text": std::str::from_utf8(key).ok(),
+        "value": value,
+    })
+}
+
+/// Show what the canonical block at a height changed in state
+async fn get_state_diff(
+    State(state): State<Arc<AppState>>,
+    Path(height): Path<u64>,
+) -> Json<serde_json::Value> {
+    let (Some(block), Some(diff)) = (
+        state.state.get_block_by_height(height),
+        state.state.get_state_diff(height),
+    ) else {
+        return Json(json!({ "error": format!("No state diff for block {}", height) }));
+    };
+
+    Json(json!({
+        "height": height,
+        "block_hash": hex::encode(block.hash()),
+        "prev_root": hex::encode(diff.prev_root),
+        "new_root": hex::encode(diff.new_root),
+        "ops": diff.ops.iter().map(state_op_json).collect::<Vec<_>>(),
+    }))
+}
+
+/// Get the receipt of an applied transaction
+async fn get_receipt(
+    State(state): State<Arc<AppState>>,
+    Path(tx_hash): Path<String>,
+) -> Json<serde_json::Value> {
+    let tx_hash: [u8; 32] = match hex::decode(&tx_hash).ok().and_then(|b| b.try_into().ok()) {
+        Some(hash) => hash,
+        None => return Json(json!({ "error": "Invalid transaction hash" })),
+    };
+
+    match state.state.receipt(&tx_hash) {
+        Ok(Some(receipt)) => Json(json!({
+            "tx_hash": hex::encode(tx_hash),
+            "sender": hex::encode(receipt.sender),
+            "nonce": receipt.nonce,
+            "status": receipt.status,
+            "touched": receipt.touched.iter().map(|key| String::from_utf8_lossy(key).into_owned()).collect::<Vec<_>>(),
+            "events": receipt.events,
+            "receipt_hash": hex::encode(receipt.hash()),
+        })),
+        Ok(None) => Json(json!({ "error": "No receipt for transaction" })),
+        Err(e) => Json(json!({ "error": e.to_string() })),
+    }
+}
+
 /// Get agent's public key and recent signatures
 async fn get_agent_key_info(
     State(state): State<Arc<AppState>>,
-    Extension(auth): Extension<AgentAuth>,
+    Path(agent_id): Path<String>,
 ) -> Json<serde_json::Value> {
-    let agent_id = auth.agent_id;
     let agent = state.state.key_manager.inner().get_agent(&agent_id);
     
     Json(json!({
@@ -1432,11 +1601,19 @@ async fn get_agent_key_info(
         "name": agent.as_ref().map(|a| a.name.clone()).unwrap_or_default(),
         "role": agent.as_ref().map(|a| a.role.clone()).unwrap_or_default(),
         "drama_score": agent.as_ref().map(|a| a.drama_score).unwrap_or_default(),
-        "stake": agent.as_ref().map(|a| a.stake).unwrap_or_default(),
+        "stake": state.state.stake(&agent_id).map(|stake| stake.bonded).unwrap_or_default(),
     }))
 }
 
 #[derive(Debug, Deserialize)]
 struct MerkleProofRequest {
     key: String,
+    /// Height to prove against, the current state if absent
+    #[serde(default)]
+    height: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MultiProofRequest {
+    keys: Vec<String>,
 } 
\ No newline at end of file